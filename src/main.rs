@@ -1,28 +1,415 @@
-mod util;
-
+use ::config::{ConfigError, FileFormat};
 use clap::Parser;
-use log::error;
-use util::{config::Config, logging, processing};
+use clippy::util::config::{self, Config, FadeDuration, SettingsOverrides};
+use clippy::util::processing::{self, ProgressOutput};
+use clippy::util::{check, error::ProcessingError, logging, probe, template, watch};
+use log::{error, info, LevelFilter};
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::process;
+
+// Exit codes, so scripts can tell a broken config apart from a failed encode
+const EXIT_FAILURE: i32 = 1;
+const EXIT_CONFIG_ERROR: i32 = 2;
+const EXIT_PROBE_ERROR: i32 = 3;
+const EXIT_FFMPEG_ERROR: i32 = 4;
+const EXIT_FFMPEG_NOT_FOUND: i32 = 5;
+// What shells report for a process ended by SIGINT
+const EXIT_INTERRUPTED: i32 = 130;
 
 #[derive(Parser)]
 struct Cli {
+    /// Config file to load, can be given multiple times with later files overriding earlier ones
+    #[arg(short, long, required_unless_present = "init")]
+    config: Vec<String>,
+
+    /// Write an example config listing every setting with its type, default and what it does to PATH, then exit
+    #[arg(long, value_name = "PATH", alias = "generate-config", conflicts_with = "config")]
+    init: Option<String>,
+
+    /// Let --init overwrite a file that already exists
+    #[arg(long, requires = "init")]
+    force: bool,
+
+    /// Format of the config files: toml, json or yaml. By default it's taken from each file's extension, falling back to toml
+    #[arg(long, value_name = "FORMAT", value_parser = ["toml", "json", "yaml"])]
+    format: Option<String>,
+
+    /// Watch this directory and process every new video dropped into it, re-reading the config when it changes
     #[arg(short, long)]
-    config: String,
+    watch: Option<String>,
+
+    /// Only encode the first SECS seconds of the clip with a fast preset, written next to the output with a _preview suffix
+    #[arg(long, value_name = "SECS")]
+    preview: Option<f64>,
+
+    /// Check the config, ffmpeg, the inputs and the output directories, print a report and exit without processing
+    #[arg(long)]
+    check: bool,
+
+    /// Probe the input and print its duration, resolution, framerate, codecs, bitrates and audio details, then exit
+    #[arg(long)]
+    info: bool,
+
+    /// Print --info as JSON instead of a table
+    #[arg(long, requires = "info")]
+    json: bool,
+
+    /// Write only the processed audio, with its fades, volumes and background mix, the same as audio_only = true. The
+    /// output's extension picks the audio codec unless audio_codec is set, e.g. .mp3, .m4a, .opus, .flac or .wav
+    #[arg(long)]
+    extract_audio: bool,
+
+    /// Print the ffmpeg commands that would run instead of running them
+    #[arg(short = 'n', long)]
+    dry_run: bool,
+
+    /// Read this video instead of the config's input_video_path
+    #[arg(short, long, value_name = "PATH")]
+    input: Option<String>,
+
+    /// Write the result here instead of the config's output_video_path. With --input alone it's written next to the
+    /// input with _faded added to the name
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<String>,
+
+    /// Overrides the config's video_bitrate
+    #[arg(long, value_name = "BITRATE")]
+    video_bitrate: Option<String>,
+
+    /// Overrides the config's fade_in_duration, in seconds or as a percentage like 10%
+    #[arg(long, value_name = "DURATION")]
+    fade_in: Option<FadeDuration>,
+
+    /// Overrides the config's fade_out_duration, in seconds or as a percentage like 10%
+    #[arg(long, value_name = "DURATION")]
+    fade_out: Option<FadeDuration>,
+
+    /// Overrides the config's video_speed
+    #[arg(long, value_name = "FACTOR")]
+    video_speed: Option<f64>,
+
+    /// Overrides the config's target_fps, converts the output to FPS frames per second by dropping or repeating frames
+    /// without changing the playback speed. It wins over the framerate a video_speed change would keep
+    #[arg(long, value_name = "FPS")]
+    force_framerate: Option<f64>,
+
+    /// Overrides the config's use_gpu, `--use-gpu` alone means true
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    use_gpu: Option<bool>,
+
+    /// Encode with libx265 instead of failing when use_gpu is set but ffmpeg has no hevc_nvenc
+    #[arg(long)]
+    gpu_fallback: bool,
+
+    /// Overrides the config's skip_existing: keep outputs that already exist (always), only those written after their
+    /// input was last changed (newer), only those encoded with the same settings (settings), or re-encode them
+    /// (never). `--skip-existing` alone means always
+    #[arg(long, value_name = "WHEN", value_parser = ["always", "newer", "settings", "never"], num_args = 0..=1, default_missing_value = "always")]
+    skip_existing: Option<String>,
+
+    /// Re-encode and overwrite outputs that already exist, the same as --skip-existing never
+    #[arg(long, conflicts_with = "skip_existing")]
+    overwrite: bool,
+
+    /// Let ffmpeg write straight to the output instead of a temporary file next to it that replaces the output once
+    /// the encode succeeded, the same as atomic_output = false
+    #[arg(long)]
+    no_atomic: bool,
+
+    /// Log every ffmpeg command before it runs, for the records (unlike --dry-run the encode still happens)
+    #[arg(long)]
+    print_command: bool,
+
+    /// Log how fast each encode ran when it's done: frames per second and how many times realtime, the same as
+    /// benchmark = true
+    #[arg(long)]
+    benchmark: bool,
+
+    /// Write the filter graphs clippy builds to PATH before running them, a chain per line, the same as
+    /// dump_filtergraph. Works with --dry-run too
+    #[arg(long, value_name = "PATH")]
+    dump_filtergraph: Option<String>,
+
+    /// Passed to ffmpeg as it is right before the output, after the config's extra_ffmpeg_args. Repeat it for every
+    /// argument, e.g. --ffmpeg-arg -metadata --ffmpeg-arg title=Holiday
+    #[arg(long, value_name = "ARG", allow_hyphen_values = true)]
+    ffmpeg_arg: Vec<String>,
+
+    /// Resolve the config's relative paths against DIR (relative to the working directory) instead of the config file's
+    /// directory or its base_dir. Mostly for --config -, which has no directory of its own
+    #[arg(long, value_name = "DIR")]
+    base_dir: Option<String>,
+
+    /// Overrides the config's timeout_secs, kills an ffmpeg run that takes longer than this
+    #[arg(long, value_name = "SECS")]
+    timeout: Option<f64>,
+
+    /// Also log the progress every SECS seconds, for runs whose progress bar nobody is watching. Overrides the
+    /// config's log_progress_interval
+    #[arg(long, value_name = "SECS")]
+    log_progress_interval: Option<f64>,
+
+    /// Overrides the config's max_parallel_jobs, how many files of a batch are encoded at the same time
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<usize>,
+
+    /// Log more detail, -v for debug output and -vv for everything
+    #[arg(short, long, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Log less, -q for only warnings and errors and -qq for only errors
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    quiet: u8,
+
+    /// Also append the log to this file, always with timestamps
+    #[arg(long, value_name = "PATH")]
+    log_file: Option<String>,
+
+    /// How to show progress: a bar on the terminal, or json for one JSON object per line on stdout
+    #[arg(long, value_name = "MODE", value_parser = ["bar", "json"], default_value = "bar")]
+    progress: String,
+
+    /// Overrides the config's progress_by, whether progress is counted in seconds or frames of the output
+    #[arg(long, value_name = "UNIT", value_parser = ["time", "frames"])]
+    progress_by: Option<String>,
+
+    /// Write a JSON report of the run to PATH: the input and output, status, encoder, output length and size and how
+    /// long it took. A batch writes a list with one entry per file
+    #[arg(long, value_name = "PATH", conflicts_with = "watch")]
+    report: Option<String>,
+
+    /// Leave the timestamps out of the log output
+    #[arg(long)]
+    no_timestamps: bool,
+
+    /// Color the log output: auto (only on a terminal and when NO_COLOR isn't set), always or never
+    #[arg(long, value_name = "WHEN", value_parser = ["auto", "always", "never"])]
+    color: Option<String>,
 }
 
-fn main() {
-    logging::setup_logging();
+impl Cli {
+    fn log_level(&self) -> LevelFilter {
+        match (self.verbose, self.quiet) {
+            (0, 0) => LevelFilter::Info,
+            (1, _) => LevelFilter::Debug,
+            (_, 0) => LevelFilter::Trace,
+            (_, 1) => LevelFilter::Warn,
+            _ => LevelFilter::Error,
+        }
+    }
+
+    fn overrides(&self) -> SettingsOverrides {
+        SettingsOverrides {
+            input_video_path: self.input.clone(),
+            // A one-off --input gets its own output next to it rather than overwriting the config's, an m4a for
+            // --extract-audio
+            output_video_path: self.output.clone().or_else(|| {
+                let input = self.input.as_ref().filter(|input| !config::is_stream(input))?;
+                let output = config::suffixed_path(input, "_faded");
+                if self.extract_audio {
+                    return Some(Path::new(&output).with_extension("m4a").to_string_lossy().into_owned());
+                }
+                Some(output)
+            }),
+            video_bitrate: self.video_bitrate.clone(),
+            fade_in_duration: self.fade_in,
+            fade_out_duration: self.fade_out,
+            video_speed: self.video_speed,
+            target_fps: self.force_framerate,
+            use_gpu: self.use_gpu,
+            max_parallel_jobs: self.jobs,
+            gpu_fallback: self.gpu_fallback.then_some(true),
+            print_command: self.print_command.then_some(true),
+            timeout_secs: self.timeout,
+            skip_existing: if self.overwrite { Some("never".to_string()) } else { self.skip_existing.clone() },
+            extra_ffmpeg_args: self.ffmpeg_arg.clone(),
+            atomic_output: self.no_atomic.then_some(false),
+            progress_by: self.progress_by.clone(),
+            audio_only: self.extract_audio.then_some(true),
+            benchmark: self.benchmark.then_some(true),
+            dump_filtergraph: self.dump_filtergraph.clone(),
+            log_progress_interval: self.log_progress_interval,
+            base_dir: self.base_dir.clone(),
+        }
+    }
+}
+
+// Ctrl-C (and a plain kill) only flags the interrupt, the processing notices it and stops ffmpeg and cleans up
+// itself. A second Ctrl-C gets the default handling back and ends clippy right away
+extern "C" fn on_interrupt(signal: libc::c_int) {
+    processing::interrupt();
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+    }
+}
+
+fn install_interrupt_handler() {
+    let handler = on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+fn exit_if_interrupted(message: &str) {
+    if processing::interrupted() {
+        error!("{}", message);
+        process::exit(EXIT_INTERRUPTED);
+    }
+}
 
+fn main() {
     let args = Cli::parse();
+    install_interrupt_handler();
+    let overrides = args.overrides();
 
-    match Config::from_file(&args.config) {
-        Ok(config) => {
-            if let Err(e) = processing::add_fade_effects(&config) {
-                error!("Oops! Something went wrong: {}", e);
-            }
+    // `--config -` reads the config from stdin, which can only be read once, so the text is kept for both loads below
+    let stdin_config = (args.config == ["-"]).then(|| {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text).map(|_| text)
+    });
+    let load_config = || match stdin_config {
+        Some(Ok(ref text)) => {
+            let format = match args.format {
+                Some(ref name) => config::file_format(name)
+                    .ok_or_else(|| ConfigError::Message(format!("Unknown config format: {}", name)))?,
+                None => FileFormat::Toml,
+            };
+            Config::from_reader(text.as_bytes(), format, overrides.base_dir.as_deref())
+        }
+        Some(Err(ref e)) => Err(ConfigError::Message(format!("Couldn't read the config from stdin: {}", e))),
+        None if args.config.iter().any(|path| path == "-") => {
+            Err(ConfigError::Message("--config - can't be combined with other config files".to_string()))
+        }
+        None => Config::from_files_with_format(&args.config, args.format.as_deref(), overrides.base_dir.as_deref()),
+    };
+
+    // The logger has to exist before the config errors can be reported, so the config is only peeked at for the
+    // log settings here and loaded properly below
+    let log_settings = load_config().ok().map(|config| config.settings);
+    let timestamps = !args.no_timestamps && log_settings.as_ref().and_then(|s| s.log_timestamps).unwrap_or(true);
+    let color = args
+        .color
+        .clone()
+        .or_else(|| log_settings.and_then(|s| s.log_color))
+        .unwrap_or_else(|| "auto".to_string());
+    if let Err(e) = logging::setup_logging(args.log_level(), timestamps, &color, args.log_file.as_deref()) {
+        logging::setup_logging(args.log_level(), timestamps, &color, None).ok();
+        error!("Failed to open the log file {}: {}", args.log_file.as_deref().unwrap_or_default(), e);
+        process::exit(EXIT_FAILURE);
+    }
+
+    if let Some(ref path) = args.init {
+        if Path::new(path).exists() && !args.force {
+            error!("{} already exists, add --force to overwrite it", path);
+            process::exit(EXIT_FAILURE);
+        }
+        let written = template::config_template().and_then(|text| fs::write(path, text).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            error!("Couldn't write the config template to {}: {}", path, e);
+            process::exit(EXIT_FAILURE);
+        }
+        info!("Wrote an example config to {}", path);
+        return;
+    }
+
+    if let Some(ref watch_dir) = args.watch {
+        if stdin_config.is_some() {
+            error!("--watch re-reads the config whenever it changes, so it needs a config file instead of --config -");
+            process::exit(EXIT_CONFIG_ERROR);
         }
+        if let Err(e) = watch::watch_folder(&args.config, args.format.as_deref(), &overrides, watch_dir) {
+            error!("Watch mode stopped: {}", e);
+            process::exit(EXIT_FAILURE);
+        }
+        exit_if_interrupted("Interrupted, stopped watching");
+        return;
+    }
+
+    let mut config = match load_config() {
+        Ok(config) => config,
         Err(e) => {
             error!("Failed to read config file: {}", e);
+            process::exit(EXIT_CONFIG_ERROR);
+        }
+    };
+    config.apply_overrides(&overrides);
+    if let Some(preview) = args.preview {
+        config.settings.preview_duration = Some(preview);
+    }
+    let progress_output = if args.progress == "json" { ProgressOutput::Json } else { ProgressOutput::Bar };
+    if args.check {
+        let checks = check::check(&config);
+        for check in &checks {
+            if check.passed() {
+                println!("PASS  {}", check.name);
+            } else {
+                println!("FAIL  {}", check.name);
+                for problem in &check.problems {
+                    println!("        {}", problem);
+                }
+            }
+        }
+        if !checks.iter().all(check::Check::passed) {
+            process::exit(EXIT_CONFIG_ERROR);
+        }
+        return;
+    }
+    if args.info {
+        match probe::probe(&config) {
+            Ok(info) if args.json => println!("{}", serde_json::to_string_pretty(&info).unwrap_or_default()),
+            Ok(info) => print!("{}", info.table()),
+            Err(e @ (ProcessingError::FfmpegNotFound(_) | ProcessingError::FfmpegSpawn { .. })) => {
+                error!("{}", e);
+                process::exit(EXIT_FFMPEG_NOT_FOUND);
+            }
+            Err(e) => {
+                error!("Couldn't probe {}: {}", config.settings.input_video_path, e);
+                process::exit(EXIT_PROBE_ERROR);
+            }
         }
+        return;
+    }
+    if !config.batch.is_empty() {
+        let failures = processing::process_batch(&config, args.dry_run, progress_output, args.report.as_deref());
+        exit_if_interrupted("Interrupted, the files that weren't finished were left out");
+        if failures > 0 {
+            process::exit(EXIT_FAILURE);
+        }
+        return;
+    }
+    if let Err(e) = processing::add_fade_effects_reporting(&config, args.dry_run, progress_output, args.report.as_deref()) {
+        let code = match e {
+            ProcessingError::ConfigInvalid(_) | ProcessingError::InvalidClipTime(_) => {
+                error!("Invalid config: {}", e);
+                EXIT_CONFIG_ERROR
+            }
+            ProcessingError::ProbeParse(_) => {
+                error!("Couldn't read the input: {}", e);
+                EXIT_PROBE_ERROR
+            }
+            ProcessingError::FfmpegNotFound(_) | ProcessingError::FfmpegSpawn { .. } => {
+                error!("{}", e);
+                EXIT_FFMPEG_NOT_FOUND
+            }
+            ProcessingError::FfmpegExit { .. }
+            | ProcessingError::GpuEncodeFailed(_)
+            | ProcessingError::FfmpegTimeout(_)
+            | ProcessingError::OutputInvalid(_) => {
+                error!("Oops! Something went wrong: {}", e);
+                EXIT_FFMPEG_ERROR
+            }
+            ProcessingError::Interrupted => {
+                error!("{}", e);
+                EXIT_INTERRUPTED
+            }
+            ProcessingError::Io { .. } => {
+                error!("Oops! Something went wrong: {}", e);
+                EXIT_FAILURE
+            }
+        };
+        process::exit(code);
     }
 }
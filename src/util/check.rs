@@ -0,0 +1,120 @@
+use std::fs::{self, File};
+use std::path::Path;
+use std::process::{self, Command};
+
+use crate::util::config::{self, Config, Settings};
+use crate::util::error::ProcessingError;
+
+/// One line of the `--check` report, passed when it found no problems.
+pub struct Check {
+    pub name: &'static str,
+    pub problems: Vec<String>,
+}
+
+impl Check {
+    pub fn passed(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Checks everything a run would need without running it: the settings, that ffmpeg can be started, that the
+/// inputs can be read and that the output directories can be written to. Every file of a batch is checked.
+pub fn check(config: &Config) -> Vec<Check> {
+    let jobs: Vec<Config> = if config.batch.is_empty() {
+        vec![config.clone()]
+    } else {
+        config
+            .batch
+            .iter()
+            .map(|job| {
+                let mut job_config = config.clone();
+                job_config.settings.input_video_path = job.input.clone();
+                job_config.settings.output_video_path = job.output.clone();
+                job_config
+            })
+            .collect()
+    };
+
+    let mut settings = Vec::new();
+    let mut inputs = Vec::new();
+    let mut outputs = Vec::new();
+    for job in &jobs {
+        for problem in job.problems() {
+            if !settings.contains(&problem) {
+                settings.push(problem);
+            }
+        }
+        inputs.extend(check_readable(&job.settings.input_video_path));
+        outputs.extend(check_output_dir(&job.settings.output_video_path));
+    }
+    for rendition in &config.outputs {
+        outputs.extend(check_output_dir(&rendition.path));
+    }
+
+    let optional_inputs = [
+        &config.settings.intro_path,
+        &config.settings.outro_path,
+        &config.settings.second_input_video_path,
+        &config.settings.subtitle_path,
+        &config.settings.watermark_path,
+        &config.settings.chapters_file,
+    ];
+    for path in optional_inputs.into_iter().flatten().filter(|path| path.to_lowercase() != "none") {
+        inputs.extend(check_readable(path));
+    }
+    for path in &config.settings.concat_inputs {
+        inputs.extend(check_readable(path));
+    }
+    for path in config.settings.background_audio_files() {
+        inputs.extend(check_readable(path));
+    }
+
+    vec![
+        Check { name: "settings", problems: settings },
+        Check { name: "ffmpeg", problems: check_ffmpeg(&config.settings).into_iter().collect() },
+        Check { name: "inputs", problems: inputs },
+        Check { name: "output directories", problems: outputs },
+    ]
+}
+
+fn check_ffmpeg(settings: &Settings) -> Option<String> {
+    if let Err(e) = settings.ffmpeg_executable() {
+        return Some(e.to_string());
+    }
+    let ffmpeg_path = &settings.ffmpeg_path;
+    match Command::new(ffmpeg_path).arg("-version").output() {
+        Ok(output) if output.status.success() => None,
+        Ok(output) => Some(format!("ffmpeg at {} exited with {} when asked for its version", ffmpeg_path, output.status)),
+        Err(source) => Some(ProcessingError::FfmpegSpawn { path: ffmpeg_path.to_string(), source }.to_string()),
+    }
+}
+
+// Streams and URLs are only opened by ffmpeg, there's nothing to check for them up front
+fn check_readable(path: &str) -> Option<String> {
+    if path.is_empty() || config::is_stream(path) {
+        return None;
+    }
+    File::open(path).err().map(|e| format!("Can't read {}: {}", path, e))
+}
+
+// Creating and removing a file is the only reliable way to tell, permissions alone miss read-only mounts
+fn check_output_dir(output_video_path: &str) -> Option<String> {
+    if output_video_path.is_empty() || output_video_path == "-" {
+        return None;
+    }
+    let dir = match Path::new(output_video_path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if !dir.is_dir() {
+        return Some(format!("The output directory {} doesn't exist", dir.display()));
+    }
+    let probe = dir.join(format!(".clippy-check-{}", process::id()));
+    match File::create(&probe) {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            None
+        }
+        Err(e) => Some(format!("Can't write to the output directory {}: {}", dir.display(), e)),
+    }
+}
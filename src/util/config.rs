@@ -1,53 +1,3203 @@
 use config::{Config as ConfigLoader, File, FileFormat};
-use serde::Deserialize;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::env;
+use std::ffi::OsStr;
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-#[derive(Debug, Deserialize)]
+use crate::util::error::ProcessingError;
+
+// Size abbreviations ffmpeg's scale filter understands on its own
+const RESOLUTION_PRESETS: &[&str] = &[
+    "ntsc", "pal", "qntsc", "qpal", "sntsc", "spal", "film", "ntsc-film", "vga", "svga", "xga", "hd480", "hd720",
+    "hd1080", "2k", "2kflat", "2kscope", "4k", "4kflat", "4kscope", "uhd2160", "uhd4320",
+];
+
+// Containers (by extension or ffmpeg format name) that can't hold the HEVC video and AAC audio clippy encodes,
+// with the container to suggest instead
+const INCOMPATIBLE_CONTAINERS: &[(&str, &str)] = &[
+    ("webm", "mkv"),
+    ("ogg", "mkv"),
+    ("ogv", "mkv"),
+    ("flv", "mp4"),
+    ("gif", "mp4"),
+    ("mp3", "mp4"),
+    ("wav", "mp4"),
+];
+
+// ffmpeg muxers that can hold HEVC and AAC, for output_format. The incompatible ones are caught separately above
+// so they get a suggestion
+const OUTPUT_FORMATS: &[&str] = &["mp4", "mov", "matroska", "mpegts", "nut", "ismv", "ipod", "3gp", "mxf", "hls", "dash"];
+
+// File extensions people reach for as a format name, with the muxer ffmpeg actually calls it
+const FORMAT_ALIASES: &[(&str, &str)] = &[("mkv", "matroska"), ("ts", "mpegts"), ("m4v", "mp4"), ("m2ts", "mpegts")];
+
+const WATERMARK_POSITIONS: &[&str] = &["top-left", "top-right", "bottom-left", "bottom-right", "center"];
+
+// Curves ffmpeg's afade filter accepts for `curve=`
+const FADE_CURVES: &[&str] = &[
+    "tri", "qsin", "hsin", "esin", "log", "ipar", "qua", "cub", "squ", "cbr", "par", "exp", "iqsin", "ihsin", "dese",
+    "desi", "losi", "sinc", "isinc", "quat", "quatr", "qsin2", "hsin2", "nofade",
+];
+
+// Pixel formats the HEVC encoders take, for pixel_format. yuv420p is the one every player can decode
+const PIXEL_FORMATS: &[&str] = &[
+    "yuv420p", "yuvj420p", "yuv422p", "yuv444p", "yuv420p10le", "yuv422p10le", "yuv444p10le", "yuv420p12le", "nv12",
+    "p010le", "gray", "gray10le",
+];
+
+// Settings that only change how a run goes (where the files are, logging, retries, progress...) and not what it
+// writes, left out of Settings::output_hash
+const RUN_ONLY_SETTINGS: &[&str] = &[
+    "input_video_path", "output_video_path", "ffmpeg_path", "base_dir", "advanced_log", "progress_file", "retries",
+    "retry_delay_secs", "log_timestamps", "log_color", "backup_existing", "verify_output", "skip_if_matches",
+    "progress_template", "progress_refresh_hz", "max_parallel_jobs", "print_command", "timeout_secs", "skip_existing",
+    "atomic_output", "progress_by", "threads", "benchmark",
+    "dump_filtergraph", "log_progress_interval", "max_parallel_copies",
+];
+
+// Color names ffmpeg knows (the HTML ones), for the fade and padding colors. Matched ignoring case like ffmpeg does
+const COLOR_NAMES: &[&str] = &[
+    "aliceblue", "antiquewhite", "aqua", "aquamarine", "azure", "beige", "bisque", "black", "blanchedalmond", "blue",
+    "blueviolet", "brown", "burlywood", "cadetblue", "chartreuse", "chocolate", "coral", "cornflowerblue", "cornsilk",
+    "crimson", "cyan", "darkblue", "darkcyan", "darkgoldenrod", "darkgray", "darkgreen", "darkkhaki", "darkmagenta",
+    "darkolivegreen", "darkorange", "darkorchid", "darkred", "darksalmon", "darkseagreen", "darkslateblue",
+    "darkslategray", "darkturquoise", "darkviolet", "deeppink", "deepskyblue", "dimgray", "dodgerblue", "firebrick",
+    "floralwhite", "forestgreen", "fuchsia", "gainsboro", "ghostwhite", "gold", "goldenrod", "gray", "green",
+    "greenyellow", "honeydew", "hotpink", "indianred", "indigo", "ivory", "khaki", "lavender", "lavenderblush",
+    "lawngreen", "lemonchiffon", "lightblue", "lightcoral", "lightcyan", "lightgoldenrodyellow", "lightgreen",
+    "lightgrey", "lightpink", "lightsalmon", "lightseagreen", "lightskyblue", "lightslategray", "lightsteelblue",
+    "lightyellow", "lime", "limegreen", "linen", "magenta", "maroon", "mediumaquamarine", "mediumblue", "mediumorchid",
+    "mediumpurple", "mediumseagreen", "mediumslateblue", "mediumspringgreen", "mediumturquoise", "mediumvioletred",
+    "midnightblue", "mintcream", "mistyrose", "moccasin", "navajowhite", "navy", "oldlace", "olive", "olivedrab",
+    "orange", "orangered", "orchid", "palegoldenrod", "palegreen", "paleturquoise", "palevioletred", "papayawhip",
+    "peachpuff", "peru", "pink", "plum", "powderblue", "purple", "red", "rosybrown", "royalblue", "saddlebrown", "salmon",
+    "sandybrown", "seagreen", "seashell", "sienna", "silver", "skyblue", "slateblue", "slategray", "snow", "springgreen",
+    "steelblue", "tan", "teal", "thistle", "tomato", "turquoise", "violet", "wheat", "white", "whitesmoke", "yellow",
+    "yellowgreen", "random",
+];
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct Config {
     pub settings: Settings,
+    /// Input/output pairs from `[[batch]]` sections, each processed with the shared settings.
+    #[serde(default)]
+    pub batch: Vec<BatchJob>,
+    /// Extra renditions from `[[output]]` sections, encoded in the same run as `output_video_path`.
+    #[serde(default, rename = "output")]
+    pub outputs: Vec<OutputRendition>,
+}
+
+/// One file of a batch, it takes the place of `input_video_path` and `output_video_path`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct BatchJob {
+    pub input: String,
+    pub output: String,
 }
 
-#[derive(Debug, Deserialize)]
+/// Another version of the output, made from the same decoded and faded picture as `output_video_path` with its own
+/// size and bitrate.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct OutputRendition {
+    pub path: String,
+    /// Scaled to this size like `upscale_resolution`, the main output's size when left out.
+    pub resolution: Option<String>,
+    /// Replaces `video_bitrate` for this rendition.
+    pub video_bitrate: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Settings {
+    // Both can be left out when the config has a [[batch]] section
+    #[serde(default)]
     pub input_video_path: String,
+    #[serde(default)]
     pub output_video_path: String,
+    // The ffmpeg on PATH when it's left out
+    #[serde(default = "default_ffmpeg_path")]
     pub ffmpeg_path: String,
+    // Kept for older configs, gpu_encoder = "nvenc" does the same
+    #[serde(default)]
     pub use_gpu: bool,
     pub video_bitrate: String,
     pub crf: Option<String>,
     pub upscale_resolution: Option<String>,
-    pub background_audio_path: Option<String>,
+    // Each of these takes a single value or a list, one per background track
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub background_audio_path: Option<Vec<String>>,
     pub audio_start_time: f64,
     pub replace_audio: bool,
-    pub original_audio_volume: f64,
-    pub background_audio_volume: f64,
+    pub original_audio_volume: Volume,
+    #[serde(deserialize_with = "one_or_many")]
+    pub background_audio_volume: Vec<Volume>,
     pub clip_start_time: Option<String>,
     pub clip_end_time: Option<String>,
     pub video_speed: f64,
     pub advanced_log: bool,
-    pub fade_in_duration: Option<f64>,
-    pub fade_out_duration: Option<f64>,
+    pub fade_in_duration: Option<FadeDuration>,
+    pub fade_out_duration: Option<FadeDuration>,
+    #[serde(default)]
+    pub interpolate: bool,
+    pub target_fps: Option<f64>,
+    pub progress_file: Option<String>,
+    pub base_dir: Option<String>,
+    pub retries: Option<u32>,
+    pub retry_delay_secs: Option<f64>,
+    pub output_format: Option<String>,
+    pub deinterlace: Option<String>,
+    pub denoise: Option<String>,
+    pub denoise_method: Option<String>,
+    pub sharpen: Option<f64>,
+    #[serde(default)]
+    pub stabilize: bool,
+    pub speed_segments: Option<Vec<SpeedSegment>>,
+    pub contact_sheet_path: Option<String>,
+    pub contact_sheet_columns: Option<u32>,
+    pub contact_sheet_rows: Option<u32>,
+    pub preview_duration: Option<f64>,
+    #[serde(default)]
+    pub comparison: bool,
+    pub fades: Option<Vec<FadeSpec>>,
+    pub log_timestamps: Option<bool>,
+    pub log_color: Option<String>,
+    #[serde(default)]
+    pub backup_existing: bool,
+    #[serde(default)]
+    pub verify_output: bool,
+    #[serde(default)]
+    pub flip_horizontal: bool,
+    #[serde(default)]
+    pub flip_vertical: bool,
+    #[serde(default)]
+    pub negate: bool,
+    #[serde(default)]
+    pub vignette: bool,
+    pub vignette_angle: Option<f64>,
+    pub film_grain: Option<f64>,
+    pub loop_count: Option<u32>,
+    pub intro_path: Option<String>,
+    pub outro_path: Option<String>,
+    #[serde(default)]
+    pub concat_inputs: Vec<String>,
+    #[serde(default)]
+    pub skip_if_matches: bool,
+    pub progress_template: Option<String>,
+    pub progress_refresh_hz: Option<u32>,
+    pub color_range: Option<String>,
+    pub clip_ranges: Option<Vec<(String, String)>>,
+    pub audio_fade_in_duration: Option<FadeDuration>,
+    pub audio_fade_out_duration: Option<FadeDuration>,
+    pub max_parallel_jobs: Option<usize>,
+    pub fade_curve: Option<String>,
+    pub fade_in_curve: Option<String>,
+    pub fade_out_curve: Option<String>,
+    pub second_input_video_path: Option<String>,
+    pub crossfade_duration: Option<f64>,
+    pub subtitle_path: Option<String>,
+    pub subtitle_style: Option<String>,
+    pub watermark_path: Option<String>,
+    pub watermark_position: Option<String>,
+    pub watermark_opacity: Option<f64>,
+    #[serde(default)]
+    pub gpu_fallback: bool,
+    pub gpu_encoder: Option<String>,
+    #[serde(default)]
+    pub two_pass: bool,
+    pub audio_mode: Option<String>,
+    pub audio_codec: Option<String>,
+    pub audio_bitrate: Option<String>,
+    pub audio_sample_rate: Option<u32>,
+    #[serde(default)]
+    pub print_command: bool,
+    pub crop: Option<Crop>,
+    pub rotate: Option<String>,
+    #[serde(default)]
+    pub loudnorm: bool,
+    pub loudnorm_target: Option<f64>,
+    pub denoise_params: Option<String>,
+    pub sharpen_params: Option<String>,
+    pub thumbnail_path: Option<String>,
+    pub thumbnail_time: Option<String>,
+    pub timeout_secs: Option<f64>,
+    #[serde(default)]
+    pub preserve_metadata: bool,
+    pub skip_existing: Option<String>,
+    pub pixel_format: Option<String>,
+    pub encoder_preset: Option<String>,
+    pub encoder_tune: Option<String>,
+    #[serde(default = "enabled")]
+    pub video_fade: bool,
+    #[serde(default)]
+    pub extra_ffmpeg_args: Vec<String>,
+    #[serde(default = "enabled")]
+    pub atomic_output: bool,
+    pub max_bitrate: Option<String>,
+    pub buffer_size: Option<String>,
+    #[serde(default)]
+    pub loop_background_audio: bool,
+    #[serde(default)]
+    pub tonemap: bool,
+    pub max_resolution: Option<String>,
+    #[serde(default)]
+    pub separate_audio_tracks: bool,
+    #[serde(default)]
+    pub audio_track_titles: Vec<String>,
+    #[serde(default)]
+    pub audio_track_languages: Vec<String>,
+    pub keyframe_interval: Option<KeyframeInterval>,
+    #[serde(default)]
+    pub strict_gop: bool,
+    pub video_codec: Option<String>,
+    #[serde(default)]
+    pub fast_seek: bool,
+    pub progress_by: Option<String>,
+    #[serde(default)]
+    pub audio_limiter: bool,
+    pub audio_limiter_threshold: Option<f64>,
+    pub master_volume: Option<Volume>,
+    pub faststart: Option<bool>,
+    #[serde(default)]
+    pub audio_only: bool,
+    pub threads: Option<usize>,
+    pub pad_to_aspect: Option<String>,
+    pub pad_color: Option<String>,
+    pub fade_in_color: Option<String>,
+    pub fade_out_color: Option<String>,
+    pub background_audio_delay: Option<f64>,
+    pub waveform_path: Option<String>,
+    pub waveform_size: Option<String>,
+    pub chapters_file: Option<String>,
+    #[serde(default)]
+    pub blurred_background: bool,
+    #[serde(default)]
+    pub benchmark: bool,
+    pub color_effect: Option<String>,
+    pub dump_filtergraph: Option<String>,
+    pub log_progress_interval: Option<f64>,
+    pub max_parallel_copies: Option<usize>,
 }
 
-impl Config {
-    pub fn from_file(file_path: &str) -> Result<Self, config::ConfigError> {
-        let config_loader = ConfigLoader::builder().add_source(File::new(file_path, FileFormat::Toml)).build()?;
-        config_loader.try_deserialize()
+// For the switches that are on unless the config turns them off
+fn enabled() -> bool {
+    true
+}
+
+// A bare name, which running it looks up on PATH (Windows adds the .exe)
+fn default_ffmpeg_path() -> String {
+    "ffmpeg".to_string()
+}
+
+/// The executable running `name` would start, the way a shell finds it: a name with a directory in it is only checked
+/// where it points, a bare name is looked for in each directory of `path_var` (the value of PATH). Windows also tries
+/// the name with `.exe` added.
+pub fn find_executable(name: &str, path_var: Option<&OsStr>) -> Option<PathBuf> {
+    let candidates = |path: PathBuf| {
+        let exe = (cfg!(windows) && path.extension().is_none()).then(|| path.with_extension("exe"));
+        std::iter::once(path).chain(exe)
+    };
+    if name.is_empty() {
+        return None;
     }
+    if Path::new(name).components().count() > 1 {
+        return candidates(PathBuf::from(name)).find(|path| is_executable(path));
+    }
+    env::split_paths(path_var?).flat_map(|dir| candidates(dir.join(name))).find(|path| is_executable(path))
+}
 
-    pub fn validate(&self) -> Result<(), String> {
-        let required_keys = vec![
-            &self.settings.input_video_path,
-            &self.settings.output_video_path,
-            &self.settings.ffmpeg_path,
-            &self.settings.video_bitrate,
-        ];
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata().is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
 
-        for key in required_keys {
-            if key.is_empty() {
-                return Err(format!("Missing required config key: {}", key));
-            }
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+fn one_or_many<'de, D, T, V>(deserializer: D) -> Result<V, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+    V: From<Vec<T>>,
+{
+    let values = match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    };
+    Ok(values.into())
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum FadeDurationValue {
+    Seconds(f64),
+    Text(String),
+}
+
+/// A fade length, either in seconds (`0.8`) or as a share of the clip length (`"10%"`).
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "FadeDurationValue", into = "FadeDurationValue")]
+pub enum FadeDuration {
+    Seconds(f64),
+    Percent(f64),
+}
+
+impl TryFrom<FadeDurationValue> for FadeDuration {
+    type Error = String;
+
+    fn try_from(value: FadeDurationValue) -> Result<Self, Self::Error> {
+        match value {
+            FadeDurationValue::Seconds(seconds) => Ok(FadeDuration::Seconds(seconds)),
+            FadeDurationValue::Text(text) => text.parse(),
         }
+    }
+}
 
-        Ok(())
+impl From<FadeDuration> for FadeDurationValue {
+    fn from(duration: FadeDuration) -> Self {
+        match duration {
+            FadeDuration::Seconds(seconds) => FadeDurationValue::Seconds(seconds),
+            FadeDuration::Percent(percent) => FadeDurationValue::Text(format!("{}%", percent)),
+        }
+    }
+}
+
+impl FromStr for FadeDuration {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        // "none" turns the fade off, unlike leaving the key out which gets the default length
+        if text.eq_ignore_ascii_case("none") {
+            return Ok(FadeDuration::Seconds(0.0));
+        }
+        let parsed = match text.strip_suffix('%') {
+            Some(percent) => percent.trim().parse().map(FadeDuration::Percent),
+            None => text.parse().map(FadeDuration::Seconds),
+        };
+        parsed.map_err(|_| format!("invalid fade duration {:?}, expected seconds or a percentage like \"10%\"", text))
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum VolumeValue {
+    Factor(f64),
+    Text(String),
+}
+
+/// A volume change, as a multiplier (`0.5`), a percentage (`"50%"`, stored as the multiplier) or in decibels
+/// (`"-6dB"`).
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "VolumeValue", into = "VolumeValue")]
+pub enum Volume {
+    Factor(f64),
+    Decibels(f64),
+}
+
+impl TryFrom<VolumeValue> for Volume {
+    type Error = String;
+
+    fn try_from(value: VolumeValue) -> Result<Self, Self::Error> {
+        match value {
+            VolumeValue::Factor(factor) => Ok(Volume::Factor(factor)),
+            VolumeValue::Text(text) => text.parse(),
+        }
+    }
+}
+
+impl From<Volume> for VolumeValue {
+    fn from(volume: Volume) -> Self {
+        match volume {
+            Volume::Factor(factor) => VolumeValue::Factor(factor),
+            Volume::Decibels(decibels) => VolumeValue::Text(format!("{}dB", decibels)),
+        }
+    }
+}
+
+impl FromStr for Volume {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        let lower = text.to_lowercase();
+        let parsed = if let Some(decibels) = lower.strip_suffix("db") {
+            decibels.trim().parse().map(Volume::Decibels)
+        } else if let Some(percent) = lower.strip_suffix('%') {
+            percent.trim().parse().map(|percent: f64| Volume::Factor(percent / 100.0))
+        } else {
+            lower.parse().map(Volume::Factor)
+        };
+        parsed.map_err(|_| {
+            format!("invalid volume {:?}, expected a multiplier, a percentage like \"50%\" or decibels like \"-6dB\"", text)
+        })
+    }
+}
+
+impl From<f64> for Volume {
+    fn from(factor: f64) -> Self {
+        Volume::Factor(factor)
+    }
+}
+
+impl Volume {
+    /// Whether the volume is left as it is.
+    pub fn is_unchanged(&self) -> bool {
+        match *self {
+            Volume::Factor(factor) => factor == 1.0,
+            Volume::Decibels(decibels) => decibels == 0.0,
+        }
+    }
+}
+
+// The way ffmpeg's volume filter takes it, which understands the dB suffix itself
+impl fmt::Display for Volume {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Volume::Factor(factor) => write!(f, "{}", factor),
+            Volume::Decibels(decibels) => write!(f, "{}dB", decibels),
+        }
+    }
+}
+
+impl FadeDuration {
+    /// The fade length in seconds for a clip of `clip_length` seconds, if it can be known.
+    pub fn seconds(&self, clip_length: Option<f64>) -> Option<f64> {
+        match *self {
+            FadeDuration::Seconds(seconds) => Some(seconds),
+            FadeDuration::Percent(percent) => clip_length.map(|length| length * percent / 100.0),
+        }
+    }
+
+    /// Whether the fade is turned off with a length of 0 (or `"none"`).
+    pub fn is_off(&self) -> bool {
+        match *self {
+            FadeDuration::Seconds(length) | FadeDuration::Percent(length) => length == 0.0,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum KeyframeIntervalValue {
+    Frames(u32),
+    Text(String),
+}
+
+/// The distance between keyframes, in frames (`60`) or in seconds (`"2s"`).
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "KeyframeIntervalValue", into = "KeyframeIntervalValue")]
+pub enum KeyframeInterval {
+    Frames(u32),
+    Seconds(f64),
+}
+
+impl TryFrom<KeyframeIntervalValue> for KeyframeInterval {
+    type Error = String;
+
+    fn try_from(value: KeyframeIntervalValue) -> Result<Self, Self::Error> {
+        match value {
+            KeyframeIntervalValue::Frames(frames) => Ok(KeyframeInterval::Frames(frames)),
+            KeyframeIntervalValue::Text(text) => text.parse(),
+        }
+    }
+}
+
+impl From<KeyframeInterval> for KeyframeIntervalValue {
+    fn from(interval: KeyframeInterval) -> Self {
+        match interval {
+            KeyframeInterval::Frames(frames) => KeyframeIntervalValue::Frames(frames),
+            KeyframeInterval::Seconds(seconds) => KeyframeIntervalValue::Text(format!("{}s", seconds)),
+        }
+    }
+}
+
+impl FromStr for KeyframeInterval {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        let parsed = match text.strip_suffix('s') {
+            Some(seconds) => seconds.trim().parse().map(KeyframeInterval::Seconds).map_err(|_| ()),
+            None => text.parse().map(KeyframeInterval::Frames).map_err(|_| ()),
+        };
+        parsed.map_err(|_| format!("invalid keyframe interval {:?}, expected frames (60) or seconds like \"2s\"", text))
+    }
+}
+
+impl KeyframeInterval {
+    /// The interval in frames of a video running at `framerate`, which is only needed for an interval in seconds.
+    pub fn frames(&self, framerate: Option<f64>) -> Option<u32> {
+        match *self {
+            KeyframeInterval::Frames(frames) => Some(frames),
+            KeyframeInterval::Seconds(seconds) => framerate.map(|rate| (seconds * rate).round() as u32),
+        }
+    }
+}
+
+/// A hardware HEVC encoder, picked with `gpu_encoder` (or `use_gpu` for NVENC).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GpuEncoder {
+    /// NVIDIA
+    Nvenc,
+    /// AMD
+    Amf,
+    /// Intel Quick Sync
+    Qsv,
+}
+
+impl GpuEncoder {
+    /// The name ffmpeg knows the encoder by.
+    pub fn ffmpeg_name(self) -> &'static str {
+        match self {
+            GpuEncoder::Nvenc => "hevc_nvenc",
+            GpuEncoder::Amf => "hevc_amf",
+            GpuEncoder::Qsv => "hevc_qsv",
+        }
+    }
+}
+
+/// A software video encoder, picked with `video_codec`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoCodec {
+    /// HEVC through libx265, the default
+    X265,
+    /// VP9 through libvpx
+    Vp9,
+    /// AV1 through libaom
+    Aom,
+    /// AV1 through SVT-AV1
+    SvtAv1,
+}
+
+impl VideoCodec {
+    /// The codecs `video_codec` takes, by the names ffmpeg knows them by.
+    pub const NAMES: [&'static str; 4] = ["libx265", "libvpx-vp9", "libaom-av1", "libsvtav1"];
+
+    /// The codec ffmpeg knows as `name`.
+    pub fn from_name(name: &str) -> Option<VideoCodec> {
+        match name.to_lowercase().as_str() {
+            "libx265" => Some(VideoCodec::X265),
+            "libvpx-vp9" => Some(VideoCodec::Vp9),
+            "libaom-av1" => Some(VideoCodec::Aom),
+            "libsvtav1" => Some(VideoCodec::SvtAv1),
+            _ => None,
+        }
+    }
+
+    /// The name ffmpeg knows the encoder by.
+    pub fn ffmpeg_name(self) -> &'static str {
+        match self {
+            VideoCodec::X265 => "libx265",
+            VideoCodec::Vp9 => "libvpx-vp9",
+            VideoCodec::Aom => "libaom-av1",
+            VideoCodec::SvtAv1 => "libsvtav1",
+        }
+    }
+
+    /// The `codec_name` ffprobe reports for video made by this encoder.
+    pub fn probe_name(self) -> &'static str {
+        match self {
+            VideoCodec::X265 => "hevc",
+            VideoCodec::Vp9 => "vp9",
+            VideoCodec::Aom | VideoCodec::SvtAv1 => "av1",
+        }
+    }
+
+    /// Whether it's one of the web codecs WebM holds.
+    pub fn is_web(self) -> bool {
+        self != VideoCodec::X265
+    }
+
+    /// The highest `crf` the encoder takes.
+    pub fn max_crf(self) -> u32 {
+        match self {
+            VideoCodec::X265 => 51,
+            _ => 63,
+        }
+    }
+}
+
+/// The option that trades encoding speed for compression on `encoder` (the software `codec` for `None`) and the
+/// values it takes, fastest first. AMF calls it `-quality` instead of `-preset`, libvpx and libaom `-cpu-used`
+/// and SVT-AV1 counts its `-preset` down from 13.
+pub fn preset_option(encoder: Option<GpuEncoder>, codec: VideoCodec) -> (&'static str, &'static [&'static str]) {
+    match encoder {
+        None => match codec {
+            VideoCodec::X265 => (
+                "-preset",
+                &["ultrafast", "superfast", "veryfast", "faster", "fast", "medium", "slow", "slower", "veryslow", "placebo"],
+            ),
+            VideoCodec::Vp9 => ("-cpu-used", &["5", "4", "3", "2", "1", "0"]),
+            VideoCodec::Aom => ("-cpu-used", &["8", "7", "6", "5", "4", "3", "2", "1", "0"]),
+            VideoCodec::SvtAv1 => (
+                "-preset",
+                &["13", "12", "11", "10", "9", "8", "7", "6", "5", "4", "3", "2", "1", "0"],
+            ),
+        },
+        Some(GpuEncoder::Nvenc) => ("-preset", &["p1", "p2", "p3", "p4", "p5", "p6", "p7"]),
+        Some(GpuEncoder::Qsv) => ("-preset", &["veryfast", "faster", "fast", "medium", "slow", "slower", "veryslow"]),
+        Some(GpuEncoder::Amf) => ("-quality", &["speed", "balanced", "quality"]),
+    }
+}
+
+/// The option that tunes `encoder` (the software `codec` for `None`) for a kind of content or use and the values
+/// it takes. AMF calls it `-usage` and libvpx `-tune-content`, QSV and SVT-AV1 have none.
+pub fn tune_option(encoder: Option<GpuEncoder>, codec: VideoCodec) -> Option<(&'static str, &'static [&'static str])> {
+    match encoder {
+        None => match codec {
+            VideoCodec::X265 => Some(("-tune", &["psnr", "ssim", "grain", "zerolatency", "fastdecode", "animation"])),
+            VideoCodec::Vp9 => Some(("-tune-content", &["default", "screen", "film"])),
+            VideoCodec::Aom => Some(("-tune", &["psnr", "ssim"])),
+            VideoCodec::SvtAv1 => None,
+        },
+        Some(GpuEncoder::Nvenc) => Some(("-tune", &["hq", "ll", "ull", "lossless"])),
+        Some(GpuEncoder::Amf) => Some(("-usage", &["transcoding", "ultralowlatency", "lowlatency", "webcam"])),
+        Some(GpuEncoder::Qsv) => None,
+    }
+}
+
+/// A time range of the source (in seconds) played back at `factor` times the normal speed.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SpeedSegment {
+    pub start: f64,
+    pub end: f64,
+    pub factor: f64,
+}
+
+/// A `width` x `height` rectangle cut out of the picture with its top-left corner at `x`/`y`. Either of those
+/// left out centers the rectangle in that direction.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Crop {
+    pub width: u32,
+    pub height: u32,
+    pub x: Option<u32>,
+    pub y: Option<u32>,
+}
+
+impl Crop {
+    /// The `crop` filter for this rectangle.
+    pub fn filter(&self) -> String {
+        let mut filter = format!("crop=w={}:h={}", self.width, self.height);
+        // crop centers by itself when x or y isn't given
+        if let Some(x) = self.x {
+            filter.push_str(&format!(":x={}", x));
+        }
+        if let Some(y) = self.y {
+            filter.push_str(&format!(":y={}", y));
+        }
+        filter
+    }
+}
+
+/// Which way a [`FadeSpec`] fades.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FadeKind {
+    In,
+    Out,
+}
+
+/// A single fade at `start` seconds into the clip, lasting `duration` seconds. Video fades from or to `color`
+/// (black by default), audio fades from or to silence.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct FadeSpec {
+    #[serde(rename = "type")]
+    pub kind: FadeKind,
+    pub start: f64,
+    pub duration: f64,
+    pub color: Option<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            input_video_path: String::new(),
+            output_video_path: String::new(),
+            ffmpeg_path: default_ffmpeg_path(),
+            use_gpu: false,
+            video_bitrate: "25M".to_string(),
+            crf: None,
+            upscale_resolution: None,
+            background_audio_path: None,
+            audio_start_time: 0.0,
+            replace_audio: false,
+            original_audio_volume: Volume::Factor(1.0),
+            background_audio_volume: vec![Volume::Factor(1.0)],
+            clip_start_time: None,
+            clip_end_time: None,
+            video_speed: 1.0,
+            advanced_log: false,
+            fade_in_duration: None,
+            fade_out_duration: None,
+            interpolate: false,
+            target_fps: None,
+            progress_file: None,
+            base_dir: None,
+            retries: None,
+            retry_delay_secs: None,
+            output_format: None,
+            deinterlace: None,
+            denoise: None,
+            denoise_method: None,
+            sharpen: None,
+            stabilize: false,
+            speed_segments: None,
+            contact_sheet_path: None,
+            contact_sheet_columns: None,
+            contact_sheet_rows: None,
+            preview_duration: None,
+            comparison: false,
+            fades: None,
+            log_timestamps: None,
+            log_color: None,
+            backup_existing: false,
+            verify_output: false,
+            flip_horizontal: false,
+            flip_vertical: false,
+            negate: false,
+            vignette: false,
+            vignette_angle: None,
+            film_grain: None,
+            loop_count: None,
+            intro_path: None,
+            outro_path: None,
+            concat_inputs: Vec::new(),
+            skip_if_matches: false,
+            progress_template: None,
+            progress_refresh_hz: None,
+            color_range: None,
+            clip_ranges: None,
+            audio_fade_in_duration: None,
+            audio_fade_out_duration: None,
+            max_parallel_jobs: None,
+            fade_curve: None,
+            fade_in_curve: None,
+            fade_out_curve: None,
+            second_input_video_path: None,
+            crossfade_duration: None,
+            subtitle_path: None,
+            subtitle_style: None,
+            watermark_path: None,
+            watermark_position: None,
+            watermark_opacity: None,
+            gpu_fallback: false,
+            gpu_encoder: None,
+            two_pass: false,
+            audio_mode: None,
+            audio_codec: None,
+            audio_bitrate: None,
+            audio_sample_rate: None,
+            print_command: false,
+            crop: None,
+            rotate: None,
+            loudnorm: false,
+            loudnorm_target: None,
+            denoise_params: None,
+            sharpen_params: None,
+            thumbnail_path: None,
+            thumbnail_time: None,
+            timeout_secs: None,
+            preserve_metadata: false,
+            skip_existing: None,
+            pixel_format: None,
+            encoder_preset: None,
+            encoder_tune: None,
+            video_fade: true,
+            extra_ffmpeg_args: Vec::new(),
+            atomic_output: true,
+            max_bitrate: None,
+            buffer_size: None,
+            loop_background_audio: false,
+            tonemap: false,
+            max_resolution: None,
+            separate_audio_tracks: false,
+            audio_track_titles: Vec::new(),
+            audio_track_languages: Vec::new(),
+            keyframe_interval: None,
+            strict_gop: false,
+            video_codec: None,
+            fast_seek: false,
+            progress_by: None,
+            audio_limiter: false,
+            audio_limiter_threshold: None,
+            master_volume: None,
+            faststart: None,
+            audio_only: false,
+            threads: None,
+            pad_to_aspect: None,
+            pad_color: None,
+            fade_in_color: None,
+            fade_out_color: None,
+            background_audio_delay: None,
+            waveform_path: None,
+            waveform_size: None,
+            chapters_file: None,
+            blurred_background: false,
+            benchmark: false,
+            color_effect: None,
+            dump_filtergraph: None,
+            log_progress_interval: None,
+            max_parallel_copies: None,
+        }
+    }
+}
+
+impl Settings {
+    pub fn builder() -> SettingsBuilder {
+        SettingsBuilder::default()
+    }
+
+    /// The background audio files to mix in, leaving out `"None"`.
+    pub fn background_audio_files(&self) -> Vec<&str> {
+        let paths = self.background_audio_path.iter().flatten();
+        paths.filter(|path| path.to_lowercase() != "none").map(String::as_str).collect()
+    }
+
+    /// The volume of the background track at `index`, a single volume applies to all of them.
+    pub fn background_audio_volume_of(&self, index: usize) -> Volume {
+        let volumes = &self.background_audio_volume;
+        volumes.get(index).or(volumes.first()).copied().unwrap_or(Volume::Factor(1.0))
+    }
+
+    /// The hardware encoder to use, or `None` for the software codec. `gpu_encoder` wins over `use_gpu`, and a
+    /// `video_codec` turns both off.
+    pub fn hardware_encoder(&self) -> Option<GpuEncoder> {
+        if self.video_codec.is_some() {
+            return None;
+        }
+        match self.gpu_encoder.as_ref().map(|encoder| encoder.to_lowercase()) {
+            Some(encoder) => match encoder.as_str() {
+                "nvenc" => Some(GpuEncoder::Nvenc),
+                "amf" => Some(GpuEncoder::Amf),
+                "qsv" => Some(GpuEncoder::Qsv),
+                _ => None,
+            },
+            None if self.use_gpu => Some(GpuEncoder::Nvenc),
+            None => None,
+        }
+    }
+
+    /// The software encoder to use when there's no hardware one, libx265 unless `video_codec` picks another.
+    pub fn software_codec(&self) -> VideoCodec {
+        self.video_codec.as_deref().and_then(VideoCodec::from_name).unwrap_or(VideoCodec::X265)
+    }
+
+    /// The output container, from `output_format` or else the output file's extension.
+    pub fn container(&self) -> Option<String> {
+        let extension = Path::new(&self.output_video_path).extension().and_then(|extension| extension.to_str());
+        self.output_format.as_deref().or(extension).map(str::to_lowercase)
+    }
+
+    /// Whether `progress_by` asks for the progress to be counted in frames rather than seconds.
+    pub fn progress_by_frames(&self) -> bool {
+        self.progress_by.as_ref().is_some_and(|by| by.to_lowercase() == "frames")
+    }
+
+    /// The audio fade curves for fade-ins and fade-outs, `fade_in_curve`/`fade_out_curve` win over `fade_curve`.
+    pub fn fade_curves(&self) -> (Option<&str>, Option<&str>) {
+        let fade_in = self.fade_in_curve.as_ref().or(self.fade_curve.as_ref());
+        let fade_out = self.fade_out_curve.as_ref().or(self.fade_curve.as_ref());
+        (fade_in.map(String::as_str), fade_out.map(String::as_str))
+    }
+
+    /// The ffmpeg `ffmpeg_path` starts, found on PATH when it's a bare name. Fails up front when there's none, rather
+    /// than with whatever the first ffmpeg run happens to report.
+    pub fn ffmpeg_executable(&self) -> Result<PathBuf, ProcessingError> {
+        find_executable(&self.ffmpeg_path, env::var_os("PATH").as_deref())
+            .ok_or_else(|| ProcessingError::FfmpegNotFound(self.ffmpeg_path.clone()))
+    }
+
+    /// A fingerprint of the settings that shape the output, as 16 hex digits. It's the same for every file of a batch
+    /// and from one run to the next as long as none of those settings change, which is what `skip_existing =
+    /// "settings"` compares against the one stored in an earlier output.
+    pub fn output_hash(&self) -> String {
+        let mut settings = serde_json::to_value(self).unwrap_or_default();
+        if let Some(settings) = settings.as_object_mut() {
+            settings.retain(|key, _| !RUN_ONLY_SETTINGS.contains(&key.as_str()));
+        }
+        // FNV-1a, unlike std's hasher it's guaranteed to give the same hash in every build. The JSON's keys are sorted
+        let hash = settings.to_string().bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+        });
+        format!("{:016x}", hash)
+    }
+
+    /// `clip_ranges` parsed into start and end seconds.
+    pub fn clip_ranges_seconds(&self) -> Result<Option<Vec<(f64, f64)>>, String> {
+        let Some(ref ranges) = self.clip_ranges else {
+            return Ok(None);
+        };
+        let parse = |time: &str| parse_time(time).map_err(|e| format!("Invalid time in clip_ranges: {}", e));
+        ranges.iter().map(|(start, end)| Ok((parse(start)?, parse(end)?))).collect::<Result<_, _>>().map(Some)
+    }
+}
+
+/// Fluent builder for [`Settings`], starting from [`Settings::default`].
+#[derive(Debug, Clone, Default)]
+pub struct SettingsBuilder {
+    settings: Settings,
+}
+
+impl SettingsBuilder {
+    pub fn input_video_path(mut self, input_video_path: impl Into<String>) -> Self {
+        self.settings.input_video_path = input_video_path.into();
+        self
+    }
+
+    pub fn output_video_path(mut self, output_video_path: impl Into<String>) -> Self {
+        self.settings.output_video_path = output_video_path.into();
+        self
+    }
+
+    pub fn ffmpeg_path(mut self, ffmpeg_path: impl Into<String>) -> Self {
+        self.settings.ffmpeg_path = ffmpeg_path.into();
+        self
+    }
+
+    pub fn use_gpu(mut self, use_gpu: bool) -> Self {
+        self.settings.use_gpu = use_gpu;
+        self
+    }
+
+    pub fn video_bitrate(mut self, video_bitrate: impl Into<String>) -> Self {
+        self.settings.video_bitrate = video_bitrate.into();
+        self
+    }
+
+    pub fn crf(mut self, crf: impl Into<String>) -> Self {
+        self.settings.crf = Some(crf.into());
+        self
+    }
+
+    pub fn upscale_resolution(mut self, upscale_resolution: impl Into<String>) -> Self {
+        self.settings.upscale_resolution = Some(upscale_resolution.into());
+        self
+    }
+
+    pub fn background_audio_path(mut self, background_audio_path: impl Into<String>) -> Self {
+        self.settings.background_audio_path = Some(vec![background_audio_path.into()]);
+        self
+    }
+
+    pub fn background_audio_paths(mut self, background_audio_paths: Vec<String>) -> Self {
+        self.settings.background_audio_path = Some(background_audio_paths);
+        self
+    }
+
+    pub fn audio_start_time(mut self, audio_start_time: f64) -> Self {
+        self.settings.audio_start_time = audio_start_time;
+        self
+    }
+
+    pub fn replace_audio(mut self, replace_audio: bool) -> Self {
+        self.settings.replace_audio = replace_audio;
+        self
+    }
+
+    pub fn original_audio_volume(mut self, original_audio_volume: impl Into<Volume>) -> Self {
+        self.settings.original_audio_volume = original_audio_volume.into();
+        self
+    }
+
+    pub fn background_audio_volume(mut self, background_audio_volume: impl Into<Volume>) -> Self {
+        self.settings.background_audio_volume = vec![background_audio_volume.into()];
+        self
+    }
+
+    pub fn background_audio_volumes(mut self, background_audio_volumes: Vec<Volume>) -> Self {
+        self.settings.background_audio_volume = background_audio_volumes;
+        self
+    }
+
+    pub fn clip_start_time(mut self, clip_start_time: impl Into<String>) -> Self {
+        self.settings.clip_start_time = Some(clip_start_time.into());
+        self
+    }
+
+    pub fn clip_end_time(mut self, clip_end_time: impl Into<String>) -> Self {
+        self.settings.clip_end_time = Some(clip_end_time.into());
+        self
+    }
+
+    pub fn video_speed(mut self, video_speed: f64) -> Self {
+        self.settings.video_speed = video_speed;
+        self
+    }
+
+    pub fn advanced_log(mut self, advanced_log: bool) -> Self {
+        self.settings.advanced_log = advanced_log;
+        self
+    }
+
+    pub fn fade_in_duration(mut self, fade_in_duration: FadeDuration) -> Self {
+        self.settings.fade_in_duration = Some(fade_in_duration);
+        self
+    }
+
+    pub fn fade_out_duration(mut self, fade_out_duration: FadeDuration) -> Self {
+        self.settings.fade_out_duration = Some(fade_out_duration);
+        self
+    }
+
+    pub fn interpolate(mut self, interpolate: bool) -> Self {
+        self.settings.interpolate = interpolate;
+        self
+    }
+
+    pub fn target_fps(mut self, target_fps: f64) -> Self {
+        self.settings.target_fps = Some(target_fps);
+        self
+    }
+
+    pub fn progress_file(mut self, progress_file: impl Into<String>) -> Self {
+        self.settings.progress_file = Some(progress_file.into());
+        self
+    }
+
+    pub fn base_dir(mut self, base_dir: impl Into<String>) -> Self {
+        self.settings.base_dir = Some(base_dir.into());
+        self
+    }
+
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.settings.retries = Some(retries);
+        self
+    }
+
+    pub fn retry_delay_secs(mut self, retry_delay_secs: f64) -> Self {
+        self.settings.retry_delay_secs = Some(retry_delay_secs);
+        self
+    }
+
+    pub fn output_format(mut self, output_format: impl Into<String>) -> Self {
+        self.settings.output_format = Some(output_format.into());
+        self
+    }
+
+    pub fn deinterlace(mut self, deinterlace: impl Into<String>) -> Self {
+        self.settings.deinterlace = Some(deinterlace.into());
+        self
+    }
+
+    pub fn denoise(mut self, denoise: impl Into<String>) -> Self {
+        self.settings.denoise = Some(denoise.into());
+        self
+    }
+
+    pub fn denoise_method(mut self, denoise_method: impl Into<String>) -> Self {
+        self.settings.denoise_method = Some(denoise_method.into());
+        self
+    }
+
+    pub fn sharpen(mut self, sharpen: f64) -> Self {
+        self.settings.sharpen = Some(sharpen);
+        self
+    }
+
+    pub fn stabilize(mut self, stabilize: bool) -> Self {
+        self.settings.stabilize = stabilize;
+        self
+    }
+
+    pub fn speed_segments(mut self, speed_segments: Vec<SpeedSegment>) -> Self {
+        self.settings.speed_segments = Some(speed_segments);
+        self
+    }
+
+    pub fn contact_sheet_path(mut self, contact_sheet_path: impl Into<String>) -> Self {
+        self.settings.contact_sheet_path = Some(contact_sheet_path.into());
+        self
+    }
+
+    pub fn contact_sheet_columns(mut self, contact_sheet_columns: u32) -> Self {
+        self.settings.contact_sheet_columns = Some(contact_sheet_columns);
+        self
+    }
+
+    pub fn contact_sheet_rows(mut self, contact_sheet_rows: u32) -> Self {
+        self.settings.contact_sheet_rows = Some(contact_sheet_rows);
+        self
+    }
+
+    pub fn preview_duration(mut self, preview_duration: f64) -> Self {
+        self.settings.preview_duration = Some(preview_duration);
+        self
+    }
+
+    pub fn comparison(mut self, comparison: bool) -> Self {
+        self.settings.comparison = comparison;
+        self
+    }
+
+    pub fn fades(mut self, fades: Vec<FadeSpec>) -> Self {
+        self.settings.fades = Some(fades);
+        self
+    }
+
+    pub fn log_timestamps(mut self, log_timestamps: bool) -> Self {
+        self.settings.log_timestamps = Some(log_timestamps);
+        self
+    }
+
+    pub fn log_color(mut self, log_color: impl Into<String>) -> Self {
+        self.settings.log_color = Some(log_color.into());
+        self
+    }
+
+    pub fn backup_existing(mut self, backup_existing: bool) -> Self {
+        self.settings.backup_existing = backup_existing;
+        self
+    }
+
+    pub fn verify_output(mut self, verify_output: bool) -> Self {
+        self.settings.verify_output = verify_output;
+        self
+    }
+
+    pub fn flip_horizontal(mut self, flip_horizontal: bool) -> Self {
+        self.settings.flip_horizontal = flip_horizontal;
+        self
+    }
+
+    pub fn flip_vertical(mut self, flip_vertical: bool) -> Self {
+        self.settings.flip_vertical = flip_vertical;
+        self
+    }
+
+    pub fn negate(mut self, negate: bool) -> Self {
+        self.settings.negate = negate;
+        self
+    }
+
+    pub fn vignette(mut self, vignette: bool) -> Self {
+        self.settings.vignette = vignette;
+        self
+    }
+
+    pub fn vignette_angle(mut self, vignette_angle: f64) -> Self {
+        self.settings.vignette_angle = Some(vignette_angle);
+        self
+    }
+
+    pub fn film_grain(mut self, film_grain: f64) -> Self {
+        self.settings.film_grain = Some(film_grain);
+        self
+    }
+
+    pub fn loop_count(mut self, loop_count: u32) -> Self {
+        self.settings.loop_count = Some(loop_count);
+        self
+    }
+
+    pub fn intro_path(mut self, intro_path: impl Into<String>) -> Self {
+        self.settings.intro_path = Some(intro_path.into());
+        self
+    }
+
+    pub fn outro_path(mut self, outro_path: impl Into<String>) -> Self {
+        self.settings.outro_path = Some(outro_path.into());
+        self
+    }
+
+    pub fn concat_inputs(mut self, concat_inputs: Vec<String>) -> Self {
+        self.settings.concat_inputs = concat_inputs;
+        self
+    }
+
+    pub fn skip_if_matches(mut self, skip_if_matches: bool) -> Self {
+        self.settings.skip_if_matches = skip_if_matches;
+        self
+    }
+
+    pub fn progress_template(mut self, progress_template: impl Into<String>) -> Self {
+        self.settings.progress_template = Some(progress_template.into());
+        self
+    }
+
+    pub fn progress_refresh_hz(mut self, progress_refresh_hz: u32) -> Self {
+        self.settings.progress_refresh_hz = Some(progress_refresh_hz);
+        self
+    }
+
+    pub fn color_range(mut self, color_range: impl Into<String>) -> Self {
+        self.settings.color_range = Some(color_range.into());
+        self
+    }
+
+    pub fn clip_ranges(mut self, clip_ranges: Vec<(String, String)>) -> Self {
+        self.settings.clip_ranges = Some(clip_ranges);
+        self
+    }
+
+    pub fn audio_fade_in_duration(mut self, audio_fade_in_duration: FadeDuration) -> Self {
+        self.settings.audio_fade_in_duration = Some(audio_fade_in_duration);
+        self
+    }
+
+    pub fn audio_fade_out_duration(mut self, audio_fade_out_duration: FadeDuration) -> Self {
+        self.settings.audio_fade_out_duration = Some(audio_fade_out_duration);
+        self
+    }
+
+    pub fn max_parallel_jobs(mut self, max_parallel_jobs: usize) -> Self {
+        self.settings.max_parallel_jobs = Some(max_parallel_jobs);
+        self
+    }
+
+    pub fn fade_curve(mut self, fade_curve: impl Into<String>) -> Self {
+        self.settings.fade_curve = Some(fade_curve.into());
+        self
+    }
+
+    pub fn fade_in_curve(mut self, fade_in_curve: impl Into<String>) -> Self {
+        self.settings.fade_in_curve = Some(fade_in_curve.into());
+        self
+    }
+
+    pub fn fade_out_curve(mut self, fade_out_curve: impl Into<String>) -> Self {
+        self.settings.fade_out_curve = Some(fade_out_curve.into());
+        self
+    }
+
+    pub fn second_input_video_path(mut self, second_input_video_path: impl Into<String>) -> Self {
+        self.settings.second_input_video_path = Some(second_input_video_path.into());
+        self
+    }
+
+    pub fn crossfade_duration(mut self, crossfade_duration: f64) -> Self {
+        self.settings.crossfade_duration = Some(crossfade_duration);
+        self
+    }
+
+    pub fn subtitle_path(mut self, subtitle_path: impl Into<String>) -> Self {
+        self.settings.subtitle_path = Some(subtitle_path.into());
+        self
+    }
+
+    pub fn subtitle_style(mut self, subtitle_style: impl Into<String>) -> Self {
+        self.settings.subtitle_style = Some(subtitle_style.into());
+        self
+    }
+
+    pub fn watermark_path(mut self, watermark_path: impl Into<String>) -> Self {
+        self.settings.watermark_path = Some(watermark_path.into());
+        self
+    }
+
+    pub fn watermark_position(mut self, watermark_position: impl Into<String>) -> Self {
+        self.settings.watermark_position = Some(watermark_position.into());
+        self
+    }
+
+    pub fn watermark_opacity(mut self, watermark_opacity: f64) -> Self {
+        self.settings.watermark_opacity = Some(watermark_opacity);
+        self
+    }
+
+    pub fn gpu_fallback(mut self, gpu_fallback: bool) -> Self {
+        self.settings.gpu_fallback = gpu_fallback;
+        self
+    }
+
+    pub fn gpu_encoder(mut self, gpu_encoder: impl Into<String>) -> Self {
+        self.settings.gpu_encoder = Some(gpu_encoder.into());
+        self
+    }
+
+    pub fn two_pass(mut self, two_pass: bool) -> Self {
+        self.settings.two_pass = two_pass;
+        self
+    }
+
+    pub fn audio_mode(mut self, audio_mode: impl Into<String>) -> Self {
+        self.settings.audio_mode = Some(audio_mode.into());
+        self
+    }
+
+    pub fn audio_codec(mut self, audio_codec: impl Into<String>) -> Self {
+        self.settings.audio_codec = Some(audio_codec.into());
+        self
+    }
+
+    pub fn audio_bitrate(mut self, audio_bitrate: impl Into<String>) -> Self {
+        self.settings.audio_bitrate = Some(audio_bitrate.into());
+        self
+    }
+
+    pub fn audio_sample_rate(mut self, audio_sample_rate: u32) -> Self {
+        self.settings.audio_sample_rate = Some(audio_sample_rate);
+        self
+    }
+
+    pub fn print_command(mut self, print_command: bool) -> Self {
+        self.settings.print_command = print_command;
+        self
+    }
+
+    pub fn crop(mut self, crop: Crop) -> Self {
+        self.settings.crop = Some(crop);
+        self
+    }
+
+    pub fn rotate(mut self, rotate: impl Into<String>) -> Self {
+        self.settings.rotate = Some(rotate.into());
+        self
+    }
+
+    pub fn loudnorm(mut self, loudnorm: bool) -> Self {
+        self.settings.loudnorm = loudnorm;
+        self
+    }
+
+    pub fn loudnorm_target(mut self, loudnorm_target: f64) -> Self {
+        self.settings.loudnorm_target = Some(loudnorm_target);
+        self
+    }
+
+    pub fn denoise_params(mut self, denoise_params: impl Into<String>) -> Self {
+        self.settings.denoise_params = Some(denoise_params.into());
+        self
+    }
+
+    pub fn sharpen_params(mut self, sharpen_params: impl Into<String>) -> Self {
+        self.settings.sharpen_params = Some(sharpen_params.into());
+        self
+    }
+
+    pub fn thumbnail_path(mut self, thumbnail_path: impl Into<String>) -> Self {
+        self.settings.thumbnail_path = Some(thumbnail_path.into());
+        self
+    }
+
+    pub fn thumbnail_time(mut self, thumbnail_time: impl Into<String>) -> Self {
+        self.settings.thumbnail_time = Some(thumbnail_time.into());
+        self
+    }
+
+    pub fn timeout_secs(mut self, timeout_secs: f64) -> Self {
+        self.settings.timeout_secs = Some(timeout_secs);
+        self
+    }
+
+    pub fn preserve_metadata(mut self, preserve_metadata: bool) -> Self {
+        self.settings.preserve_metadata = preserve_metadata;
+        self
+    }
+
+    pub fn skip_existing(mut self, skip_existing: impl Into<String>) -> Self {
+        self.settings.skip_existing = Some(skip_existing.into());
+        self
+    }
+
+    pub fn pixel_format(mut self, pixel_format: impl Into<String>) -> Self {
+        self.settings.pixel_format = Some(pixel_format.into());
+        self
+    }
+
+    pub fn encoder_preset(mut self, encoder_preset: impl Into<String>) -> Self {
+        self.settings.encoder_preset = Some(encoder_preset.into());
+        self
+    }
+
+    pub fn encoder_tune(mut self, encoder_tune: impl Into<String>) -> Self {
+        self.settings.encoder_tune = Some(encoder_tune.into());
+        self
+    }
+
+    pub fn video_fade(mut self, video_fade: bool) -> Self {
+        self.settings.video_fade = video_fade;
+        self
+    }
+
+    pub fn extra_ffmpeg_args(mut self, extra_ffmpeg_args: Vec<String>) -> Self {
+        self.settings.extra_ffmpeg_args = extra_ffmpeg_args;
+        self
+    }
+
+    pub fn atomic_output(mut self, atomic_output: bool) -> Self {
+        self.settings.atomic_output = atomic_output;
+        self
+    }
+
+    pub fn max_bitrate(mut self, max_bitrate: impl Into<String>) -> Self {
+        self.settings.max_bitrate = Some(max_bitrate.into());
+        self
+    }
+
+    pub fn buffer_size(mut self, buffer_size: impl Into<String>) -> Self {
+        self.settings.buffer_size = Some(buffer_size.into());
+        self
+    }
+
+    pub fn loop_background_audio(mut self, loop_background_audio: bool) -> Self {
+        self.settings.loop_background_audio = loop_background_audio;
+        self
+    }
+
+    pub fn tonemap(mut self, tonemap: bool) -> Self {
+        self.settings.tonemap = tonemap;
+        self
+    }
+
+    pub fn max_resolution(mut self, max_resolution: impl Into<String>) -> Self {
+        self.settings.max_resolution = Some(max_resolution.into());
+        self
+    }
+
+    pub fn separate_audio_tracks(mut self, separate_audio_tracks: bool) -> Self {
+        self.settings.separate_audio_tracks = separate_audio_tracks;
+        self
+    }
+
+    pub fn audio_track_titles(mut self, audio_track_titles: Vec<String>) -> Self {
+        self.settings.audio_track_titles = audio_track_titles;
+        self
+    }
+
+    pub fn audio_track_languages(mut self, audio_track_languages: Vec<String>) -> Self {
+        self.settings.audio_track_languages = audio_track_languages;
+        self
+    }
+
+    pub fn keyframe_interval(mut self, keyframe_interval: KeyframeInterval) -> Self {
+        self.settings.keyframe_interval = Some(keyframe_interval);
+        self
+    }
+
+    pub fn strict_gop(mut self, strict_gop: bool) -> Self {
+        self.settings.strict_gop = strict_gop;
+        self
+    }
+
+    pub fn video_codec(mut self, video_codec: impl Into<String>) -> Self {
+        self.settings.video_codec = Some(video_codec.into());
+        self
+    }
+
+    pub fn fast_seek(mut self, fast_seek: bool) -> Self {
+        self.settings.fast_seek = fast_seek;
+        self
+    }
+
+    pub fn progress_by(mut self, progress_by: impl Into<String>) -> Self {
+        self.settings.progress_by = Some(progress_by.into());
+        self
+    }
+
+    pub fn audio_limiter(mut self, audio_limiter: bool) -> Self {
+        self.settings.audio_limiter = audio_limiter;
+        self
+    }
+
+    pub fn audio_limiter_threshold(mut self, audio_limiter_threshold: f64) -> Self {
+        self.settings.audio_limiter_threshold = Some(audio_limiter_threshold);
+        self
+    }
+
+    pub fn master_volume(mut self, master_volume: impl Into<Volume>) -> Self {
+        self.settings.master_volume = Some(master_volume.into());
+        self
+    }
+
+    pub fn faststart(mut self, faststart: bool) -> Self {
+        self.settings.faststart = Some(faststart);
+        self
+    }
+
+    pub fn audio_only(mut self, audio_only: bool) -> Self {
+        self.settings.audio_only = audio_only;
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.settings.threads = Some(threads);
+        self
+    }
+
+    pub fn pad_to_aspect(mut self, pad_to_aspect: impl Into<String>) -> Self {
+        self.settings.pad_to_aspect = Some(pad_to_aspect.into());
+        self
+    }
+
+    pub fn pad_color(mut self, pad_color: impl Into<String>) -> Self {
+        self.settings.pad_color = Some(pad_color.into());
+        self
+    }
+
+    pub fn fade_in_color(mut self, fade_in_color: impl Into<String>) -> Self {
+        self.settings.fade_in_color = Some(fade_in_color.into());
+        self
+    }
+
+    pub fn fade_out_color(mut self, fade_out_color: impl Into<String>) -> Self {
+        self.settings.fade_out_color = Some(fade_out_color.into());
+        self
+    }
+
+    pub fn background_audio_delay(mut self, background_audio_delay: f64) -> Self {
+        self.settings.background_audio_delay = Some(background_audio_delay);
+        self
+    }
+
+    pub fn waveform_path(mut self, waveform_path: impl Into<String>) -> Self {
+        self.settings.waveform_path = Some(waveform_path.into());
+        self
+    }
+
+    pub fn waveform_size(mut self, waveform_size: impl Into<String>) -> Self {
+        self.settings.waveform_size = Some(waveform_size.into());
+        self
+    }
+
+    pub fn chapters_file(mut self, chapters_file: impl Into<String>) -> Self {
+        self.settings.chapters_file = Some(chapters_file.into());
+        self
+    }
+
+    pub fn blurred_background(mut self, blurred_background: bool) -> Self {
+        self.settings.blurred_background = blurred_background;
+        self
+    }
+
+    pub fn benchmark(mut self, benchmark: bool) -> Self {
+        self.settings.benchmark = benchmark;
+        self
+    }
+
+    pub fn color_effect(mut self, color_effect: impl Into<String>) -> Self {
+        self.settings.color_effect = Some(color_effect.into());
+        self
+    }
+
+    pub fn dump_filtergraph(mut self, dump_filtergraph: impl Into<String>) -> Self {
+        self.settings.dump_filtergraph = Some(dump_filtergraph.into());
+        self
+    }
+
+    pub fn log_progress_interval(mut self, log_progress_interval: f64) -> Self {
+        self.settings.log_progress_interval = Some(log_progress_interval);
+        self
+    }
+
+    pub fn max_parallel_copies(mut self, max_parallel_copies: usize) -> Self {
+        self.settings.max_parallel_copies = Some(max_parallel_copies);
+        self
+    }
+
+    pub fn build(self) -> Settings {
+        self.settings
+    }
+}
+
+/// Settings given on the command line, each one that's set replaces the config file's value.
+#[derive(Debug, Clone, Default)]
+pub struct SettingsOverrides {
+    pub input_video_path: Option<String>,
+    pub output_video_path: Option<String>,
+    pub video_bitrate: Option<String>,
+    pub fade_in_duration: Option<FadeDuration>,
+    pub fade_out_duration: Option<FadeDuration>,
+    pub video_speed: Option<f64>,
+    pub target_fps: Option<f64>,
+    pub use_gpu: Option<bool>,
+    pub max_parallel_jobs: Option<usize>,
+    pub gpu_fallback: Option<bool>,
+    pub print_command: Option<bool>,
+    pub timeout_secs: Option<f64>,
+    pub skip_existing: Option<String>,
+    // Added after the config's own extra_ffmpeg_args instead of replacing them
+    pub extra_ffmpeg_args: Vec<String>,
+    pub atomic_output: Option<bool>,
+    pub progress_by: Option<String>,
+    pub audio_only: Option<bool>,
+    pub benchmark: Option<bool>,
+    pub dump_filtergraph: Option<String>,
+    pub log_progress_interval: Option<f64>,
+    // Not applied by apply_overrides, the paths are already resolved by then. It's passed to the loaders instead
+    pub base_dir: Option<String>,
+}
+
+/// The config format called `name`: toml, json or yaml (or yml).
+pub fn file_format(name: &str) -> Option<FileFormat> {
+    match name.to_lowercase().as_str() {
+        "toml" => Some(FileFormat::Toml),
+        "json" => Some(FileFormat::Json),
+        "yaml" | "yml" => Some(FileFormat::Yaml),
+        _ => None,
+    }
+}
+
+// Goes by the extension, anything unrecognised is read as TOML like before
+fn detect_format(file_path: &str) -> FileFormat {
+    Path::new(file_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(file_format)
+        .unwrap_or(FileFormat::Toml)
+}
+
+// serde skips keys it has no field for, so a misspelled setting would load fine and quietly do nothing. Every key of
+// every section is checked against the fields instead, with the closest one suggested for a typo
+fn check_unknown_keys(loaded: &ConfigLoader) -> Result<(), config::ConfigError> {
+    let Ok(serde_json::Value::Object(settings)) = serde_json::to_value(Settings::default()) else {
+        return Ok(());
+    };
+    let setting_keys: Vec<&str> = settings.keys().map(String::as_str).collect();
+    let raw: serde_json::Value = loaded.clone().try_deserialize()?;
+    let Some(sections) = raw.as_object() else {
+        return Ok(());
+    };
+
+    let mut unknown = Vec::new();
+    let mut check = |table: &serde_json::Value, known: &[&str], section: &str| {
+        for key in table.as_object().into_iter().flat_map(|table| table.keys()) {
+            if !known.contains(&key.as_str()) {
+                let suggestion = closest_key(key, known).map(|close| format!(", did you mean {}?", close)).unwrap_or_default();
+                unknown.push(format!("Unknown key {} in {}{}", key, section, suggestion));
+            }
+        }
+    };
+    check(&raw, &["settings", "batch", "output"], "the config");
+    if let Some(table) = sections.get("settings") {
+        check(table, &setting_keys, "[settings]");
+    }
+    for (section, known) in [("batch", &["input", "output"][..]), ("output", &["path", "resolution", "video_bitrate"][..])] {
+        for (index, table) in sections.get(section).and_then(|value| value.as_array()).into_iter().flatten().enumerate() {
+            check(table, known, &format!("[[{}]] #{}", section, index + 1));
+        }
+    }
+
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    Err(config::ConfigError::Message(unknown.join("\n  ")))
+}
+
+// The known key a typo most likely meant, if any is close enough to be worth suggesting
+fn closest_key<'a>(key: &str, known: &[&'a str]) -> Option<&'a str> {
+    let limit = (key.chars().count() / 3).max(2);
+    known
+        .iter()
+        .map(|candidate| (edit_distance(&key.to_lowercase(), candidate), *candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+// Levenshtein distance, the number of single character edits that turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+impl Config {
+    pub fn from_file(file_path: &str) -> Result<Self, config::ConfigError> {
+        Self::from_files(&[file_path])
+    }
+
+    // Later files override the keys of earlier ones, relative paths are resolved against the
+    // directory of the last file
+    pub fn from_files<S: AsRef<str>>(file_paths: &[S]) -> Result<Self, config::ConfigError> {
+        Self::from_files_with_format(file_paths, None, None)
+    }
+
+    // Like `from_files`, but `format` ("toml", "json" or "yaml") forces the format of every file
+    // instead of going by their extensions, and `base_dir` (relative to the working directory)
+    // replaces the config's own
+    pub fn from_files_with_format<S: AsRef<str>>(
+        file_paths: &[S],
+        format: Option<&str>,
+        base_dir: Option<&str>,
+    ) -> Result<Self, config::ConfigError> {
+        let forced = match format {
+            Some(name) => Some(file_format(name).ok_or_else(|| config::ConfigError::Message(format!("Unknown config format: {}", name)))?),
+            None => None,
+        };
+        let mut builder = ConfigLoader::builder();
+        for file_path in file_paths {
+            let file_path = file_path.as_ref();
+            let format = forced.unwrap_or_else(|| detect_format(file_path));
+            builder = builder.add_source(File::new(file_path, format));
+        }
+        let loaded = builder.build()?;
+        check_unknown_keys(&loaded)?;
+        let mut config: Config = loaded.try_deserialize()?;
+
+        let config_dir = file_paths
+            .last()
+            .and_then(|file_path| Path::new(file_path.as_ref()).parent())
+            .unwrap_or(Path::new(""));
+        config.normalize_paths(config_dir, base_dir)?;
+
+        Ok(config)
+    }
+
+    /// Reads a whole config in `format` from `reader`, e.g. stdin. Relative paths are resolved against `base_dir`,
+    /// or else the working directory since there's no config file to be relative to.
+    pub fn from_reader<R: Read>(mut reader: R, format: FileFormat, base_dir: Option<&str>) -> Result<Self, config::ConfigError> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text).map_err(|e| config::ConfigError::Foreign(Box::new(e)))?;
+        let loaded = ConfigLoader::builder().add_source(File::from_str(&text, format)).build()?;
+        check_unknown_keys(&loaded)?;
+        let mut config: Config = loaded.try_deserialize()?;
+        config.normalize_paths(Path::new(""), base_dir)?;
+        Ok(config)
+    }
+
+    /// Applies the command line overrides on top of the loaded settings. Paths given on the command
+    /// line are taken as they are, relative to the working directory rather than the config file.
+    pub fn apply_overrides(&mut self, overrides: &SettingsOverrides) {
+        let settings = &mut self.settings;
+        if let Some(ref input_video_path) = overrides.input_video_path {
+            settings.input_video_path = input_video_path.clone();
+        }
+        if let Some(ref output_video_path) = overrides.output_video_path {
+            settings.output_video_path = output_video_path.clone();
+        }
+        if let Some(ref video_bitrate) = overrides.video_bitrate {
+            settings.video_bitrate = video_bitrate.clone();
+        }
+        if let Some(fade_in_duration) = overrides.fade_in_duration {
+            settings.fade_in_duration = Some(fade_in_duration);
+        }
+        if let Some(fade_out_duration) = overrides.fade_out_duration {
+            settings.fade_out_duration = Some(fade_out_duration);
+        }
+        if let Some(video_speed) = overrides.video_speed {
+            settings.video_speed = video_speed;
+        }
+        if let Some(target_fps) = overrides.target_fps {
+            settings.target_fps = Some(target_fps);
+        }
+        if let Some(use_gpu) = overrides.use_gpu {
+            settings.use_gpu = use_gpu;
+        }
+        if let Some(max_parallel_jobs) = overrides.max_parallel_jobs {
+            settings.max_parallel_jobs = Some(max_parallel_jobs);
+        }
+        if let Some(gpu_fallback) = overrides.gpu_fallback {
+            settings.gpu_fallback = gpu_fallback;
+        }
+        if let Some(print_command) = overrides.print_command {
+            settings.print_command = print_command;
+        }
+        if let Some(timeout_secs) = overrides.timeout_secs {
+            settings.timeout_secs = Some(timeout_secs);
+        }
+        if let Some(ref skip_existing) = overrides.skip_existing {
+            settings.skip_existing = Some(skip_existing.clone());
+        }
+        settings.extra_ffmpeg_args.extend(overrides.extra_ffmpeg_args.iter().cloned());
+        if let Some(atomic_output) = overrides.atomic_output {
+            settings.atomic_output = atomic_output;
+        }
+        if let Some(ref progress_by) = overrides.progress_by {
+            settings.progress_by = Some(progress_by.clone());
+        }
+        if let Some(audio_only) = overrides.audio_only {
+            settings.audio_only = audio_only;
+        }
+        if let Some(benchmark) = overrides.benchmark {
+            settings.benchmark = benchmark;
+        }
+        if let Some(ref dump_filtergraph) = overrides.dump_filtergraph {
+            settings.dump_filtergraph = Some(dump_filtergraph.clone());
+        }
+        if let Some(interval) = overrides.log_progress_interval {
+            settings.log_progress_interval = Some(interval);
+        }
+    }
+
+    // Expands `~` and environment variables, then takes relative paths relative to `base_dir` (itself
+    // relative to the config file) or the config file's directory, so the config works no matter
+    // where the binary is run from. A variable that isn't set is an error naming the setting it's in.
+    // `base_dir_override` comes from the command line and replaces the config's `base_dir`
+    fn normalize_paths(&mut self, config_dir: &Path, base_dir_override: Option<&str>) -> Result<(), config::ConfigError> {
+        if let Some(dir) = base_dir_override {
+            self.settings.base_dir = Some(dir.to_string());
+        }
+        let Config { settings, batch, outputs } = self;
+        let mut paths: Vec<(String, &mut String)> = vec![
+            ("input_video_path".to_string(), &mut settings.input_video_path),
+            ("output_video_path".to_string(), &mut settings.output_video_path),
+            ("ffmpeg_path".to_string(), &mut settings.ffmpeg_path),
+        ];
+        for (name, path) in [
+            ("progress_file", &mut settings.progress_file),
+            ("base_dir", &mut settings.base_dir),
+            ("contact_sheet_path", &mut settings.contact_sheet_path),
+            ("intro_path", &mut settings.intro_path),
+            ("outro_path", &mut settings.outro_path),
+            ("second_input_video_path", &mut settings.second_input_video_path),
+            ("subtitle_path", &mut settings.subtitle_path),
+            ("watermark_path", &mut settings.watermark_path),
+            ("thumbnail_path", &mut settings.thumbnail_path),
+            ("waveform_path", &mut settings.waveform_path),
+            ("chapters_file", &mut settings.chapters_file),
+            ("dump_filtergraph", &mut settings.dump_filtergraph),
+        ] {
+            paths.extend(path.as_mut().map(|path| (name.to_string(), path)));
+        }
+        for (index, path) in settings.background_audio_path.iter_mut().flatten().enumerate() {
+            paths.push((format!("background_audio_path[{}]", index), path));
+        }
+        for (index, path) in settings.concat_inputs.iter_mut().enumerate() {
+            paths.push((format!("concat_inputs[{}]", index), path));
+        }
+        for (index, job) in batch.iter_mut().enumerate() {
+            paths.push((format!("batch[{}].input", index), &mut job.input));
+            paths.push((format!("batch[{}].output", index), &mut job.output));
+        }
+        for (index, rendition) in outputs.iter_mut().enumerate() {
+            paths.push((format!("output[{}].path", index), &mut rendition.path));
+        }
+        for (name, path) in paths {
+            *path = expand_path(path).map_err(|variable| {
+                config::ConfigError::Message(format!("{} uses ${}, which isn't set: {}", name, variable, path))
+            })?;
+        }
+
+        // Like every other path on the command line, --base-dir is relative to the working directory
+        let base_dir = match self.settings.base_dir {
+            Some(ref dir) if base_dir_override.is_some() => PathBuf::from(dir),
+            Some(ref dir) => config_dir.join(dir),
+            None => config_dir.to_path_buf(),
+        };
+
+        for job in &mut self.batch {
+            for path in [&mut job.input, &mut job.output] {
+                resolve_path(path, &base_dir);
+            }
+        }
+        for rendition in &mut self.outputs {
+            resolve_path(&mut rendition.path, &base_dir);
+        }
+
+        let settings = &mut self.settings;
+        resolve_path(&mut settings.input_video_path, &base_dir);
+        resolve_path(&mut settings.output_video_path, &base_dir);
+
+        // A bare executable name is looked up on PATH, so only resolve it when it's an actual path
+        if Path::new(&settings.ffmpeg_path).components().count() > 1 {
+            resolve_path(&mut settings.ffmpeg_path, &base_dir);
+        }
+
+        for path in [
+            &mut settings.progress_file,
+            &mut settings.contact_sheet_path,
+            &mut settings.intro_path,
+            &mut settings.outro_path,
+            &mut settings.second_input_video_path,
+            &mut settings.subtitle_path,
+            &mut settings.watermark_path,
+            &mut settings.thumbnail_path,
+            &mut settings.waveform_path,
+            &mut settings.chapters_file,
+            &mut settings.dump_filtergraph,
+        ]
+            .into_iter()
+            .flatten()
+            .chain(settings.background_audio_path.iter_mut().flatten())
+            .chain(settings.concat_inputs.iter_mut())
+        {
+            if path.to_lowercase() != "none" {
+                resolve_path(path, &base_dir);
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the settings make sense and work together, the error lists every problem found.
+    pub fn validate(&self) -> Result<(), ProcessingError> {
+        let problems = self.problems();
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            // One problem per line, so a config with several is readable in the log
+            Err(ProcessingError::ConfigInvalid(problems.join("\n  ")))
+        }
+    }
+
+    /// Everything wrong with the settings, empty when they're fine.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let required_keys = [
+            ("input_video_path", &self.settings.input_video_path),
+            ("output_video_path", &self.settings.output_video_path),
+            ("ffmpeg_path", &self.settings.ffmpeg_path),
+            ("video_bitrate", &self.settings.video_bitrate),
+        ];
+
+        for (key, value) in required_keys {
+            if value.is_empty() {
+                problems.push(format!("Missing required config key: {}", key));
+            }
+        }
+
+        let settings = &self.settings;
+
+        if settings.output_video_path == "-" && settings.output_format.is_none() {
+            problems.push("output_format is required when writing to stdout (e.g. \"matroska\" or \"mpegts\")".to_string());
+        }
+
+        let codec = settings.software_codec();
+        // An audio_only output is an audio file, mp3 or wav included, and picks its codec by the container itself
+        if let Some(ref container) = settings.container().filter(|_| !settings.audio_only) {
+            if container == "webm" && codec.is_web() {
+                // WebM is made for VP9 and AV1, but only takes Opus or Vorbis next to them
+                if let Some(ref audio_codec) = settings.audio_codec {
+                    if !["libopus", "opus", "libvorbis", "vorbis"].contains(&audio_codec.to_lowercase().as_str()) {
+                        problems.push(format!("webm only holds Opus or Vorbis audio, got audio_codec {}", audio_codec));
+                    }
+                }
+            } else if let Some((_, suggestion)) = INCOMPATIBLE_CONTAINERS.iter().find(|(name, _)| name == container) {
+                problems.push(format!(
+                    "{} can't hold the {} video and AAC audio clippy encodes, use {} instead",
+                    container,
+                    codec.probe_name().to_uppercase(),
+                    suggestion
+                ));
+            } else if settings.output_format.is_some() && !OUTPUT_FORMATS.contains(&container.as_str()) {
+                match FORMAT_ALIASES.iter().find(|(alias, _)| alias == container) {
+                    Some((_, format)) => problems.push(format!("output_format {} is called {} in ffmpeg", container, format)),
+                    None => problems.push(format!(
+                        "Unknown output_format {}, expected one of: {}",
+                        container,
+                        OUTPUT_FORMATS.join(", ")
+                    )),
+                }
+            }
+        }
+
+        if settings.video_speed <= 0.0 {
+            problems.push(format!("video_speed must be greater than 0, got {}", settings.video_speed));
+        }
+        // Decibels can be negative, that's just quieter
+        let negative = |volume: &Volume| matches!(*volume, Volume::Factor(factor) if factor < 0.0);
+        if negative(&settings.original_audio_volume) {
+            problems.push(format!("original_audio_volume can't be negative, got {}", settings.original_audio_volume));
+        }
+        for volume in settings.background_audio_volume.iter().filter(|volume| negative(volume)) {
+            problems.push(format!("background_audio_volume can't be negative, got {}", volume));
+        }
+        if let Some(volume) = settings.master_volume.filter(negative) {
+            problems.push(format!("master_volume can't be negative, got {}", volume));
+        }
+        let background_tracks = settings.background_audio_files().len();
+        let volumes = settings.background_audio_volume.len();
+        if volumes != 1 && volumes != background_tracks {
+            problems.push(format!(
+                "background_audio_volume needs a single volume or one for each of the {} background tracks, got {}",
+                background_tracks, volumes
+            ));
+        }
+        if settings.separate_audio_tracks && background_tracks == 0 {
+            problems.push("separate_audio_tracks needs a background_audio_path to put on its own track".to_string());
+        }
+        // Players show the language from the ISO 639-2 code, anything else is shown as it is or not at all
+        let language_re = Regex::new(r"^[a-z]{3}$").unwrap();
+        for language in settings.audio_track_languages.iter().filter(|language| !language_re.is_match(language)) {
+            problems.push(format!("audio_track_languages must be three letter codes like eng or deu, got {}", language));
+        }
+        if settings.audio_start_time < 0.0 {
+            problems.push(format!("audio_start_time can't be negative, got {}", settings.audio_start_time));
+        }
+        if let Some(delay) = settings.background_audio_delay.filter(|delay| *delay < 0.0) {
+            problems.push(format!("background_audio_delay can't be negative, got {}", delay));
+        }
+
+        for (name, duration) in [
+            ("fade_in_duration", settings.fade_in_duration),
+            ("fade_out_duration", settings.fade_out_duration),
+            ("audio_fade_in_duration", settings.audio_fade_in_duration),
+            ("audio_fade_out_duration", settings.audio_fade_out_duration),
+        ] {
+            match duration {
+                Some(FadeDuration::Seconds(seconds)) if seconds < 0.0 => {
+                    problems.push(format!("{} can't be negative, got {}", name, seconds));
+                }
+                Some(FadeDuration::Percent(percent)) if !(0.0..=100.0).contains(&percent) => {
+                    problems.push(format!("{} must be between 0% and 100%, got {}%", name, percent));
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(ref crf) = settings.crf {
+            if crf.to_lowercase() != "none" {
+                let max_crf = settings.software_codec().max_crf();
+                match crf.parse::<u32>() {
+                    Ok(value) if value <= max_crf => {}
+                    _ => problems.push(format!("crf must be a whole number between 0 and {}, got {}", max_crf, crf)),
+                }
+            }
+        }
+
+        if let Some(delay) = settings.retry_delay_secs {
+            if delay < 0.0 {
+                problems.push(format!("retry_delay_secs can't be negative, got {}", delay));
+            }
+        }
+        if let Some(timeout) = settings.timeout_secs {
+            if timeout <= 0.0 {
+                problems.push(format!("timeout_secs must be greater than 0, got {}", timeout));
+            }
+        }
+        if let Some(interval) = settings.log_progress_interval {
+            if interval <= 0.0 {
+                problems.push(format!("log_progress_interval must be greater than 0, got {}", interval));
+            }
+        }
+
+        // video_bitrate is only optional when crf is actually in control of the quality
+        let crf_in_use = settings.crf.as_ref().is_some_and(|crf| crf.to_lowercase() != "none");
+        if settings.video_bitrate.to_lowercase() == "none" && !crf_in_use {
+            problems.push("video_bitrate can only be \"None\" when a crf is set".to_string());
+        }
+
+        let bitrate_re = Regex::new(r"^\d+(\.\d+)?[kM]?$").unwrap();
+        // An empty one is already reported as missing
+        let bitrate_given = !settings.video_bitrate.is_empty() && settings.video_bitrate.to_lowercase() != "none";
+        if bitrate_given && !bitrate_re.is_match(&settings.video_bitrate) {
+            problems.push(format!(
+                "video_bitrate must be a number with an optional k or M suffix (e.g. 8000k or 25M), got {}",
+                settings.video_bitrate
+            ));
+        }
+
+        let rate_re = Regex::new(r"^\d+[kM]$").unwrap();
+        for (name, value) in [("max_bitrate", &settings.max_bitrate), ("buffer_size", &settings.buffer_size)] {
+            if let Some(value) = value.as_ref().filter(|value| !rate_re.is_match(value)) {
+                problems.push(format!(
+                    "{} must be a whole number with a k or M suffix (e.g. 8000k or 25M), got {}",
+                    name, value
+                ));
+            }
+        }
+
+        if let Some(ref resolution) = settings.upscale_resolution {
+            let resolution_re = Regex::new(r"^\d+x\d+$").unwrap();
+            let is_preset = RESOLUTION_PRESETS.contains(&resolution.to_lowercase().as_str());
+            if resolution.to_lowercase() != "none" && !resolution_re.is_match(resolution) && !is_preset {
+                problems.push(format!(
+                    "upscale_resolution must look like 1920x1080 or be one of {}, got {}",
+                    RESOLUTION_PRESETS.join(", "),
+                    resolution
+                ));
+            }
+        }
+
+        if let Some(ref resolution) = settings.max_resolution {
+            let resolution_re = Regex::new(r"^\d+x\d+$").unwrap();
+            if resolution.to_lowercase() != "none" && !resolution_re.is_match(resolution) {
+                problems.push(format!("max_resolution must look like 1920x1080, got {}", resolution));
+            }
+        }
+        // One scales to an exact size and the other caps it, together one of them would quietly do nothing
+        let is_on = |resolution: &Option<String>| resolution.as_ref().is_some_and(|resolution| resolution.to_lowercase() != "none");
+        if is_on(&settings.upscale_resolution) && is_on(&settings.max_resolution) {
+            problems.push("upscale_resolution and max_resolution can't both be set, pick the exact size or the cap".to_string());
+        }
+        if let Some(ref aspect) = settings.pad_to_aspect {
+            if parse_aspect_ratio(aspect).is_none() {
+                problems.push(format!("pad_to_aspect must be a ratio like 16:9 or 2.39, got {}", aspect));
+            }
+        } else if settings.pad_color.is_some() {
+            problems.push("pad_color is the color of pad_to_aspect's bars, which isn't set".to_string());
+        } else if settings.blurred_background {
+            problems.push("blurred_background fills pad_to_aspect's bars, which isn't set".to_string());
+        }
+        if settings.blurred_background && settings.pad_color.is_some() {
+            problems.push("pad_color and blurred_background both fill the bars, pick one".to_string());
+        }
+
+        // The renditions share the main encode's settings, so only what they change is checked here
+        let resolution_re = Regex::new(r"^\d+x\d+$").unwrap();
+        let crf_set = settings.crf.as_ref().is_some_and(|crf| crf.to_lowercase() != "none");
+        for (index, rendition) in self.outputs.iter().enumerate() {
+            if rendition.path.is_empty() {
+                problems.push(format!("output[{}] needs a path", index));
+            } else if rendition.path == settings.output_video_path || rendition.path == "-" {
+                problems.push(format!("output[{}] has to be written to a file of its own, got {}", index, rendition.path));
+            }
+            if let Some(ref resolution) = rendition.resolution {
+                let is_preset = RESOLUTION_PRESETS.contains(&resolution.to_lowercase().as_str());
+                if !resolution_re.is_match(resolution) && !is_preset {
+                    problems.push(format!(
+                        "output[{}].resolution must look like 1280x720 or be one of {}, got {}",
+                        index,
+                        RESOLUTION_PRESETS.join(", "),
+                        resolution
+                    ));
+                }
+            }
+            if let Some(ref bitrate) = rendition.video_bitrate {
+                if !rate_re.is_match(bitrate) {
+                    problems.push(format!(
+                        "output[{}].video_bitrate must be a whole number with a k or M suffix (e.g. 4000k), got {}",
+                        index, bitrate
+                    ));
+                } else if crf_set {
+                    problems.push(format!("output[{}].video_bitrate can't be used while a crf controls the quality", index));
+                }
+            }
+        }
+        if !self.outputs.is_empty() {
+            if !self.batch.is_empty() {
+                problems.push("[[output]] sections name fixed files, they can't be combined with [[batch]]".to_string());
+            }
+            if settings.two_pass {
+                problems.push("[[output]] sections can't be combined with two_pass".to_string());
+            }
+        }
+
+        // Only the audio is written, so there has to be some and nothing can be asked of the video that needs a pass
+        // or a file of its own
+        if settings.audio_only {
+            if settings.audio_mode.as_ref().is_some_and(|mode| mode.to_lowercase() == "none") {
+                problems.push("audio_only writes just the audio, which audio_mode none drops".to_string());
+            }
+            for (conflicts, name) in [
+                (settings.two_pass, "two_pass"),
+                (settings.stabilize, "stabilize"),
+                (settings.comparison, "comparison"),
+                (!self.outputs.is_empty(), "[[output]] sections"),
+            ] {
+                if conflicts {
+                    problems.push(format!("audio_only leaves the video out, it can't be combined with {}", name));
+                }
+            }
+        }
+
+        match settings.keyframe_interval {
+            Some(KeyframeInterval::Frames(0)) => problems.push("keyframe_interval must be at least 1 frame".to_string()),
+            Some(KeyframeInterval::Seconds(seconds)) if seconds <= 0.0 => {
+                problems.push(format!("keyframe_interval must be longer than 0 seconds, got {}s", seconds))
+            }
+            None if settings.strict_gop => problems.push("strict_gop needs a keyframe_interval to hold to".to_string()),
+            _ => {}
+        }
+
+        if let Some(ref mode) = settings.deinterlace {
+            if !["none", "auto", "yadif", "bwdif"].contains(&mode.to_lowercase().as_str()) {
+                problems.push(format!("deinterlace must be one of none, auto, yadif or bwdif, got {}", mode));
+            }
+        }
+
+        if let Some(ref strength) = settings.denoise {
+            if !["none", "light", "medium", "strong"].contains(&strength.to_lowercase().as_str()) {
+                problems.push(format!("denoise must be one of none, light, medium or strong, got {}", strength));
+            }
+        }
+        if let Some(ref method) = settings.denoise_method {
+            if !["hqdn3d", "nlmeans"].contains(&method.to_lowercase().as_str()) {
+                problems.push(format!("denoise_method must be hqdn3d or nlmeans, got {}", method));
+            }
+        }
+
+        if let Some(amount) = settings.sharpen {
+            if !(0.0..=1.5).contains(&amount) {
+                problems.push(format!("sharpen must be between 0.0 and 1.5, got {}", amount));
+            }
+        }
+
+        if let Some(ref segments) = settings.speed_segments {
+            if settings.video_speed != 1.0 {
+                problems.push("video_speed has to be 1.0 when speed_segments are used".to_string());
+            }
+            if settings.stabilize {
+                problems.push("stabilize can't be combined with speed_segments".to_string());
+            }
+
+            let mut segments: Vec<&SpeedSegment> = segments.iter().collect();
+            segments.sort_by(|a, b| a.start.total_cmp(&b.start));
+            for segment in &segments {
+                if segment.start < 0.0 || segment.end <= segment.start {
+                    problems.push(format!(
+                        "speed segment {}-{} must start at 0 or later and end after it starts",
+                        segment.start, segment.end
+                    ));
+                }
+                if segment.factor <= 0.0 {
+                    problems.push(format!("speed segment {}-{} needs a factor greater than 0", segment.start, segment.end));
+                }
+            }
+            for pair in segments.windows(2) {
+                if pair[1].start < pair[0].end {
+                    problems.push(format!(
+                        "speed segments {}-{} and {}-{} overlap",
+                        pair[0].start, pair[0].end, pair[1].start, pair[1].end
+                    ));
+                }
+            }
+        }
+
+        // Nothing would be left to encode. Times that don't parse fail on their own when processing
+        let clip_time = |time: &Option<String>| {
+            time.as_deref().filter(|time| time.to_lowercase() != "none").and_then(|time| parse_time(time).ok())
+        };
+        if let (Some(start), Some(end)) = (clip_time(&settings.clip_start_time), clip_time(&settings.clip_end_time)) {
+            if start >= end {
+                problems.push(format!("clip_start_time ({}s) has to be before clip_end_time ({}s)", start, end));
+            }
+        }
+
+        let ranges = settings.clip_ranges_seconds().unwrap_or_else(|e| {
+            problems.push(e);
+            None
+        });
+        if let Some(ranges) = ranges {
+            let is_set = |time: &Option<String>| time.as_ref().is_some_and(|time| time.to_lowercase() != "none");
+            if is_set(&settings.clip_start_time) || is_set(&settings.clip_end_time) {
+                problems.push("clip_ranges replace clip_start_time and clip_end_time, set only one of them".to_string());
+            }
+            if settings.speed_segments.is_some() || settings.stabilize {
+                problems.push("clip_ranges can't be combined with speed_segments or stabilize".to_string());
+            }
+            if ranges.is_empty() {
+                problems.push("clip_ranges needs at least one range".to_string());
+            }
+            for (start, end) in &ranges {
+                if *start < 0.0 || end <= start {
+                    problems.push(format!("clip range {}-{} must start at 0 or later and end after it starts", start, end));
+                }
+            }
+            for pair in ranges.windows(2) {
+                if pair[1].0 < pair[0].1 {
+                    problems.push(format!(
+                        "clip ranges have to be listed in order without overlapping, {}-{} starts before {}-{} ends",
+                        pair[1].0, pair[1].1, pair[0].0, pair[0].1
+                    ));
+                }
+            }
+        }
+
+        // The colors go into the filter graph as they are, where a typo would only show up as ffmpeg failing
+        let mut colors = vec![
+            ("fade_in_color".to_string(), &settings.fade_in_color),
+            ("fade_out_color".to_string(), &settings.fade_out_color),
+            ("pad_color".to_string(), &settings.pad_color),
+        ];
+        for (index, fade) in settings.fades.iter().flatten().enumerate() {
+            colors.push((format!("fades[{}].color", index), &fade.color));
+        }
+        for (key, color) in colors {
+            if let Some(color) = color.as_ref().filter(|color| !is_color(color)) {
+                problems.push(format!("{} must be an ffmpeg color name like white or a hex color like #FF8800, got {}", key, color));
+            }
+        }
+        if settings.fades.is_some() && (settings.fade_in_color.is_some() || settings.fade_out_color.is_some()) {
+            problems.push("fade_in_color and fade_out_color don't apply to fades, give each fade its own color".to_string());
+        }
+
+        if let Some(ref fades) = settings.fades {
+            for fade in fades {
+                if fade.start < 0.0 || fade.duration <= 0.0 {
+                    problems.push(format!(
+                        "fade at {} must start at 0 or later and last longer than 0 seconds, got a duration of {}",
+                        fade.start, fade.duration
+                    ));
+                }
+            }
+            for pair in fades.windows(2) {
+                if pair[1].start < pair[0].start + pair[0].duration {
+                    problems.push(format!(
+                        "fades have to be listed in order without overlapping, the fade at {} starts before the one at {} ends",
+                        pair[1].start, pair[0].start
+                    ));
+                }
+            }
+        }
+
+        for (name, count) in [
+            ("contact_sheet_columns", settings.contact_sheet_columns),
+            ("contact_sheet_rows", settings.contact_sheet_rows),
+        ] {
+            if count == Some(0) {
+                problems.push(format!("{} must be at least 1", name));
+            }
+        }
+
+        if let Some(duration) = settings.preview_duration {
+            if duration <= 0.0 {
+                problems.push(format!("preview_duration must be greater than 0, got {}", duration));
+            }
+        }
+
+        if let Some(ref color) = settings.log_color {
+            if !["auto", "always", "never"].contains(&color.to_lowercase().as_str()) {
+                problems.push(format!("log_color must be one of auto, always or never, got {}", color));
+            }
+        }
+
+        if let Some(angle) = settings.vignette_angle {
+            if !(angle > 0.0 && angle <= std::f64::consts::FRAC_PI_2) {
+                problems.push(format!("vignette_angle must be above 0 and at most 1.57 (pi/2) radians, got {}", angle));
+            }
+        }
+
+        if let Some(strength) = settings.film_grain {
+            if !(0.0..=100.0).contains(&strength) {
+                problems.push(format!("film_grain must be between 0 and 100, got {}", strength));
+            }
+        }
+
+        // Anything else with options is taken as a filter of its own, a bare word is more likely a misspelled effect
+        if let Some(ref effect) = settings.color_effect {
+            if !["grayscale", "sepia", "none"].contains(&effect.to_lowercase().as_str()) && !effect.contains('=') {
+                problems.push(format!(
+                    "color_effect must be grayscale, sepia, none or an ffmpeg filter like curves=preset=vintage, got {}",
+                    effect
+                ));
+            }
+        }
+
+        let has_bumper = [&settings.intro_path, &settings.outro_path]
+            .into_iter()
+            .flatten()
+            .any(|path| path.to_lowercase() != "none")
+            || !settings.concat_inputs.is_empty();
+        if has_bumper && settings.stabilize {
+            problems.push("stabilize can't be combined with intro_path, outro_path or concat_inputs".to_string());
+        }
+
+        let has_second_input = settings.second_input_video_path.as_ref().is_some_and(|path| path.to_lowercase() != "none");
+        match (has_second_input, settings.crossfade_duration) {
+            (true, None) => problems.push("second_input_video_path needs a crossfade_duration".to_string()),
+            (false, Some(_)) => problems.push("crossfade_duration needs a second_input_video_path".to_string()),
+            (true, Some(duration)) if duration <= 0.0 => {
+                problems.push(format!("crossfade_duration must be greater than 0, got {}", duration));
+            }
+            _ => {}
+        }
+        if has_second_input && (has_bumper || settings.stabilize) {
+            problems.push(
+                "second_input_video_path can't be combined with intro_path, outro_path, concat_inputs or stabilize".to_string(),
+            );
+        }
+
+        if let Some(hz) = settings.progress_refresh_hz {
+            if !(1..=60).contains(&hz) {
+                problems.push(format!("progress_refresh_hz must be between 1 and 60, got {}", hz));
+            }
+        }
+        if let Some(ref by) = settings.progress_by {
+            if !["time", "frames"].contains(&by.to_lowercase().as_str()) {
+                problems.push(format!("progress_by must be time or frames, got {}", by));
+            }
+        }
+
+        if let Some(ref range) = settings.color_range {
+            if !["tv", "pc"].contains(&range.to_lowercase().as_str()) {
+                problems.push(format!("color_range must be tv (limited) or pc (full), got {}", range));
+            }
+        }
+        if let Some(ref pixel_format) = settings.pixel_format {
+            if !PIXEL_FORMATS.contains(&pixel_format.to_lowercase().as_str()) {
+                problems.push(format!("pixel_format must be one of {}, got {}", PIXEL_FORMATS.join(", "), pixel_format));
+            }
+        }
+
+        if settings.max_parallel_jobs == Some(0) {
+            problems.push("max_parallel_jobs must be at least 1".to_string());
+        }
+        if settings.max_parallel_copies == Some(0) {
+            problems.push("max_parallel_copies must be at least 1".to_string());
+        }
+        if settings.threads == Some(0) {
+            problems.push("threads must be at least 1, leave it out to let the encoder use every core".to_string());
+        }
+
+        for (key, curve) in [
+            ("fade_curve", &settings.fade_curve),
+            ("fade_in_curve", &settings.fade_in_curve),
+            ("fade_out_curve", &settings.fade_out_curve),
+        ] {
+            if let Some(curve) = curve {
+                if !FADE_CURVES.contains(&curve.as_str()) {
+                    problems.push(format!("Unknown {} {:?}, expected one of: {}", key, curve, FADE_CURVES.join(", ")));
+                }
+            }
+        }
+
+        if let Some(ref position) = settings.watermark_position {
+            if !WATERMARK_POSITIONS.contains(&position.to_lowercase().as_str()) {
+                problems.push(format!("watermark_position must be one of {}, got {}", WATERMARK_POSITIONS.join(", "), position));
+            }
+        }
+        if let Some(opacity) = settings.watermark_opacity {
+            if !(0.0..=1.0).contains(&opacity) {
+                problems.push(format!("watermark_opacity must be between 0.0 and 1.0, got {}", opacity));
+            }
+        }
+
+        if let Some(ref encoder) = settings.gpu_encoder {
+            if !["nvenc", "amf", "qsv", "none"].contains(&encoder.to_lowercase().as_str()) {
+                problems.push(format!("gpu_encoder must be one of nvenc, amf, qsv or none, got {}", encoder));
+            }
+        }
+
+        if let Some(ref codec) = settings.video_codec {
+            if VideoCodec::from_name(codec).is_none() {
+                problems.push(format!(
+                    "video_codec must be one of {}, got {}",
+                    VideoCodec::NAMES.join(", "),
+                    codec
+                ));
+            }
+            if settings.gpu_encoder.as_ref().is_some_and(|encoder| encoder.to_lowercase() != "none") {
+                problems.push("gpu_encoder and video_codec both pick the encoder, set only one of them".to_string());
+            }
+        }
+
+        // The presets and tunes differ between encoders, e.g. libx265's slow means nothing to hevc_nvenc
+        let encoder = settings.hardware_encoder();
+        let codec = settings.software_codec();
+        let encoder_name = encoder.map_or(codec.ffmpeg_name(), GpuEncoder::ffmpeg_name);
+        if let Some(ref preset) = settings.encoder_preset {
+            let (_, presets) = preset_option(encoder, codec);
+            if !presets.contains(&preset.to_lowercase().as_str()) {
+                problems.push(format!(
+                    "encoder_preset {} isn't a {} preset, it takes one of {}",
+                    preset,
+                    encoder_name,
+                    presets.join(", ")
+                ));
+            }
+        }
+        if let Some(ref tune) = settings.encoder_tune {
+            match tune_option(encoder, codec) {
+                Some((_, tunes)) if !tunes.contains(&tune.to_lowercase().as_str()) => problems.push(format!(
+                    "encoder_tune {} isn't a {} tune, it takes one of {}",
+                    tune,
+                    encoder_name,
+                    tunes.join(", ")
+                )),
+                Some(_) => {}
+                None => problems.push(format!("{} has no tune option, leave encoder_tune out", encoder_name)),
+            }
+        }
+
+        if settings.two_pass {
+            if settings.crf.as_ref().is_some_and(|crf| crf.to_lowercase() != "none") {
+                problems.push("two_pass targets video_bitrate, it can't be combined with a crf".to_string());
+            }
+            if settings.hardware_encoder().is_some() {
+                problems.push("two_pass only works with libx265, the GPU encoders don't have a two-pass mode".to_string());
+            } else if settings.software_codec() != VideoCodec::X265 {
+                problems.push(format!("two_pass only works with libx265, not {}", settings.software_codec().ffmpeg_name()));
+            }
+            if settings.input_video_path == "-" {
+                problems.push("two_pass reads the input twice, which isn't possible when reading from stdin".to_string());
+            }
+        }
+
+        // A pipe can't be probed, so the fade-out has no end to count back from unless it's given
+        let fades_out = settings.fades.is_none() && !settings.fade_out_duration.is_some_and(|fade| fade.is_off());
+        let has_end = settings.clip_end_time.as_ref().is_some_and(|time| time.to_lowercase() != "none");
+        if settings.input_video_path == "-" && fades_out && !has_end && settings.clip_ranges.is_none() {
+            problems.push(
+                "The length of a stdin stream can't be probed, set clip_end_time for the fade-out (or fade_out_duration = \
+                 \"none\" to go without one)"
+                    .to_string(),
+            );
+        }
+
+        // Copying the audio means it never goes through a filter, so nothing that changes it can be set
+        match settings.audio_mode.as_ref().map(|mode| mode.to_lowercase()).as_deref() {
+            None | Some("reencode") => {}
+            Some("copy") => {
+                let has_background_audio = background_tracks > 0;
+                let has_audio_fades = settings.audio_fade_in_duration.is_some() || settings.audio_fade_out_duration.is_some();
+                let retimed = settings.speed_segments.is_some() || settings.clip_ranges.is_some();
+                for (conflicts, reason) in [
+                    (has_background_audio, "background_audio_path mixes in other audio"),
+                    (has_audio_fades, "audio_fade_in_duration/audio_fade_out_duration fade it"),
+                    (!settings.original_audio_volume.is_unchanged(), "original_audio_volume changes its volume"),
+                    (settings.master_volume.is_some_and(|volume| !volume.is_unchanged()), "master_volume changes its volume"),
+                    (settings.audio_limiter, "audio_limiter limits its peaks"),
+                    (settings.loudnorm, "loudnorm normalizes its loudness"),
+                    (settings.video_speed != 1.0, "video_speed changes its speed"),
+                    (retimed, "speed_segments and clip_ranges cut and retime it"),
+                    (
+                        has_bumper || has_second_input,
+                        "intro_path, outro_path, concat_inputs and second_input_video_path join other audio on",
+                    ),
+                ] {
+                    if conflicts {
+                        problems.push(format!("audio_mode copy leaves the audio untouched, but {}", reason));
+                    }
+                }
+            }
+            Some("none") => {
+                if background_tracks > 0 {
+                    problems.push("audio_mode none drops the audio, so background_audio_path would be ignored".to_string());
+                }
+            }
+            Some(_) => problems.push(format!(
+                "audio_mode must be one of reencode, copy or none, got {}",
+                settings.audio_mode.as_deref().unwrap_or_default()
+            )),
+        }
+
+        if let Some(ref codec) = settings.audio_codec {
+            let codec_re = Regex::new(r"^[a-z0-9_]+$").unwrap();
+            if !codec_re.is_match(codec) {
+                problems.push(format!("audio_codec must be an ffmpeg encoder name like aac or libopus, got {}", codec));
+            }
+        }
+        if let Some(ref bitrate) = settings.audio_bitrate {
+            let bitrate_re = Regex::new(r"^\d+k$").unwrap();
+            if !bitrate_re.is_match(bitrate) {
+                problems.push(format!("audio_bitrate must be a number of kbit/s like 192k, got {}", bitrate));
+            }
+        }
+        if settings.audio_sample_rate == Some(0) {
+            problems.push("audio_sample_rate must be a number of Hz above 0 like 48000, got 0".to_string());
+        }
+        let reencodes_audio = settings.audio_mode.as_ref().is_none_or(|mode| mode.to_lowercase() == "reencode");
+        let audio_encoding_set =
+            settings.audio_codec.is_some() || settings.audio_bitrate.is_some() || settings.audio_sample_rate.is_some();
+        if !reencodes_audio && audio_encoding_set {
+            problems.push("audio_codec, audio_bitrate and audio_sample_rate only apply when audio_mode is reencode".to_string());
+        }
+
+        if let Some(crop) = settings.crop {
+            if crop.width == 0 || crop.height == 0 {
+                problems.push(format!("crop needs a width and height above 0, got {}x{}", crop.width, crop.height));
+            }
+        }
+
+        if let Some(ref rotate) = settings.rotate {
+            if !["90", "180", "270", "none"].contains(&rotate.to_lowercase().as_str()) {
+                problems.push(format!("rotate must be one of 90, 180, 270 or none, got {}", rotate));
+            }
+        }
+
+        if let Some(target) = settings.loudnorm_target {
+            // The range loudnorm accepts for I
+            if !(-70.0..=-5.0).contains(&target) {
+                problems.push(format!("loudnorm_target must be between -70 and -5 LUFS, got {}", target));
+            }
+            if !settings.loudnorm {
+                problems.push("loudnorm_target only applies when loudnorm is enabled".to_string());
+            }
+        }
+
+        if let Some(threshold) = settings.audio_limiter_threshold {
+            // alimiter's limit goes down to 0.0625, which is -24 dBFS
+            if !(-24.0..=0.0).contains(&threshold) {
+                problems.push(format!("audio_limiter_threshold must be between -24 and 0 dBFS, got {}", threshold));
+            }
+            if !settings.audio_limiter {
+                problems.push("audio_limiter_threshold only applies when audio_limiter is enabled".to_string());
+            }
+        }
+
+        // They go into the filter graph as they are, so they can only be the filter's own option list
+        for (name, params) in [("denoise_params", &settings.denoise_params), ("sharpen_params", &settings.sharpen_params)] {
+            if let Some(params) = params {
+                if params.is_empty() || params.contains([',', ';', '[', ']']) {
+                    problems.push(format!(
+                        "{} must be the filter's options like 4:3:6:4.5, without , ; [ or ], got {:?}",
+                        name, params
+                    ));
+                }
+            }
+        }
+        if settings.sharpen_params.is_some() && settings.sharpen.is_some() {
+            problems.push("sharpen_params replaces sharpen, set only one of them".to_string());
+        }
+
+        if let Some(ref time) = settings.thumbnail_time {
+            match parse_time(time) {
+                Ok(_) if settings.thumbnail_path.is_none() => {
+                    problems.push("thumbnail_time needs a thumbnail_path to write the frame to".to_string())
+                }
+                Ok(seconds) if seconds < 0.0 => problems.push(format!("thumbnail_time can't be negative, got {}", time)),
+                Ok(_) => {}
+                Err(e) => problems.push(format!("Invalid thumbnail_time: {}", e)),
+            }
+        }
+
+        // The waveform is drawn from the finished output's audio, so there has to be a file with audio in it
+        if settings.waveform_path.is_some() {
+            if settings.output_video_path == "-" {
+                problems.push("waveform_path reads the finished output, which isn't possible when writing to stdout".to_string());
+            }
+            if settings.audio_mode.as_ref().is_some_and(|mode| mode.to_lowercase() == "none") {
+                problems.push("waveform_path draws the output's audio, which audio_mode none drops".to_string());
+            }
+        }
+        if let Some(ref size) = settings.waveform_size {
+            if !Regex::new(r"^\d+x\d+$").unwrap().is_match(size) {
+                problems.push(format!("waveform_size must look like 1920x240, got {}", size));
+            } else if settings.waveform_path.is_none() {
+                problems.push("waveform_size needs a waveform_path to write the image to".to_string());
+            }
+        }
+
+        if let Some(ref skip_existing) = settings.skip_existing {
+            if !["always", "newer", "settings", "never"].contains(&skip_existing.to_lowercase().as_str()) {
+                problems.push(format!("skip_existing must be one of always, newer, settings or never, got {}", skip_existing));
+            }
+        }
+
+        if let Some(fps) = settings.target_fps {
+            if fps <= 0.0 {
+                problems.push(format!("target_fps must be greater than 0, got {}", fps));
+            }
+        }
+
+        problems
+    }
+}
+
+/// Whether `path` is stdin (`-`) or a URL rather than a file on disk.
+pub fn is_stream(path: &str) -> bool {
+    path == "-" || path.contains("://")
+}
+
+/// `path` with `suffix` added to the file name, e.g. "clip.mp4" -> "clip_preview.mp4" for "_preview". The extension
+/// stays last so ffmpeg still picks the container from it.
+pub fn suffixed_path(path: &str, suffix: &str) -> String {
+    let path = Path::new(path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(ext) => format!("{}{}.{}", stem, suffix, ext.to_string_lossy()),
+        None => format!("{}{}", stem, suffix),
+    };
+    path.with_file_name(file_name).to_string_lossy().into_owned()
+}
+
+/// Whether `color` is a color ffmpeg understands: a name like `white`, or `#RRGGBB`/`0xRRGGBB` with an optional alpha
+/// byte, either optionally followed by an `@0.5` opacity.
+fn is_color(color: &str) -> bool {
+    let color_re = Regex::new(r"^(?:(?:#|0x)[0-9a-fA-F]{6}(?:[0-9a-fA-F]{2})?|[A-Za-z]+)(?:@(?:0|1|0?\.\d+|1\.0+))?$").unwrap();
+    let name = color.split('@').next().unwrap_or_default().to_lowercase();
+    color_re.is_match(color) && (name.starts_with('#') || name.starts_with("0x") || COLOR_NAMES.contains(&name.as_str()))
+}
+
+/// Parses an aspect ratio given as width:height (`16:9`) or as a single number (`2.39`).
+fn parse_aspect_ratio(aspect: &str) -> Option<f64> {
+    let ratio = match aspect.trim().split_once(':') {
+        Some((width, height)) => width.trim().parse::<f64>().ok()? / height.trim().parse::<f64>().ok()?,
+        None => aspect.trim().parse::<f64>().ok()?,
+    };
+    Some(ratio).filter(|ratio| ratio.is_finite() && *ratio > 0.0)
+}
+
+/// Parses a time given in seconds (`90.5`) or as a timecode (`01:30`, `1:02:03.5`) into seconds.
+pub fn parse_time(time: &str) -> Result<f64, String> {
+    let time = time.trim();
+    let invalid = || format!("{:?} isn't a time, expected seconds (90.5) or a timecode like 01:30 or 1:02:03.5", time);
+    let parts: Vec<&str> = time.split(':').collect();
+    if parts.len() == 1 {
+        return time.parse::<f64>().map_err(|_| invalid());
+    }
+    if parts.len() > 3 {
+        return Err(invalid());
+    }
+
+    let mut seconds = 0.0;
+    for (index, part) in parts.iter().enumerate() {
+        // Only the seconds can have a fraction, the hours and minutes are whole numbers
+        let value = if index == parts.len() - 1 {
+            part.parse::<f64>().ok().filter(|value| *value >= 0.0)
+        } else {
+            part.parse::<u32>().ok().map(f64::from)
+        };
+        let value = value.ok_or_else(invalid)?;
+        if index > 0 && value >= 60.0 {
+            return Err(format!("{:?} isn't a valid timecode, the minutes and seconds have to be below 60", time));
+        }
+        seconds = seconds * 60.0 + value;
+    }
+    Ok(seconds)
+}
+
+fn home_dir() -> Option<String> {
+    env::var("HOME").or_else(|_| env::var("USERPROFILE")).ok()
+}
+
+// Fails with the name of the first variable that isn't set
+fn expand_path(path: &str) -> Result<String, String> {
+    if is_stream(path) {
+        return Ok(path.to_string());
+    }
+
+    let mut expanded = String::new();
+    let mut rest = path;
+
+    if let Some(stripped) = path.strip_prefix('~') {
+        if stripped.is_empty() || stripped.starts_with(['/', '\\']) {
+            if let Some(home) = home_dir() {
+                expanded.push_str(&home);
+                rest = stripped;
+            }
+        }
+    }
+
+    let re = Regex::new(r"\$(?:\{(\w+)\}|(\w+))").unwrap();
+    let mut unset = None;
+    let rest = re.replace_all(rest, |caps: &Captures| {
+        let name = caps.get(1).or_else(|| caps.get(2)).unwrap().as_str();
+        env::var(name).unwrap_or_else(|_| {
+            unset.get_or_insert_with(|| name.to_string());
+            caps[0].to_string()
+        })
+    });
+    if let Some(name) = unset {
+        return Err(name);
+    }
+    expanded.push_str(&rest);
+
+    Ok(expanded)
+}
+
+fn resolve_path(path: &mut String, base_dir: &Path) {
+    if path.is_empty() || is_stream(path) || Path::new(path.as_str()).is_absolute() {
+        return;
+    }
+    *path = base_dir.join(path.as_str()).to_string_lossy().into_owned();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // A fresh directory under the system temp dir, unique to the test and the test run
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("clippy-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_executable(path: &Path) {
+        fs::write(path, "#!/bin/sh\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+    }
+
+    fn problems(settings: SettingsBuilder) -> Vec<String> {
+        let settings = settings.input_video_path("in.mp4").output_video_path("out.mp4").build();
+        Config { settings, batch: Vec::new(), outputs: Vec::new() }.problems()
+    }
+
+    #[test]
+    fn speeds_have_to_be_positive() {
+        assert!(problems(Settings::builder().video_speed(0.25)).is_empty());
+        assert_eq!(problems(Settings::builder().video_speed(0.0)), ["video_speed must be greater than 0, got 0"]);
+        assert_eq!(problems(Settings::builder().video_speed(-2.0)), ["video_speed must be greater than 0, got -2"]);
+
+        let segment = SpeedSegment { start: 5.0, end: 10.0, factor: 0.0 };
+        let segments = problems(Settings::builder().clip_end_time("20").speed_segments(vec![segment]));
+        assert_eq!(segments, ["speed segment 5-10 needs a factor greater than 0"]);
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let settings = Settings::builder()
+            .output_video_path("out.mp4")
+            .video_speed(0.0)
+            .original_audio_volume(Volume::Factor(-0.5))
+            .audio_start_time(-1.0)
+            .crf("60")
+            .build();
+        let config = Config { settings, batch: Vec::new(), outputs: Vec::new() };
+        assert_eq!(
+            config.problems(),
+            [
+                "Missing required config key: input_video_path",
+                "video_speed must be greater than 0, got 0",
+                "original_audio_volume can't be negative, got -0.5",
+                "audio_start_time can't be negative, got -1",
+                "crf must be a whole number between 0 and 51, got 60",
+            ]
+        );
+        let ProcessingError::ConfigInvalid(message) = config.validate().unwrap_err() else {
+            panic!("validate didn't fail with ConfigInvalid");
+        };
+        assert_eq!(message.lines().count(), 5);
+    }
+
+    #[test]
+    fn fades_cant_be_negative() {
+        assert_eq!(
+            problems(Settings::builder().fade_in_duration(FadeDuration::Seconds(-1.0))),
+            ["fade_in_duration can't be negative, got -1"]
+        );
+        assert_eq!(
+            problems(Settings::builder().fade_out_duration(FadeDuration::Percent(120.0))),
+            ["fade_out_duration must be between 0% and 100%, got 120%"]
+        );
+    }
+
+    #[test]
+    fn the_clip_has_to_start_before_it_ends() {
+        assert!(problems(Settings::builder().clip_start_time("0:10").clip_end_time("0:40")).is_empty());
+        assert_eq!(
+            problems(Settings::builder().clip_start_time("0:40").clip_end_time("10")),
+            ["clip_start_time (40s) has to be before clip_end_time (10s)"]
+        );
+        assert_eq!(
+            problems(Settings::builder().clip_start_time("10").clip_end_time("10")),
+            ["clip_start_time (10s) has to be before clip_end_time (10s)"]
+        );
+    }
+
+    #[test]
+    fn no_bitrate_needs_a_crf() {
+        assert!(problems(Settings::builder().video_bitrate("None").crf("23")).is_empty());
+        assert_eq!(
+            problems(Settings::builder().video_bitrate("None")),
+            ["video_bitrate can only be \"None\" when a crf is set"]
+        );
+    }
+
+    #[test]
+    fn missing_keys_are_named() {
+        let empty_ffmpeg = problems(Settings::builder().ffmpeg_path(""));
+        assert_eq!(empty_ffmpeg, ["Missing required config key: ffmpeg_path"]);
+        let empty_bitrate = problems(Settings::builder().video_bitrate(""));
+        assert_eq!(empty_bitrate, ["Missing required config key: video_bitrate"]);
+
+        let config = Config { settings: Settings::default(), batch: Vec::new(), outputs: Vec::new() };
+        let message = config.validate().unwrap_err().to_string();
+        assert!(message.contains("Missing required config key: input_video_path"), "{}", message);
+        assert!(message.contains("Missing required config key: output_video_path"), "{}", message);
+
+        let settings = Settings::builder().input_video_path("in.mp4").output_video_path("out.mp4").ffmpeg_path("").build();
+        let config = Config { settings, batch: Vec::new(), outputs: Vec::new() };
+        let message = config.validate().unwrap_err().to_string();
+        assert!(message.contains("Missing required config key: ffmpeg_path"), "{}", message);
+    }
+
+    #[test]
+    fn finds_a_bare_name_on_path() {
+        let dir = temp_dir("which-bare");
+        let (empty, bin) = (dir.join("empty"), dir.join("bin"));
+        fs::create_dir_all(&empty).unwrap();
+        fs::create_dir_all(&bin).unwrap();
+        let ffmpeg = bin.join(if cfg!(windows) { "ffmpeg.exe" } else { "ffmpeg" });
+        write_executable(&ffmpeg);
+        let path_var = env::join_paths([&empty, &bin]).unwrap();
+
+        assert_eq!(find_executable("ffmpeg", Some(&path_var)), Some(ffmpeg));
+        assert_eq!(find_executable("ffprobe", Some(&path_var)), None);
+        assert_eq!(find_executable("ffmpeg", None), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn an_explicit_path_overrides_path() {
+        let dir = temp_dir("which-explicit");
+        let (custom, bin) = (dir.join("custom"), dir.join("bin"));
+        fs::create_dir_all(&custom).unwrap();
+        fs::create_dir_all(&bin).unwrap();
+        write_executable(&custom.join("ffmpeg"));
+        write_executable(&bin.join("ffmpeg"));
+        let path_var = env::join_paths([&bin]).unwrap();
+
+        let explicit = custom.join("ffmpeg");
+        assert_eq!(find_executable(&explicit.to_string_lossy(), Some(&path_var)), Some(explicit));
+        // A path that doesn't exist isn't looked up on PATH instead
+        let missing = dir.join("missing").join("ffmpeg");
+        assert_eq!(find_executable(&missing.to_string_lossy(), Some(&path_var)), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skips_files_that_arent_executable() {
+        let dir = temp_dir("which-mode");
+        let ffmpeg = dir.join("ffmpeg");
+        fs::write(&ffmpeg, "").unwrap();
+        let path_var = env::join_paths([&dir]).unwrap();
+
+        assert_eq!(find_executable("ffmpeg", Some(&path_var)), None);
+        assert_eq!(find_executable(&ffmpeg.to_string_lossy(), None), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_missing_explicit_ffmpeg_isnt_looked_for_on_path() {
+        let dir = temp_dir("which-missing");
+        let missing = dir.join("ffmpeg");
+        let settings = Settings { ffmpeg_path: missing.to_string_lossy().into_owned(), ..Settings::default() };
+        let e = settings.ffmpeg_executable().unwrap_err();
+        assert!(matches!(e, ProcessingError::FfmpegNotFound(ref path) if *path == settings.ffmpeg_path));
+        assert_eq!(e.to_string(), format!("ffmpeg not found at {}; install it or set ffmpeg_path", settings.ffmpeg_path));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_missing_ffmpeg_is_reported_up_front() {
+        let settings = Settings { ffmpeg_path: "clippy-no-such-ffmpeg".to_string(), ..Settings::default() };
+        let e = settings.ffmpeg_executable().unwrap_err();
+        assert!(matches!(e, ProcessingError::FfmpegNotFound(_)));
+        assert_eq!(
+            e.to_string(),
+            "clippy-no-such-ffmpeg not found on PATH; install ffmpeg or set ffmpeg_path to where it is"
+        );
+    }
+
+    #[test]
+    fn parses_volumes() {
+        assert_eq!("50%".parse(), Ok(Volume::Factor(0.5)));
+        assert_eq!("-6dB".parse(), Ok(Volume::Decibels(-6.0)));
+        assert_eq!("1.5".parse(), Ok(Volume::Factor(1.5)));
+        assert_eq!(serde_json::from_str::<Volume>("1.5").unwrap(), Volume::Factor(1.5));
+        assert_eq!(serde_json::from_str::<Volume>("\"-6dB\"").unwrap(), Volume::Decibels(-6.0));
+        assert!("loud".parse::<Volume>().is_err());
+
+        // Decibels go to ffmpeg as they are, it understands the suffix
+        assert_eq!(Volume::Decibels(-6.0).to_string(), "-6dB");
+        assert_eq!(Volume::Factor(0.5).to_string(), "0.5");
+    }
+
+    #[test]
+    fn parses_fade_durations() {
+        let half: FadeDuration = "50%".parse().unwrap();
+        assert_eq!(half, FadeDuration::Percent(50.0));
+        assert_eq!(half.seconds(Some(20.0)), Some(10.0));
+        assert_eq!(half.seconds(None), None);
+        assert_eq!("1.5".parse(), Ok(FadeDuration::Seconds(1.5)));
+        assert_eq!("none".parse(), Ok(FadeDuration::Seconds(0.0)));
+        assert!(FadeDuration::Seconds(0.0).is_off());
+        assert_eq!(
+            "soon".parse::<FadeDuration>(),
+            Err("invalid fade duration \"soon\", expected seconds or a percentage like \"10%\"".to_string())
+        );
+        assert!("%".parse::<FadeDuration>().is_err());
+    }
+
+    #[test]
+    fn suggests_the_closest_key() {
+        let keys = ["fade_in_duration", "fade_out_duration", "video_bitrate", "video_speed"];
+        assert_eq!(closest_key("fade_duraton", &keys), Some("fade_in_duration"));
+        assert_eq!(closest_key("fade_out_duraton", &keys), Some("fade_out_duration"));
+        assert_eq!(closest_key("Video_Bitrate", &keys), Some("video_bitrate"));
+        assert_eq!(closest_key("thumbnail", &keys), None);
+        assert_eq!(closest_key("vb", &keys), None);
+    }
+
+    #[test]
+    fn unknown_keys_are_reported_with_a_suggestion() {
+        let toml = r#"
+[settings]
+input_video_path = "/videos/input.mp4"
+output_video_path = "/videos/output.mp4"
+fade_duraton = 2.0
+thumbnail = true
+"#;
+        let message = Config::from_reader(toml.as_bytes(), FileFormat::Toml, None).unwrap_err().to_string();
+        assert_eq!(
+            message,
+            "Unknown key fade_duraton in [settings], did you mean fade_in_duration?\n  Unknown key thumbnail in [settings]"
+        );
+    }
+
+    #[test]
+    fn parses_times() {
+        assert_eq!(parse_time("01:02:03.500"), Ok(3723.5));
+        assert_eq!(parse_time("1:30"), Ok(90.0));
+        assert_eq!(parse_time("00:00:59.999"), Ok(59.999));
+        assert_eq!(parse_time("90.5"), Ok(90.5));
+        assert_eq!(parse_time(" 45 "), Ok(45.0));
+        assert_eq!(parse_time("120"), Ok(120.0));
+    }
+
+    #[test]
+    fn rejects_malformed_times() {
+        assert_eq!(
+            parse_time("1:70:00"),
+            Err("\"1:70:00\" isn't a valid timecode, the minutes and seconds have to be below 60".to_string())
+        );
+        assert!(parse_time("1:30:60").is_err());
+        assert!(parse_time("1:2:3:4").is_err());
+        assert!(parse_time("1.5:30").is_err());
+        assert!(parse_time("1:-5").is_err());
+        assert!(parse_time("1:").is_err());
+        assert_eq!(
+            parse_time("soon"),
+            Err("\"soon\" isn't a time, expected seconds (90.5) or a timecode like 01:30 or 1:02:03.5".to_string())
+        );
+    }
+
+    #[test]
+    fn later_files_override_earlier_ones() {
+        let dir = temp_dir("layers");
+        let local = dir.join("local");
+        fs::create_dir_all(&local).unwrap();
+        let base = dir.join("base.toml");
+        fs::write(
+            &base,
+            r#"
+[settings]
+input_video_path = "in.mp4"
+output_video_path = "out.mp4"
+video_bitrate = "8000k"
+video_speed = 1.0
+advanced_log = false
+fade_in_duration = 2.0
+original_audio_volume = 1.0
+background_audio_volume = 1.0
+audio_start_time = 0.0
+replace_audio = false
+"#,
+        )
+        .unwrap();
+        let overrides = local.join("overrides.toml");
+        fs::write(&overrides, "[settings]
+video_bitrate = \"4M\"\noutput_video_path = \"short.mp4\"\n").unwrap();
+
+        let files = [base.to_string_lossy().into_owned(), overrides.to_string_lossy().into_owned()];
+        let settings = Config::from_files(&files).unwrap().settings;
+        assert_eq!(settings.video_bitrate, "4M");
+        assert_eq!(settings.fade_in_duration, Some(FadeDuration::Seconds(2.0)));
+        // Relative to the last file, wherever the path was set
+        assert_eq!(Path::new(&settings.output_video_path), local.join("short.mp4"));
+        assert_eq!(Path::new(&settings.input_video_path), local.join("in.mp4"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn flags_override_the_file() {
+        let toml = r#"
+[settings]
+input_video_path = "/videos/input.mp4"
+output_video_path = "/videos/output.mp4"
+video_bitrate = "8000k"
+video_speed = 1.5
+advanced_log = false
+fade_in_duration = 2.0
+fade_out_duration = 2.5
+original_audio_volume = 1.0
+background_audio_volume = 1.0
+audio_start_time = 0.0
+replace_audio = false
+use_gpu = true
+"#;
+        let file = Config::from_reader(toml.as_bytes(), FileFormat::Toml, None).unwrap();
+
+        let mut overridden = file.clone();
+        overridden.apply_overrides(&SettingsOverrides {
+            output_video_path: Some("short.mp4".to_string()),
+            video_bitrate: Some("4M".to_string()),
+            fade_in_duration: Some(FadeDuration::Percent(5.0)),
+            use_gpu: Some(false),
+            ..SettingsOverrides::default()
+        });
+        let settings = &overridden.settings;
+        assert_eq!(settings.output_video_path, "short.mp4");
+        assert_eq!(settings.video_bitrate, "4M");
+        assert_eq!(settings.fade_in_duration, Some(FadeDuration::Percent(5.0)));
+        assert!(!settings.use_gpu);
+        // The flags that weren't given keep the file's values
+        assert_eq!(settings.fade_out_duration, Some(FadeDuration::Seconds(2.5)));
+        assert_eq!(settings.video_speed, 1.5);
+        assert_eq!(settings.input_video_path, "/videos/input.mp4");
+    }
+
+    #[test]
+    fn no_flags_leave_the_file_alone() {
+        let mut config = Config {
+            settings: Settings::builder().video_bitrate("8000k").fade_in_duration(FadeDuration::Seconds(2.0)).build(),
+            batch: Vec::new(),
+            outputs: Vec::new(),
+        };
+        let file = config.clone();
+        config.apply_overrides(&SettingsOverrides::default());
+        assert_eq!(config, file);
+    }
+
+    #[test]
+    fn every_format_gives_the_same_settings() {
+        let toml = r#"
+[settings]
+input_video_path = "/videos/input.mp4"
+output_video_path = "/videos/output.mp4"
+video_bitrate = "8000k"
+video_speed = 1.5
+advanced_log = false
+fade_in_duration = "10%"
+fade_out_duration = 2.5
+original_audio_volume = "-6dB"
+background_audio_volume = ["50%", 0.8]
+audio_start_time = 0.0
+replace_audio = false
+crop = { width = 1280, height = 720 }
+speed_segments = [{ start = 5.0, end = 10.0, factor = 2.0 }]
+"#;
+        let json = r#"{
+  "settings": {
+    "input_video_path": "/videos/input.mp4",
+    "output_video_path": "/videos/output.mp4",
+    "video_bitrate": "8000k",
+    "video_speed": 1.5,
+    "advanced_log": false,
+    "fade_in_duration": "10%",
+    "fade_out_duration": 2.5,
+    "original_audio_volume": "-6dB",
+    "background_audio_volume": ["50%", 0.8],
+    "audio_start_time": 0.0,
+    "replace_audio": false,
+    "crop": { "width": 1280, "height": 720 },
+    "speed_segments": [{ "start": 5.0, "end": 10.0, "factor": 2.0 }]
+  }
+}"#;
+        let yaml = r#"
+settings:
+  input_video_path: /videos/input.mp4
+  output_video_path: /videos/output.mp4
+  video_bitrate: 8000k
+  video_speed: 1.5
+  advanced_log: false
+  fade_in_duration: 10%
+  fade_out_duration: 2.5
+  original_audio_volume: -6dB
+  background_audio_volume: [50%, 0.8]
+  audio_start_time: 0.0
+  replace_audio: false
+  crop:
+    width: 1280
+    height: 720
+  speed_segments:
+    - { start: 5.0, end: 10.0, factor: 2.0 }
+"#;
+        let parse = |text: &str, format| Config::from_reader(text.as_bytes(), format, None).unwrap().settings;
+        let from_toml = parse(toml, FileFormat::Toml);
+
+        assert_eq!(from_toml.fade_in_duration, Some(FadeDuration::Percent(10.0)));
+        assert_eq!(from_toml.background_audio_volume, vec![Volume::Factor(0.5), Volume::Factor(0.8)]);
+        assert_eq!(parse(json, FileFormat::Json), from_toml);
+        assert_eq!(parse(yaml, FileFormat::Yaml), from_toml);
     }
 }
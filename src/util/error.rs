@@ -0,0 +1,90 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::process::ExitStatus;
+use std::time::Duration;
+
+/// Everything that can go wrong while checking a config or processing a video.
+#[derive(Debug)]
+pub enum ProcessingError {
+    /// A setting, or a combination of settings and the input, that can't work.
+    ConfigInvalid(String),
+    /// `clip_start_time` or `clip_end_time` isn't a number of seconds.
+    InvalidClipTime(String),
+    /// ffmpeg's stream info is missing something that's needed, like the duration.
+    ProbeParse(String),
+    /// There's no executable at `ffmpeg_path`, or on PATH when it's a bare name.
+    FfmpegNotFound(String),
+    /// ffmpeg couldn't be started at `path`.
+    FfmpegSpawn { path: String, source: io::Error },
+    /// ffmpeg ran and failed, `stderr` has the last lines it printed (empty with `advanced_log`, where they went to
+    /// the terminal).
+    FfmpegExit { status: ExitStatus, stderr: Vec<String> },
+    /// ffmpeg failed because the GPU encoder couldn't run, see `gpu_fallback`.
+    GpuEncodeFailed(ExitStatus),
+    /// ffmpeg was still running after `timeout_secs` and was killed.
+    FfmpegTimeout(Duration),
+    /// The run was interrupted (Ctrl-C) and ffmpeg was stopped.
+    Interrupted,
+    /// The finished output didn't pass `verify_output`.
+    OutputInvalid(String),
+    /// A file operation around the encode failed.
+    Io { context: String, source: io::Error },
+}
+
+impl fmt::Display for ProcessingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProcessingError::ConfigInvalid(message)
+            | ProcessingError::InvalidClipTime(message)
+            | ProcessingError::ProbeParse(message)
+            | ProcessingError::OutputInvalid(message) => write!(f, "{}", message),
+            ProcessingError::FfmpegNotFound(path) if !path.contains(['/', '\\']) => {
+                write!(f, "{} not found on PATH; install ffmpeg or set ffmpeg_path to where it is", path)
+            }
+            ProcessingError::FfmpegNotFound(path) => write!(f, "ffmpeg not found at {}; install it or set ffmpeg_path", path),
+            // A bare "No such file or directory" doesn't tell anyone that ffmpeg is what's missing
+            ProcessingError::FfmpegSpawn { path, source } => match source.kind() {
+                // A bare name was looked for on PATH rather than at a path
+                io::ErrorKind::NotFound if !path.contains(['/', '\\']) => {
+                    write!(f, "{} not found on PATH; install ffmpeg or set ffmpeg_path to where it is", path)
+                }
+                io::ErrorKind::NotFound => write!(f, "ffmpeg not found at {}; install it or set ffmpeg_path", path),
+                io::ErrorKind::PermissionDenied => {
+                    write!(f, "ffmpeg at {} can't be run ({}); check it's executable or set ffmpeg_path", path, source)
+                }
+                _ => write!(f, "Failed to run ffmpeg at {}: {}", path, source),
+            },
+            ProcessingError::FfmpegExit { status, stderr } => {
+                write!(f, "FFmpeg command failed with status: {}", status)?;
+                if !stderr.is_empty() {
+                    write!(f, ", its last output was:")?;
+                    for line in stderr {
+                        write!(f, "\n    {}", line)?;
+                    }
+                }
+                Ok(())
+            }
+            ProcessingError::GpuEncodeFailed(status) => write!(
+                f,
+                "FFmpeg command failed with status: {}, the GPU encoder couldn't run; set gpu_fallback (or pass \
+                 --gpu-fallback) to encode with libx265 when that happens",
+                status
+            ),
+            ProcessingError::FfmpegTimeout(timeout) => {
+                write!(f, "ffmpeg was still running after {}s and was stopped, see timeout_secs", timeout.as_secs_f64())
+            }
+            ProcessingError::Interrupted => write!(f, "Interrupted, ffmpeg was stopped before it finished"),
+            ProcessingError::Io { context, source } => write!(f, "{}: {}", context, source),
+        }
+    }
+}
+
+impl Error for ProcessingError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ProcessingError::FfmpegSpawn { source, .. } | ProcessingError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
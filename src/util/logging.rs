@@ -1,6 +1,72 @@
-use log::LevelFilter;
+use log::{LevelFilter, Log, Metadata, Record};
 use simple_logger::SimpleLogger;
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{self, stderr, IsTerminal, Write};
+use std::sync::Mutex;
+use time::format_description::FormatItem;
+use time::OffsetDateTime;
 
-pub fn setup_logging() {
-    SimpleLogger::new().with_level(LevelFilter::Info).init().unwrap();
+const FILE_TIMESTAMP_FORMAT: &[FormatItem] =
+    time::macros::format_description!("[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond digits:3]Z");
+
+// Sends every record to the terminal logger and, when there is one, appends it to the log file as well
+struct TeeLogger {
+    terminal: SimpleLogger,
+    file: Option<Mutex<File>>,
+    level: LevelFilter,
+}
+
+impl Log for TeeLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        self.terminal.log(record);
+        if let Some(ref file) = self.file {
+            // The file always gets timestamps and never colors, it's read long after the fact
+            let timestamp = OffsetDateTime::now_utc().format(FILE_TIMESTAMP_FORMAT).unwrap_or_default();
+            let mut file = file.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let _ = writeln!(file, "{} {:<5} [{}] {}", timestamp, record.level(), record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {
+        self.terminal.flush();
+        if let Some(ref file) = self.file {
+            let _ = file.lock().map(|mut file| file.flush());
+        }
+    }
+}
+
+/// Sets up logging to stderr at `level`, and to `log_file` too when it's given (appending to it). `color` is
+/// `always`, `never` or `auto`, which only colors the output when stderr is a terminal and `NO_COLOR` isn't set.
+/// Only the first call installs a logger, later ones leave it as it is.
+pub fn setup_logging(level: LevelFilter, timestamps: bool, color: &str, log_file: Option<&str>) -> io::Result<()> {
+    let colors = match color.to_lowercase().as_str() {
+        "always" => true,
+        "never" => false,
+        _ => env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()) && stderr().is_terminal(),
+    };
+
+    let file = match log_file {
+        Some(path) => Some(OpenOptions::new().create(true).append(true).open(path)?),
+        None => None,
+    };
+
+    let mut terminal = SimpleLogger::new().with_level(level).with_colors(colors);
+    if !timestamps {
+        terminal = terminal.without_timestamps();
+    }
+    let logger = TeeLogger { terminal, file: file.map(Mutex::new), level };
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(level);
+    }
+    // simple_logger decides on its own whether stderr can show colors, this makes the choice above stick
+    colored::control::set_override(colors);
+    Ok(())
 }
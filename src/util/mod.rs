@@ -1,3 +1,9 @@
+pub mod check;
 pub mod config;
+pub mod error;
 pub mod logging;
+pub mod probe;
 pub mod processing;
+pub mod shell;
+pub mod template;
+pub mod watch;
@@ -0,0 +1,653 @@
+use log::{debug, info};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+
+use crate::util::config::{self, Config};
+use crate::util::error::ProcessingError;
+
+/// What ffmpeg reports about a media file. Anything it doesn't list comes back as `None`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MediaInfo {
+    pub duration: Option<f64>,
+    /// Overall bitrate of the file in bits per second
+    pub bitrate: Option<u64>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub framerate: Option<f64>,
+    pub video_codec: Option<String>,
+    pub audio_codec: Option<String>,
+    pub audio_sample_rate: Option<u32>,
+    pub audio_channels: Option<u32>,
+    /// Bitrate of the audio stream in bits per second
+    pub audio_bitrate: Option<u64>,
+    pub has_video: bool,
+    pub has_audio: bool,
+    pub interlaced: bool,
+    /// `tv` (limited) or `pc` (full)
+    pub color_range: Option<String>,
+    pub colorspace: Option<String>,
+    pub color_primaries: Option<String>,
+    pub color_transfer: Option<String>,
+    /// The file's global metadata, like `title` or `clippy_settings`
+    pub tags: BTreeMap<String, String>,
+    /// The prober's output as-is (ffprobe's JSON, or ffmpeg's stream info when ffprobe isn't there), for anything
+    /// not parsed into the fields above
+    #[serde(skip)]
+    pub raw: String,
+}
+
+impl MediaInfo {
+    /// Number of frames in the video, from the duration and the exact framerate.
+    pub fn total_frames(&self) -> Option<u64> {
+        Some((self.duration? * self.framerate?).round() as u64)
+    }
+
+    /// The duration and framerate that processing can't do without, an error naming whichever is missing.
+    pub fn duration_and_framerate(&self) -> Result<(f64, f64), ProcessingError> {
+        let duration =
+            self.duration.ok_or_else(|| ProcessingError::ProbeParse("Could not determine video duration".to_string()))?;
+        let framerate =
+            self.framerate.ok_or_else(|| ProcessingError::ProbeParse("Could not determine video framerate".to_string()))?;
+        Ok((duration, framerate))
+    }
+
+    /// Whether the video is HDR, going by its PQ (smpte2084) or HLG (arib-std-b67) transfer.
+    pub fn is_hdr(&self) -> bool {
+        matches!(self.color_transfer.as_deref(), Some("smpte2084" | "arib-std-b67"))
+    }
+
+    /// A readable summary for `--info`, one property per line. Video and audio lines are only there when the file
+    /// has those streams, and what the prober didn't report shows as `unknown`.
+    pub fn table(&self) -> String {
+        fn or_unknown<T: ToString>(value: Option<T>) -> String {
+            value.map_or_else(|| "unknown".to_string(), |value| value.to_string())
+        }
+        let kbps = |bitrate: Option<u64>| bitrate.map(|bitrate| format!("{} kb/s", bitrate / 1000));
+
+        let mut rows = vec![
+            ("Duration", or_unknown(self.duration.map(format_duration))),
+            ("Bitrate", or_unknown(kbps(self.bitrate))),
+        ];
+        if self.has_video {
+            let resolution = self.width.zip(self.height).map(|(width, height)| format!("{}x{}", width, height));
+            rows.push(("Video codec", or_unknown(self.video_codec.as_ref())));
+            rows.push(("Resolution", or_unknown(resolution)));
+            rows.push(("Framerate", or_unknown(self.framerate.map(|rate| format!("{:.3} fps", rate)))));
+            rows.push(("Frames", or_unknown(self.total_frames())));
+            rows.push(("Scan", if self.interlaced { "interlaced" } else { "progressive" }.to_string()));
+            if let Some(ref range) = self.color_range {
+                rows.push(("Color range", range.clone()));
+            }
+            if let Some(ref colorspace) = self.colorspace {
+                rows.push(("Colorspace", colorspace.clone()));
+            }
+        } else {
+            rows.push(("Video", "none".to_string()));
+        }
+        if self.has_audio {
+            rows.push(("Audio codec", or_unknown(self.audio_codec.as_ref())));
+            rows.push(("Sample rate", or_unknown(self.audio_sample_rate.map(|rate| format!("{} Hz", rate)))));
+            rows.push(("Channels", or_unknown(self.audio_channels)));
+            rows.push(("Audio bitrate", or_unknown(kbps(self.audio_bitrate))));
+        } else {
+            rows.push(("Audio", "none".to_string()));
+        }
+
+        let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or_default();
+        let mut table = String::new();
+        for (name, value) in rows {
+            let _ = writeln!(table, "{:<width$}  {}", name, value, width = width);
+        }
+        table
+    }
+}
+
+// 3725.5 -> "01:02:05.500"
+fn format_duration(seconds: f64) -> String {
+    let millis = (seconds * 1000.0).round() as u64;
+    format!("{:02}:{:02}:{:02}.{:03}", millis / 3_600_000, millis / 60_000 % 60, millis / 1000 % 60, millis % 1000)
+}
+
+/// Probes the configured input video.
+pub fn probe(config: &Config) -> Result<MediaInfo, ProcessingError> {
+    let settings = &config.settings;
+    // Probing reads from the input, which would eat the start of a piped stream
+    if settings.input_video_path == "-" {
+        return Err(ProcessingError::ConfigInvalid("A stdin stream can't be probed".to_string()));
+    }
+    settings.ffmpeg_executable()?;
+    probe_media(&settings.ffmpeg_path, &settings.input_video_path)
+}
+
+// A local file, by the ffmpeg that probed it and when the file was last changed
+type ProbeKey = (String, String, SystemTime);
+
+/// Probe results of local files, reused until the file changes. Streams and urls have no modification time to go
+/// by, so they're probed every time.
+#[derive(Default)]
+pub struct ProbeCache {
+    probed: Mutex<HashMap<ProbeKey, MediaInfo>>,
+}
+
+impl ProbeCache {
+    /// The earlier result for `input`, or what `probe` makes of it when there's none or the file has changed since.
+    /// A failed probe isn't kept.
+    pub fn get_or_probe<P>(&self, ffmpeg_path: &str, input: &str, probe: P) -> Result<MediaInfo, ProcessingError>
+    where
+        P: FnOnce(&str, &str) -> Result<MediaInfo, ProcessingError>,
+    {
+        let modified = fs::metadata(input).and_then(|metadata| metadata.modified()).ok();
+        let key = modified.map(|modified| (ffmpeg_path.to_string(), input.to_string(), modified));
+        if let Some(info) = key.as_ref().and_then(|key| self.probed.lock().unwrap().get(key).cloned()) {
+            debug!("Reusing the earlier probe of {}", input);
+            return Ok(info);
+        }
+
+        let info = probe(ffmpeg_path, input)?;
+        if let Some(key) = key {
+            self.probed.lock().unwrap().insert(key, info.clone());
+        }
+        Ok(info)
+    }
+}
+
+/// Probes `input` with the ffprobe next to `ffmpeg_path`. If there's no usable ffprobe it falls back to running
+/// `ffmpeg -i` on it without an output and reading the stream info it prints. A local file is only probed once per
+/// run unless it changes in the meantime, so the background audio of a batch or an intro used twice isn't probed
+/// for every file. Streams and urls are probed every time.
+pub fn probe_media(ffmpeg_path: &str, input: &str) -> Result<MediaInfo, ProcessingError> {
+    static CACHE: OnceLock<ProbeCache> = OnceLock::new();
+    CACHE.get_or_init(Default::default).get_or_probe(ffmpeg_path, input, probe_uncached)
+}
+
+fn probe_uncached(ffmpeg_path: &str, input: &str) -> Result<MediaInfo, ProcessingError> {
+    // A server that stops answering would hang the probe for good, -rw_timeout (in microseconds) gives up on it
+    let network_options: &[&str] = if input.contains("://") { &["-rw_timeout", "30000000"] } else { &[] };
+    if !network_options.is_empty() {
+        info!("Probing {}, a remote input can take a while", input);
+    }
+    let ffprobe_path = ffprobe_path(ffmpeg_path, env::var_os("PATH").as_deref());
+    match ffprobe_path.as_deref().and_then(|ffprobe_path| ffprobe(ffprobe_path, input, network_options)) {
+        Some(info) => Ok(info),
+        None => {
+            match ffprobe_path {
+                Some(ffprobe_path) => debug!("Couldn't probe {} with {}, reading ffmpeg's stream info instead", input, ffprobe_path),
+                None => debug!("No ffprobe found for {}, reading ffmpeg's stream info instead", ffmpeg_path),
+            }
+            let probe_command = Command::new(ffmpeg_path)
+                .args(network_options)
+                .arg("-i")
+                .arg(input)
+                .arg("-hide_banner")
+                .output()
+                .map_err(|source| ProcessingError::FfmpegSpawn { path: ffmpeg_path.to_string(), source })?;
+            Ok(parse_media_info(&String::from_utf8_lossy(&probe_command.stderr)))
+        }
+    }
+}
+
+/// The encoders `ffmpeg -encoders` lists, e.g. `libx265` or `hevc_nvenc`. The list is only queried once per ffmpeg
+/// binary, so a batch doesn't ask for every file.
+pub fn encoders(ffmpeg_path: &str) -> Result<Vec<String>, ProcessingError> {
+    static CACHE: OnceLock<Mutex<HashMap<String, Vec<String>>>> = OnceLock::new();
+    let cache = CACHE.get_or_init(Default::default);
+    if let Some(encoders) = cache.lock().unwrap().get(ffmpeg_path) {
+        return Ok(encoders.clone());
+    }
+
+    let output = Command::new(ffmpeg_path)
+        .args(["-hide_banner", "-encoders"])
+        .output()
+        .map_err(|source| ProcessingError::FfmpegSpawn { path: ffmpeg_path.to_string(), source })?;
+    let encoders = parse_encoders(&String::from_utf8_lossy(&output.stdout));
+    cache.lock().unwrap().insert(ffmpeg_path.to_string(), encoders.clone());
+    Ok(encoders)
+}
+
+// " V....D hevc_nvenc  NVIDIA NVENC hevc encoder (codec hevc)" lines follow a legend that ends with " ------"
+fn parse_encoders(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("---"))
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().nth(1).map(str::to_string))
+        .collect()
+}
+
+// ffprobe ships alongside ffmpeg, so "/opt/ffmpeg/bin/ffmpeg.exe" -> "/opt/ffmpeg/bin/ffprobe.exe", and a bare
+// "ffmpeg" gets the ffprobe on PATH. So does a custom build with another name. None when that ffprobe doesn't exist
+fn ffprobe_path(ffmpeg_path: &str, path_var: Option<&OsStr>) -> Option<String> {
+    let path = Path::new(ffmpeg_path);
+    let ffprobe = match path.file_name().and_then(|name| name.to_str()).and_then(|name| name.strip_prefix("ffmpeg")) {
+        Some(suffix) => path.with_file_name(format!("ffprobe{}", suffix)).to_string_lossy().into_owned(),
+        None => "ffprobe".to_string(),
+    };
+    config::find_executable(&ffprobe, path_var).map(|path| path.to_string_lossy().into_owned())
+}
+
+#[derive(Deserialize)]
+struct FfprobeOutput {
+    #[serde(default)]
+    streams: Vec<FfprobeStream>,
+    format: Option<FfprobeFormat>,
+}
+
+#[derive(Deserialize)]
+struct FfprobeStream {
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    avg_frame_rate: Option<String>,
+    r_frame_rate: Option<String>,
+    field_order: Option<String>,
+    color_range: Option<String>,
+    color_space: Option<String>,
+    color_primaries: Option<String>,
+    color_transfer: Option<String>,
+    duration: Option<String>,
+    bit_rate: Option<String>,
+    sample_rate: Option<String>,
+    channels: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct FfprobeFormat {
+    duration: Option<String>,
+    bit_rate: Option<String>,
+    #[serde(default)]
+    tags: BTreeMap<String, String>,
+}
+
+// None when ffprobe can't be run, fails, or prints something that isn't its JSON
+fn ffprobe(ffprobe_path: &str, input: &str, input_options: &[&str]) -> Option<MediaInfo> {
+    let output = Command::new(ffprobe_path)
+        .args(["-v", "quiet", "-print_format", "json", "-show_format", "-show_streams"])
+        .args(input_options)
+        .arg(input)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let raw = String::from_utf8_lossy(&output.stdout).into_owned();
+    let probed: FfprobeOutput = serde_json::from_str(&raw).ok()?;
+
+    let stream = |kind: &str| probed.streams.iter().find(|stream| stream.codec_type.as_deref() == Some(kind));
+    let video = stream("video");
+    let audio = stream("audio");
+    let known = |name: &Option<String>| name.clone().filter(|name| name != "unknown" && name != "reserved");
+
+    // avg_frame_rate is what ffmpeg's "fps" shows, r_frame_rate is only the base rate of the timestamps
+    let framerate = video.and_then(|video| {
+        [&video.avg_frame_rate, &video.r_frame_rate].into_iter().flatten().find_map(|rate| parse_rational(rate))
+    });
+    let duration = probed
+        .format
+        .as_ref()
+        .and_then(|format| format.duration.as_ref())
+        .or_else(|| video.and_then(|video| video.duration.as_ref()))
+        .and_then(|duration| duration.parse::<f64>().ok());
+
+    Some(MediaInfo {
+        duration,
+        bitrate: probed.format.as_ref().and_then(|format| number(&format.bit_rate)),
+        width: video.and_then(|video| video.width),
+        height: video.and_then(|video| video.height),
+        framerate,
+        video_codec: video.and_then(|video| video.codec_name.clone()),
+        audio_codec: audio.and_then(|audio| audio.codec_name.clone()),
+        audio_sample_rate: audio.and_then(|audio| number(&audio.sample_rate)),
+        audio_channels: audio.and_then(|audio| audio.channels),
+        audio_bitrate: audio.and_then(|audio| number(&audio.bit_rate)),
+        has_video: video.is_some(),
+        has_audio: audio.is_some(),
+        interlaced: video.is_some_and(|video| matches!(video.field_order.as_deref(), Some("tt" | "bb" | "tb" | "bt"))),
+        color_range: video.and_then(|video| known(&video.color_range)),
+        colorspace: video.and_then(|video| known(&video.color_space)),
+        color_primaries: video.and_then(|video| known(&video.color_primaries)),
+        color_transfer: video.and_then(|video| known(&video.color_transfer)),
+        tags: probed.format.map(|format| format.tags).unwrap_or_default(),
+        raw,
+    })
+}
+
+// ffprobe gives the numbers as strings, like "128000"
+fn number<T: std::str::FromStr>(value: &Option<String>) -> Option<T> {
+    value.as_ref().and_then(|value| value.parse().ok())
+}
+
+// "30000/1001" -> 29.97..., ffprobe reports "0/0" when it doesn't know the rate
+fn parse_rational(rate: &str) -> Option<f64> {
+    let (num, den) = rate.split_once('/').unwrap_or((rate, "1"));
+    let (num, den) = (num.trim().parse::<f64>().ok()?, den.trim().parse::<f64>().ok()?);
+    (num > 0.0 && den > 0.0).then(|| num / den)
+}
+
+/// The duration and framerate from the stream info `ffmpeg -i` prints to stderr, see `parse_media_info`.
+pub fn parse_probe_output(stderr: &str) -> Result<(f64, f64), ProcessingError> {
+    parse_media_info(stderr).duration_and_framerate()
+}
+
+/// Everything `ffmpeg -i` reports about a file in the stream info it prints to stderr, without running anything.
+pub fn parse_media_info(output: &str) -> MediaInfo {
+    let video_stream = output.lines().find(|line| line.contains("Stream") && line.contains("Video:"));
+    let audio_stream = output.lines().find(|line| line.contains("Stream") && line.contains("Audio:"));
+
+    // ", 29.97 fps, 29.97 tbr, 30k tbn". Rates from 1000 up are shortened to "1k", and when there's no fps the
+    // tbr (the guessed base rate) is the best there is
+    let framerate = video_stream.and_then(|line| {
+        let rate_re = Regex::new(r"(\d+(?:\.\d+)?)(k?) (fps|tbr)\b").unwrap();
+        let mut rates = rate_re.captures_iter(line);
+        let caps = rates.find(|caps| &caps[3] == "fps").or_else(|| rate_re.captures_iter(line).next())?;
+        let rate = caps[1].parse::<f64>().ok()? * if caps[2].is_empty() { 1.0 } else { 1000.0 };
+        (rate > 0.0).then(|| exact_framerate(rate))
+    });
+    let size = video_stream.and_then(|line| {
+        let caps = Regex::new(r"\b(\d{2,5})x(\d{2,5})\b").unwrap().captures(line)?;
+        Some((caps[1].parse::<u32>().ok()?, caps[2].parse::<u32>().ok()?))
+    });
+    // "Stream #0:0(und): Video: h264 (High), ..." -> "h264"
+    let codec = |line: &str, kind: &str| {
+        let codec = line.split(kind).nth(1)?.split(|c: char| c == ',' || c.is_whitespace()).find(|part| !part.is_empty())?;
+        Some(codec.to_string())
+    };
+
+    let color = video_stream.map(parse_color_info).unwrap_or_default();
+
+    // "Duration: 00:01:00.00, start: 0.000000, bitrate: 1000 kb/s" and "Audio: aac (LC), 48000 Hz, stereo, fltp,
+    // 128 kb/s"
+    let kbps = |line: &str| {
+        let caps = Regex::new(r"(\d+) kb/s").unwrap().captures(line)?;
+        Some(caps[1].parse::<u64>().ok()? * 1000)
+    };
+    let bitrate = output.lines().find(|line| line.contains("Duration:")).and_then(kbps);
+    let sample_rate = audio_stream.and_then(|line| {
+        let caps = Regex::new(r"(\d+) Hz").unwrap().captures(line)?;
+        caps[1].parse::<u32>().ok()
+    });
+    let channels = audio_stream.and_then(|line| {
+        line.split(", ").find_map(|part| match part.split('(').next()?.trim() {
+            "mono" => Some(1),
+            "stereo" => Some(2),
+            "2.1" => Some(3),
+            "quad" | "4.0" => Some(4),
+            "5.0" => Some(5),
+            "5.1" => Some(6),
+            "6.1" => Some(7),
+            "7.1" => Some(8),
+            layout => layout.strip_suffix(" channels")?.parse().ok(),
+        })
+    });
+
+    MediaInfo {
+        duration: parse_duration(output),
+        bitrate,
+        width: size.map(|(width, _)| width),
+        height: size.map(|(_, height)| height),
+        framerate,
+        video_codec: video_stream.and_then(|line| codec(line, "Video:")),
+        audio_codec: audio_stream.and_then(|line| codec(line, "Audio:")),
+        audio_sample_rate: sample_rate,
+        audio_channels: channels,
+        audio_bitrate: audio_stream.and_then(kbps),
+        has_video: video_stream.is_some(),
+        has_audio: audio_stream.is_some(),
+        // ffmpeg lists the field order next to the pixel format, e.g. "yuv420p(tv, top first)"
+        interlaced: video_stream.is_some_and(|line| line.contains("top first") || line.contains("bottom first")),
+        color_range: color.range,
+        colorspace: color.space,
+        color_primaries: color.primaries,
+        color_transfer: color.transfer,
+        tags: parse_global_tags(output),
+        raw: output.to_string(),
+    }
+}
+
+// The file's own "Metadata:" block sits right under "Input #0" and above "Duration:", indented by two with its
+// "key : value" lines by four. The streams' blocks are indented further
+fn parse_global_tags(output: &str) -> BTreeMap<String, String> {
+    output
+        .lines()
+        .skip_while(|line| !line.starts_with("Input #0"))
+        .skip(1)
+        .skip_while(|line| line.trim_end() != "  Metadata:")
+        .skip(1)
+        .take_while(|line| line.starts_with("    ") && !line.starts_with("     "))
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+#[derive(Default)]
+struct ColorInfo {
+    range: Option<String>,
+    space: Option<String>,
+    primaries: Option<String>,
+    transfer: Option<String>,
+}
+
+// The pixel format details, e.g. "yuv420p(tv, bt709, progressive)" or "yuv420p10le(tv, bt2020nc/bt2020/smpte2084)",
+// list the range and then either one name for all three color properties or space/primaries/transfer
+fn parse_color_info(line: &str) -> ColorInfo {
+    let mut color = ColorInfo::default();
+    let details = Regex::new(r", [0-9a-z]+\(([^()]*)\)").unwrap();
+    let Some(caps) = details.captures(line) else {
+        return color;
+    };
+    let known = |name: &str| (!name.is_empty() && name != "unknown" && name != "reserved").then(|| name.to_string());
+    for part in caps[1].split(',').map(str::trim) {
+        match part {
+            "tv" | "pc" => color.range = Some(part.to_string()),
+            "progressive" => {}
+            _ if part.contains(' ') => {}
+            _ => {
+                let names: Vec<&str> = part.split('/').collect();
+                let (space, primaries, transfer) = match names[..] {
+                    [all] => (all, all, all),
+                    [space, primaries, transfer] => (space, primaries, transfer),
+                    _ => continue,
+                };
+                color.space = known(space);
+                color.primaries = known(primaries);
+                color.transfer = known(transfer);
+            }
+        }
+    }
+    color
+}
+
+// Reads the "Duration: 00:01:02.50" line from ffmpeg's stream info
+fn parse_duration(output: &str) -> Option<f64> {
+    output.lines().find(|line| line.contains("Duration")).and_then(|line| {
+        let duration_str = line.split("Duration: ").nth(1)?.split(',').next()?;
+        let mut parts = duration_str.split(':');
+        let h: f64 = parts.next()?.parse().ok()?;
+        let m: f64 = parts.next()?.parse().ok()?;
+        let s: f64 = parts.next()?.parse().ok()?;
+        Some(h * 3600.0 + m * 60.0 + s)
+    })
+}
+
+/// ffmpeg rounds NTSC rates like 30000/1001 to "29.97" in its stream info, this restores the exact rate so frame
+/// counts and retimed rates don't drift by a frame every few seconds.
+pub fn exact_framerate(rate: f64) -> f64 {
+    let ntsc_base = (rate * 1.001).round();
+    if ntsc_base > 0.0 && (rate - ntsc_base * 1000.0 / 1001.0).abs() < 0.006 && (rate - ntsc_base).abs() > 0.006 {
+        ntsc_base * 1000.0 / 1001.0
+    } else {
+        rate
+    }
+}
+
+/// Formats a framerate for ffmpeg, as an exact fraction for NTSC rates (`30000/1001`).
+pub fn format_framerate(rate: f64) -> String {
+    let ntsc_base = (rate * 1.001).round();
+    if ntsc_base > 0.0 && (rate - ntsc_base * 1000.0 / 1001.0).abs() < 1e-6 && rate.fract() != 0.0 {
+        format!("{}/1001", ntsc_base * 1000.0)
+    } else {
+        rate.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROBED: &str = "\
+Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'clip.mp4':
+  Metadata:
+    major_brand     : isom
+    clippy_settings : 0123456789abcdef
+  Duration: 00:01:02.50, start: 0.000000, bitrate: 8000 kb/s
+  Stream #0:0[0x1](und): Video: h264 (High) (avc1 / 0x31637661), yuv420p(tv, bt709, progressive), 1920x1080 [SAR 1:1 DAR 16:9], 7800 kb/s, 29.97 fps, 29.97 tbr, 30k tbn (default)
+    Metadata:
+      handler_name    : VideoHandler
+  Stream #0:1[0x2](und): Audio: aac (LC) (mp4a / 0x6134706D), 48000 Hz, stereo, fltp, 192 kb/s (default)
+";
+
+    #[test]
+    fn parses_duration_and_exact_framerate() {
+        let (duration, framerate) = parse_probe_output(PROBED).unwrap();
+        assert_eq!(duration, 62.5);
+        assert_eq!(framerate, 30000.0 / 1001.0);
+    }
+
+    #[test]
+    fn counts_frames_at_the_exact_ntsc_rate() {
+        let info = MediaInfo { duration: Some(10.0), framerate: Some(exact_framerate(29.97)), ..MediaInfo::default() };
+        assert_eq!(info.framerate, Some(30000.0 / 1001.0));
+        assert_eq!(info.total_frames(), Some(300));
+        assert_eq!(exact_framerate(59.94), 60000.0 / 1001.0);
+        assert_eq!(exact_framerate(25.0), 25.0);
+        assert_eq!(format_framerate(exact_framerate(29.97)), "30000/1001");
+    }
+
+    #[test]
+    fn parses_streams_and_global_tags() {
+        let info = parse_media_info(PROBED);
+        assert_eq!((info.width, info.height), (Some(1920), Some(1080)));
+        assert_eq!(info.video_codec.as_deref(), Some("h264"));
+        assert_eq!(info.audio_codec.as_deref(), Some("aac"));
+        assert_eq!((info.audio_sample_rate, info.audio_channels), (Some(48000), Some(2)));
+        assert_eq!(info.bitrate, Some(8_000_000));
+        assert_eq!(info.tags.get("clippy_settings").map(String::as_str), Some("0123456789abcdef"));
+        assert_eq!(info.tags.len(), 2);
+    }
+
+    #[test]
+    fn unknown_duration_is_an_error() {
+        let stderr = PROBED.replace("Duration: 00:01:02.50", "Duration: N/A");
+        let error = parse_probe_output(&stderr).unwrap_err();
+        assert!(matches!(error, ProcessingError::ProbeParse(ref message) if message.contains("duration")));
+    }
+
+    #[test]
+    fn missing_fps_falls_back_to_tbr() {
+        let stderr = PROBED.replace("29.97 fps, ", "").replace("29.97 tbr", "25 tbr");
+        assert_eq!(parse_probe_output(&stderr).unwrap(), (62.5, 25.0));
+    }
+
+    #[test]
+    fn shortened_rates_are_scaled() {
+        let stderr = PROBED.replace("29.97 fps, 29.97 tbr", "1k fps, 1k tbr");
+        assert_eq!(parse_probe_output(&stderr).unwrap().1, 1000.0);
+    }
+
+    #[test]
+    fn parses_long_mkv_output() {
+        let stderr = "\
+Input #0, matroska,webm, from 'talk.mkv':
+  Duration: 01:02:03.04, start: 0.000000, bitrate: 12000 kb/s
+  Stream #0:0: Video: vp9 (Profile 0), yuv420p(tv, bt709), 3840x2160, SAR 1:1 DAR 16:9, 23.98 fps, 23.98 tbr, 1k tbn (default)
+  Stream #0:1(eng): Audio: opus, 48000 Hz, stereo, fltp (default)
+";
+        assert_eq!(parse_probe_output(stderr).unwrap(), (3723.04, 24000.0 / 1001.0));
+    }
+
+    #[test]
+    fn audio_only_input_has_no_framerate() {
+        let stderr = "\
+Input #0, mp3, from 'song.mp3':
+  Duration: 00:03:00.00, start: 0.025057, bitrate: 320 kb/s
+  Stream #0:0: Audio: mp3, 44100 Hz, stereo, fltp, 320 kb/s
+";
+        let info = parse_media_info(stderr);
+        assert!(!info.has_video && info.has_audio);
+        let error = parse_probe_output(stderr).unwrap_err();
+        assert!(matches!(error, ProcessingError::ProbeParse(ref message) if message.contains("framerate")));
+    }
+
+    #[test]
+    fn cache_probes_an_unchanged_file_once() {
+        let path = std::env::temp_dir().join(format!("clippy-probe-cache-{}.mp4", std::process::id()));
+        fs::write(&path, b"").unwrap();
+        let path = path.to_string_lossy().into_owned();
+        let cache = ProbeCache::default();
+        let probes = std::cell::Cell::new(0);
+        let probe = |_: &str, _: &str| {
+            probes.set(probes.get() + 1);
+            Ok(MediaInfo { duration: Some(10.0), ..Default::default() })
+        };
+
+        assert_eq!(cache.get_or_probe("ffmpeg", &path, probe).unwrap().duration, Some(10.0));
+        assert_eq!(cache.get_or_probe("ffmpeg", &path, probe).unwrap().duration, Some(10.0));
+        assert_eq!(probes.get(), 1);
+        // Another ffmpeg may well read the file differently
+        cache.get_or_probe("/opt/ffmpeg/bin/ffmpeg", &path, probe).unwrap();
+        assert_eq!(probes.get(), 2);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn cache_probes_streams_every_time() {
+        let cache = ProbeCache::default();
+        let probes = std::cell::Cell::new(0);
+        let probe = |_: &str, _: &str| {
+            probes.set(probes.get() + 1);
+            Ok(MediaInfo::default())
+        };
+        cache.get_or_probe("ffmpeg", "https://example.com/live.m3u8", probe).unwrap();
+        cache.get_or_probe("ffmpeg", "https://example.com/live.m3u8", probe).unwrap();
+        assert_eq!(probes.get(), 2);
+    }
+
+    #[test]
+    fn unparseable_output_is_an_error() {
+        assert!(parse_probe_output("clip.mp4: No such file or directory").is_err());
+        assert!(parse_probe_output("").is_err());
+    }
+
+    #[test]
+    fn ffprobe_is_looked_for_next_to_ffmpeg() {
+        let dir = std::env::temp_dir().join(format!("clippy-ffprobe-{}", std::process::id()));
+        let (custom, bin) = (dir.join("custom"), dir.join("bin"));
+        fs::create_dir_all(&custom).unwrap();
+        fs::create_dir_all(&bin).unwrap();
+        for ffprobe in [custom.join("ffprobe-6"), bin.join("ffprobe")] {
+            fs::write(&ffprobe, "#!/bin/sh\n").unwrap();
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(&ffprobe, fs::Permissions::from_mode(0o755)).unwrap();
+            }
+        }
+        let path_var = env::join_paths([&bin]).unwrap();
+        let found = |ffmpeg_path: &Path| ffprobe_path(&ffmpeg_path.to_string_lossy(), Some(&path_var));
+
+        assert_eq!(found(&custom.join("ffmpeg-6")), Some(custom.join("ffprobe-6").to_string_lossy().into_owned()));
+        assert_eq!(found(Path::new("ffmpeg")), Some(bin.join("ffprobe").to_string_lossy().into_owned()));
+        // There's no ffprobe next to this one, so ffmpeg itself does the probing
+        assert_eq!(found(&custom.join("ffmpeg")), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
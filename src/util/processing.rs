@@ -1,10 +1,39 @@
-use log::info;
+use log::{error, info, warn};
 use regex::Regex;
-use std::process::{Command, Stdio};
-use std::io::{BufRead, BufReader};
-use indicatif::{ProgressBar, ProgressStyle};
+use serde::Serialize;
+use std::env;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use std::process::{self, Child, Command, Stdio};
+use std::io::{self, BufRead, BufReader};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
 
-use crate::util::config::{Config, Settings};
+use crate::util::config::{self, Config, FadeDuration, FadeKind, FadeSpec, GpuEncoder, Settings, SpeedSegment, VideoCodec};
+use crate::util::error::ProcessingError;
+use crate::util::probe;
+use crate::util::shell::shell_quote;
+
+// How much shorter than the video a background track can be before it's worth a warning
+const BACKGROUND_AUDIO_SLACK_SECS: f64 = 1.0;
+
+// Containers (by extension or ffmpeg format name) that hold VP9 and AV1 and that players expect them in
+const WEB_CODEC_CONTAINERS: &[&str] = &["webm", "mkv", "matroska", "mp4", "m4v", "dash"];
+
+// Containers (by extension or ffmpeg format name) written by ffmpeg's mov muxer, the ones -movflags applies to
+const FASTSTART_CONTAINERS: &[&str] = &["mp4", "mov", "m4v", "m4a", "3gp", "ipod"];
+
+// The global metadata tag skip_existing = "settings" stores the settings' hash under
+const SETTINGS_TAG: &str = "clippy_settings";
+
+// Sample rates every platform and audio encoder takes, for audio_sample_rate
+const COMMON_SAMPLE_RATES: &[u32] = &[44100, 48000, 96000];
 
 fn parse_ffmpeg_progress(line: &str) -> Option<f64> {
     let re = Regex::new(r"time=(\d+):(\d+):(\d+\.\d+)").unwrap();
@@ -16,272 +45,3636 @@ fn parse_ffmpeg_progress(line: &str) -> Option<f64> {
     })
 }
 
-fn run_ffmpeg_command(ffmpeg_command: &[String], duration: f64, advanced_log: bool) -> Result<(), String> {
-    let mut command = Command::new(&ffmpeg_command[0]);
-    command.args(&ffmpeg_command[1..]);
+// Paths inside a filter graph need their colons (e.g. Windows drive letters) escaped, and quoting keeps the
+// graph parser from eating the escapes before the filter sees them
+fn escape_filter_path(path: &str) -> String {
+    format!("'{}'", path.replace('\\', "/").replace(':', "\\:"))
+}
 
-    if advanced_log {
-        let status = command.status().map_err(|e| e.to_string())?;
-        if !status.success() {
-            return Err(format!("FFmpeg command failed with status: {}", status));
+/// An intro or outro clip, `input` counting from the first of them.
+struct Bumper {
+    input: usize,
+    duration: f64,
+    has_audio: bool,
+}
+
+// Length of an intro/outro and whether it has an audio track
+fn probe_bumper(ffmpeg_path: &str, path: &str) -> Result<(f64, bool), ProcessingError> {
+    let info = probe::probe_media(ffmpeg_path, path)?;
+    let duration = info
+        .duration
+        .ok_or_else(|| ProcessingError::ProbeParse(format!("Could not determine the duration of {}", path)))?;
+    Ok((duration, info.has_audio))
+}
+
+// Concatenates the intro, the main clip, the concat_inputs clips and the outro in that order, producing [cv] and
+// [ca]. concat needs every piece in the same size and format, so the bumpers are scaled and padded to the main clip,
+// everything is brought to its framerate and a common audio layout, and a bumper without sound gets silence
+fn bumper_graph(
+    (main_video, main_audio): (&str, &str),
+    main_trim: Option<(f64, f64)>,
+    (intro, clips, outro): (Option<&Bumper>, &[Bumper], Option<&Bumper>),
+    first_input: usize,
+    (width, height): (u32, u32),
+    framerate: f64,
+) -> String {
+    let video_format = format!("setsar=1,fps={},format=yuv420p", framerate);
+    let audio_format = "aresample=48000,aformat=sample_fmts=fltp:channel_layouts=stereo";
+
+    let mut graph = Vec::new();
+    let mut concat_inputs = String::new();
+    let mut count = 0;
+    let push_bumper = |graph: &mut Vec<String>, concat_inputs: &mut String, name: &str, bumper: &Bumper| {
+        let input = first_input + bumper.input;
+        graph.push(format!(
+            "[{input}:v]scale={width}:{height}:force_original_aspect_ratio=decrease,\
+             pad={width}:{height}:(ow-iw)/2:(oh-ih)/2,{video_format}[{name}v]"
+        ));
+        if bumper.has_audio {
+            graph.push(format!("[{input}:a]{audio_format}[{name}a]"));
+        } else {
+            graph.push(format!("anullsrc=r=48000:cl=stereo,atrim=duration={}[{name}a]", bumper.duration));
         }
+        concat_inputs.push_str(&format!("[{name}v][{name}a]"));
+    };
+
+    if let Some(intro) = intro {
+        push_bumper(&mut graph, &mut concat_inputs, "intro", intro);
+        count += 1;
+    }
+    let (video_trim, audio_trim) = match main_trim {
+        Some((start, end)) => (
+            format!("trim=start={start}:end={end},setpts=PTS-STARTPTS,"),
+            format!("atrim=start={start}:end={end},asetpts=PTS-STARTPTS,"),
+        ),
+        None => (String::new(), String::new()),
+    };
+    graph.push(format!("{main_video}{video_trim}{video_format}[mainv]"));
+    graph.push(format!("{main_audio}{audio_trim}{audio_format}[maina]"));
+    concat_inputs.push_str("[mainv][maina]");
+    count += 1;
+    for (index, clip) in clips.iter().enumerate() {
+        push_bumper(&mut graph, &mut concat_inputs, &format!("clip{}", index), clip);
+        count += 1;
+    }
+    if let Some(outro) = outro {
+        push_bumper(&mut graph, &mut concat_inputs, "outro", outro);
+        count += 1;
+    }
+
+    graph.push(format!("{}concat=n={}:v=1:a=1[cv][ca]", concat_inputs, count));
+    graph.join(";")
+}
+
+// Joins the main clip and the second input with a crossfade, producing [cv] and [ca]. xfade needs both clips in
+// the same size, framerate and format like concat does, and starts the transition `duration` seconds before the
+// end of the main clip
+fn crossfade_graph(
+    (main_video, main_audio): (&str, &str),
+    main_trim: Option<(f64, f64)>,
+    main_length: f64,
+    (second, duration): (&Bumper, f64),
+    second_input: usize,
+    (width, height): (u32, u32),
+    framerate: f64,
+) -> String {
+    let video_format = format!("setsar=1,fps={},format=yuv420p", framerate);
+    let audio_format = "aresample=48000,aformat=sample_fmts=fltp:channel_layouts=stereo";
+    let (video_trim, audio_trim) = match main_trim {
+        Some((start, end)) => (
+            format!("trim=start={start}:end={end},setpts=PTS-STARTPTS,"),
+            format!("atrim=start={start}:end={end},asetpts=PTS-STARTPTS,"),
+        ),
+        None => (String::new(), String::new()),
+    };
+
+    let mut graph = vec![
+        format!("{main_video}{video_trim}{video_format}[mainv]"),
+        format!("{main_audio}{audio_trim}{audio_format}[maina]"),
+        format!(
+            "[{second_input}:v]scale={width}:{height}:force_original_aspect_ratio=decrease,\
+             pad={width}:{height}:(ow-iw)/2:(oh-ih)/2,{video_format}[secondv]"
+        ),
+    ];
+    if second.has_audio {
+        graph.push(format!("[{second_input}:a]{audio_format}[seconda]"));
     } else {
-        command.stderr(Stdio::piped()).stdout(Stdio::null());
-        let mut child = command.spawn().map_err(|e| e.to_string())?;
-        let stderr = child.stderr.take().ok_or("Failed to capture stderr")?;
-        let reader = BufReader::new(stderr);
-
-        let progress = ProgressBar::new(duration as u64);
-        progress.set_style(
-            ProgressStyle::default_bar()
-                .template("[{elapsed_precise}] {bar:40.cyan/blue} {percent}% ({pos}/{len}, {eta})")
-                .unwrap()
-                .progress_chars("#>-")
-        );
+        graph.push(format!("anullsrc=r=48000:cl=stereo,atrim=duration={}[seconda]", second.duration));
+    }
+    graph.push(format!("[mainv][secondv]xfade=transition=fade:duration={}:offset={}[cv]", duration, main_length - duration));
+    graph.push(format!("[maina][seconda]acrossfade=d={}[ca]", duration));
+    graph.join(";")
+}
 
-        for line in reader.lines() {
-            if let Ok(line) = line {
-                if let Some(elapsed) = parse_ffmpeg_progress(&line) {
-                    progress.set_position(elapsed as u64);
-                }
-            }
-        }
+// Where `watermark_position` puts the overlay, 10 pixels in from the edges
+fn watermark_coordinates(position: &str) -> &'static str {
+    match position {
+        "top-left" => "10:10",
+        "top-right" => "main_w-overlay_w-10:10",
+        "bottom-left" => "10:main_h-overlay_h-10",
+        "center" => "(main_w-overlay_w)/2:(main_h-overlay_h)/2",
+        _ => "main_w-overlay_w-10:main_h-overlay_h-10",
+    }
+}
 
-        let status = child.wait().map_err(|e| e.to_string())?;
-        if !status.success() {
-            return Err(format!("FFmpeg command failed with status: {}", status));
-        }
+// Whether the hardware encoder can be used. It's only in the encoder list of ffmpeg builds that support it, and
+// without it ffmpeg would only fail once the encode starts, so that's an error up front unless `fallback` allows
+// libx265 instead
+fn gpu_encoder_available(ffmpeg_path: &str, encoder: GpuEncoder, fallback: bool) -> Result<bool, ProcessingError> {
+    let name = encoder.ffmpeg_name();
+    if probe::encoders(ffmpeg_path)?.iter().any(|listed| listed == name) {
+        return Ok(true);
+    }
+    if fallback {
+        warn!("This ffmpeg has no {} encoder, falling back to libx265 on the CPU", name);
+        Ok(false)
+    } else {
+        Err(ProcessingError::ConfigInvalid(format!(
+            "The ffmpeg at {} has no {} encoder for the GPU, set gpu_fallback (or pass --gpu-fallback) to encode with \
+             libx265 instead",
+            ffmpeg_path, name
+        )))
     }
+}
 
-    Ok(())
+// Turns the picture clockwise by `rotate` degrees, transpose=1 is a quarter turn clockwise and transpose=2 one
+// counter-clockwise
+fn rotation_filter(rotate: Option<&str>) -> Option<&'static str> {
+    match rotate? {
+        "90" => Some("transpose=1"),
+        "180" => Some("transpose=2,transpose=2"),
+        "270" => Some("transpose=2"),
+        _ => None,
+    }
 }
 
-pub fn add_fade_effects(config: &Config) -> Result<(), String> {
-    config.validate()?;
+// The filter for `color_effect`. grayscale takes the saturation out and sepia mixes the channels with the usual
+// sepia weights, anything else is already a filter
+fn color_effect_filter(effect: Option<&str>) -> Option<String> {
+    match effect?.to_lowercase().as_str() {
+        "grayscale" => Some("hue=s=0".to_string()),
+        "sepia" => Some("colorchannelmixer=.393:.769:.189:0:.349:.686:.168:0:.272:.534:.131".to_string()),
+        "none" => None,
+        _ => effect.map(str::to_string),
+    }
+}
 
-    let Settings {
-        input_video_path,
-        output_video_path,
-        ffmpeg_path,
-        use_gpu,
-        video_bitrate,
-        crf,
-        upscale_resolution,
-        background_audio_path,
-        audio_start_time,
-        replace_audio,
-        original_audio_volume,
-        background_audio_volume,
-        clip_start_time,
-        clip_end_time,
-        video_speed,
-        advanced_log,
-        fade_in_duration,
-        fade_out_duration,
-    } = &config.settings;
+// Whether the input is already in the output codec at the requested resolution, so encoding it again would only
+// lose quality
+fn already_conforms(info: &probe::MediaInfo, codec: VideoCodec, upscale_resolution: Option<&str>, max_resolution: Option<&str>) -> bool {
+    let parse_size = |resolution: &str| -> Option<(u32, u32)> {
+        let (width, height) = resolution.split_once('x')?;
+        Some((width.parse().ok()?, height.parse().ok()?))
+    };
+    let resolution_matches = match (upscale_resolution, max_resolution) {
+        (Some(resolution), _) if resolution.to_lowercase() != "none" => {
+            let size = parse_size(resolution);
+            size.is_some() && size == info.width.zip(info.height)
+        }
+        (_, Some(resolution)) if resolution.to_lowercase() != "none" => match (parse_size(resolution), info.width.zip(info.height)) {
+            (Some((max_width, max_height)), Some((width, height))) => width <= max_width && height <= max_height,
+            _ => false,
+        },
+        _ => true,
+    };
+    info.video_codec.as_deref() == Some(codec.probe_name()) && resolution_matches
+}
 
-    let probe_command = Command::new(ffmpeg_path)
-        .arg("-i")
-        .arg(input_video_path)
-        .arg("-hide_banner")
-        .output()
-        .map_err(|e| e.to_string())?;
-    let output = String::from_utf8_lossy(&probe_command.stderr);
+// Fits the picture inside the box without ever growing it. The aspect ratio is kept and the size stays even, which
+// the 4:2:0 encoders need
+fn max_resolution_filter(max_resolution: Option<&str>) -> Option<String> {
+    let (width, height) = max_resolution?.split_once('x')?;
+    Some(format!(
+        "scale='min(iw,{})':'min(ih,{})':force_original_aspect_ratio=decrease:force_divisible_by=2",
+        width, height
+    ))
+}
 
-    let duration = output
-        .lines()
-        .find(|line| line.contains("Duration"))
-        .and_then(|line| {
-            let duration_str = line.split("Duration: ").nth(1)?.split(',').next()?;
-            let mut parts = duration_str.split(':');
-            let h: f64 = parts.next()?.parse().ok()?;
-            let m: f64 = parts.next()?.parse().ok()?;
-            let s: f64 = parts.next()?.parse().ok()?;
-            Some(h * 3600.0 + m * 60.0 + s)
-        })
-        .ok_or("Could not determine video duration")?;
+// atempo only takes factors between 0.5 and 2.0 on older ffmpeg builds and gets less exact towards the ends of
+// that range, so bigger changes are split into a chain of steps that multiply up to the factor
+fn atempo_chain(factor: f64) -> String {
+    let mut steps = Vec::new();
+    let mut remaining = factor;
+    while remaining > 2.0 {
+        steps.push(2.0);
+        remaining /= 2.0;
+    }
+    while remaining < 0.5 {
+        steps.push(0.5);
+        remaining /= 0.5;
+    }
+    steps.push(remaining);
+    steps.iter().map(|step| format!("atempo={}", step)).collect::<Vec<_>>().join(",")
+}
 
-    let framerate = output
-        .lines()
-        .find(|line| line.contains("Stream") && line.contains("Video"))
-        .and_then(|line| {
-            let fps_str = line.split("fps").next()?.split_whitespace().last()?;
-            fps_str.parse::<f64>().ok()
-        })
-        .ok_or("Could not determine video framerate")?;
+// Splits the clip into the given speed segments plus real-time pieces around them, see piece_graph
+fn speed_segment_graph(segments: &[SpeedSegment], start: f64, end: f64) -> (String, f64) {
+    let mut segments: Vec<&SpeedSegment> = segments.iter().collect();
+    segments.sort_by(|a, b| a.start.total_cmp(&b.start));
 
-    let fade_in_duration = fade_in_duration.unwrap_or(3.0);
-    let fade_out_duration = fade_out_duration.unwrap_or(3.0);
+    let mut pieces = Vec::new();
+    let mut cursor = start;
+    for segment in segments {
+        if segment.start > cursor {
+            pieces.push((cursor, segment.start, 1.0));
+        }
+        pieces.push((segment.start, segment.end, segment.factor));
+        cursor = segment.end;
+    }
+    if cursor < end {
+        pieces.push((cursor, end, 1.0));
+    }
+    piece_graph(pieces)
+}
 
-    let clip_start_time_float = if let Some(ref clip_start_time) = clip_start_time {
-        if clip_start_time.to_lowercase() == "none" {
-            0.0
+// Cuts the (start, end, speed factor) pieces out of the source and concatenates them, returning the graph
+// (producing [sv] and [sa]) and the length of the result
+fn piece_graph(pieces: Vec<(f64, f64, f64)>) -> (String, f64) {
+    let count = pieces.len();
+    let split_labels = |prefix: &str| (0..count).map(|i| format!("[{}{}]", prefix, i)).collect::<String>();
+    let mut graph = vec![
+        format!("[0:v]split={}{}", count, split_labels("vs")),
+        format!("[0:a]asplit={}{}", count, split_labels("as")),
+    ];
+    let mut concat_inputs = String::new();
+    let mut output_duration = 0.0;
+
+    for (i, (piece_start, piece_end, factor)) in pieces.into_iter().enumerate() {
+        let (setpts, atempo) = if factor == 1.0 {
+            ("PTS-STARTPTS".to_string(), String::new())
         } else {
-            clip_start_time.parse::<f64>().map_err(|_| "Invalid clip_start_time")?
+            (format!("(PTS-STARTPTS)/{}", factor), format!(",{}", atempo_chain(factor)))
+        };
+        graph.push(format!("[vs{i}]trim=start={piece_start}:end={piece_end},setpts={setpts}[sv{i}]"));
+        graph.push(format!("[as{i}]atrim=start={piece_start}:end={piece_end},asetpts=PTS-STARTPTS{atempo}[sa{i}]"));
+        concat_inputs.push_str(&format!("[sv{i}][sa{i}]"));
+        output_duration += (piece_end - piece_start) / factor;
+    }
+    graph.push(format!("{}concat=n={}:v=1:a=1[sv][sa]", concat_inputs, count));
+
+    (graph.join(";"), output_duration)
+}
+
+/// Video and audio fade filters for `fades`, in the order they are listed. A finished fade-out keeps the picture
+/// dark and a pending fade-in does the same, so each fade is only enabled between its neighbours. The fade times
+/// are relative to the clip, `timeline_start` is where the clip starts on the graph's timeline.
+fn fade_chain(
+    fades: &[FadeSpec],
+    curves: (Option<&str>, Option<&str>),
+    timeline_start: f64,
+) -> (Vec<String>, Vec<String>) {
+    fades
+        .iter()
+        .enumerate()
+        .map(|(index, fade)| {
+            let start = timeline_start + fade.start;
+            let (kind, enable) = match fade.kind {
+                FadeKind::In if index > 0 => ("in", format!(":enable='gte(t,{})'", start)),
+                FadeKind::In => ("in", String::new()),
+                FadeKind::Out => match fades.get(index + 1) {
+                    Some(next) => ("out", format!(":enable='lt(t,{})'", timeline_start + next.start)),
+                    None => ("out", String::new()),
+                },
+            };
+            let color = fade.color.as_deref().map(|color| format!(":c={}", color)).unwrap_or_default();
+            let curve = match fade.kind {
+                FadeKind::In => curve_option(curves.0),
+                FadeKind::Out => curve_option(curves.1),
+            };
+            (
+                format!("fade=t={}:st={}:d={}{}{}", kind, start, fade.duration, color, enable),
+                format!("afade=t={}:st={}:d={}{}{}", kind, start, fade.duration, curve, enable),
+            )
+        })
+        .unzip()
+}
+
+// Only afade has a curve option, ffmpeg's video fade is always linear
+fn curve_option(curve: Option<&str>) -> String {
+    curve.map(|curve| format!(":curve={}", curve)).unwrap_or_default()
+}
+
+/// ffmpeg's metadata format for a chapters file of "timestamp title" lines, in seconds of the output. Each chapter
+/// runs until the next one starts and the last one until `output_length`. Blank lines and `#` comments are skipped.
+fn chapters_metadata(text: &str, output_length: f64) -> Result<String, String> {
+    let mut chapters: Vec<(f64, &str)> = Vec::new();
+    for (number, line) in text.lines().enumerate().map(|(index, line)| (index + 1, line.trim())) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
         }
-    } else {
-        0.0
+        let (time, title) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let start = config::parse_time(time).map_err(|e| format!("line {}: {}", number, e))?;
+        if start >= output_length {
+            return Err(format!("line {}: {} is past the end of the {:.2}s output", number, time, output_length));
+        }
+        if let Some((previous, _)) = chapters.last().filter(|(previous, _)| start <= *previous) {
+            return Err(format!("line {}: {} has to come after the chapter before it at {}s", number, time, previous));
+        }
+        chapters.push((start, title.trim()));
+    }
+    if chapters.is_empty() {
+        return Err("it has no chapters".to_string());
+    }
+
+    // = ; # and \ mean something in the format, a title has them escaped
+    let escape = |title: &str| {
+        title.chars().fold(String::new(), |mut escaped, c| {
+            if "=;#\\".contains(c) {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+            escaped
+        })
     };
+    let millis = |seconds: f64| (seconds * 1000.0).round() as u64;
+    let mut metadata = String::from(";FFMETADATA1\n");
+    for (index, (start, title)) in chapters.iter().enumerate() {
+        let end = chapters.get(index + 1).map_or(output_length, |(next, _)| *next);
+        let title = if title.is_empty() { format!("Chapter {}", index + 1) } else { escape(title) };
+        metadata.push_str(&format!(
+            "[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            millis(*start),
+            millis(end),
+            title
+        ));
+    }
+    Ok(metadata)
+}
 
-    let clip_end_time_float = if let Some(ref clip_end_time) = clip_end_time {
-        if clip_end_time.to_lowercase() == "none" {
-            duration
-        } else {
-            clip_end_time.parse::<f64>().map_err(|_| "Invalid clip_end_time")?
+// The filter graphs (-filter_complex, -vf or -af) of the named commands for dump_filtergraph, each under a "# name"
+// line with a chain per line. ffmpeg skips whitespace between chains, so the lines still read as the same graph
+fn filtergraph_dump(commands: &[(&str, &[String])]) -> String {
+    let mut dump = String::new();
+    for (name, command) in commands {
+        let graphs = command.windows(2).filter(|pair| ["-filter_complex", "-vf", "-af"].contains(&pair[0].as_str()));
+        for pair in graphs {
+            dump.push_str(&format!("# {} ({})\n", name, pair[0]));
+            for chain in split_chains(&pair[1]) {
+                dump.push_str(chain.trim());
+                dump.push('\n');
+            }
+            dump.push('\n');
         }
-    } else {
-        duration
+    }
+    dump
+}
+
+// Splits a graph at its ";"s, leaving the ones that are escaped or inside '...' alone, e.g. in a drawtext text
+fn split_chains(graph: &str) -> Vec<&str> {
+    let mut chains = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (index, c) in graph.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '\'' => quoted = !quoted,
+            ';' if !quoted => {
+                chains.push(&graph[start..index + 1]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    chains.push(&graph[start..]);
+    chains
+}
+
+// Moves an existing output out of the way to `<output>.bak`, or `<output>.<unix time>.bak` when that is taken too.
+fn backup_output(output_video_path: &str) -> Result<String, ProcessingError> {
+    let mut backup = format!("{}.bak", output_video_path);
+    if Path::new(&backup).exists() {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0);
+        backup = format!("{}.{}.bak", output_video_path, now);
+    }
+    fs::rename(output_video_path, &backup).map_err(|source| ProcessingError::Io {
+        context: format!("Failed to back up the existing output {}", output_video_path),
+        source,
+    })?;
+    Ok(backup)
+}
+
+/// Decodes the whole output and fails if ffmpeg reports any errors, which catches truncated or corrupt files.
+fn verify_output_file(ffmpeg_path: &str, output_video_path: &str) -> Result<(), ProcessingError> {
+    let verify_command = Command::new(ffmpeg_path)
+        .args(["-v", "error", "-i", output_video_path, "-f", "null", "-"])
+        .output()
+        .map_err(|source| ProcessingError::FfmpegSpawn { path: ffmpeg_path.to_string(), source })?;
+    let errors: Vec<_> = String::from_utf8_lossy(&verify_command.stderr)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(str::to_string)
+        .collect();
+
+    if errors.is_empty() && verify_command.status.success() {
+        info!("Output verified, no decode errors found");
+        return Ok(());
+    }
+    for line in errors.iter().take(10) {
+        warn!("{}", line);
+    }
+    Err(ProcessingError::OutputInvalid(format!(
+        "Output {} failed verification with {} decode error(s)",
+        output_video_path,
+        errors.len()
+    )))
+}
+
+/// Probes the finished output and warns when its length is off from what the speed change should produce.
+fn check_output_duration(ffmpeg_path: &str, output_video_path: &str, expected: f64) {
+    let actual = probe::probe_media(ffmpeg_path, output_video_path).ok().and_then(|info| info.duration);
+
+    match actual {
+        Some(actual) if (actual - expected).abs() > (expected * 0.005).max(0.1) => warn!(
+            "The output is {:.2}s long but {:.2}s was expected after the speed change, audio and video may be out of sync",
+            actual, expected
+        ),
+        Some(_) => {}
+        None => warn!("Could not probe the duration of {} to check it", output_video_path),
+    }
+}
+
+// A bitrate as ffmpeg takes it, "25M", "5000k" or plain bits/s, in bits/s. The suffixes are decimal like ffmpeg's
+fn parse_bitrate(bitrate: &str) -> Option<u64> {
+    let (number, multiplier) = match bitrate.chars().last()? {
+        'k' | 'K' => (&bitrate[..bitrate.len() - 1], 1e3),
+        'M' => (&bitrate[..bitrate.len() - 1], 1e6),
+        'G' => (&bitrate[..bitrate.len() - 1], 1e9),
+        _ => (bitrate, 1.0),
     };
+    number.parse::<f64>().ok().filter(|rate| *rate >= 0.0).map(|rate| (rate * multiplier) as u64)
+}
 
-    let fade_out_start_time = clip_end_time_float - fade_out_duration;
+/// Bytes a `length` seconds long output at the given video and audio bitrates comes to, leaving out the container.
+fn estimated_output_size(length: f64, video_bitrate: u64, audio_bitrate: u64) -> u64 {
+    ((video_bitrate + audio_bitrate) as f64 * length / 8.0) as u64
+}
 
-    let mut video_filters = vec![format!(
-        "fade=t=in:st=0:d={},fade=t=out:st={}:d={}",
-        fade_in_duration, fade_out_start_time, fade_out_duration
-    )];
+// 1234567890 -> "1.23 GB"
+fn format_size(bytes: u64) -> String {
+    match bytes as f64 {
+        bytes if bytes >= 1e9 => format!("{:.2} GB", bytes / 1e9),
+        bytes => format!("{:.1} MB", bytes / 1e6),
+    }
+}
 
-    if let Some(ref resolution) = upscale_resolution {
-        if resolution.to_lowercase() != "none" {
-            video_filters.push(format!("scale={}", resolution));
+fn parse_ffmpeg_speed(line: &str) -> Option<f64> {
+    let re = Regex::new(r"speed=\s*(\d+(?:\.\d+)?)x").unwrap();
+    re.captures(line).and_then(|caps| caps[1].parse().ok())
+}
+
+fn parse_ffmpeg_frame(line: &str) -> Option<u64> {
+    let re = Regex::new(r"frame=\s*(\d+)").unwrap();
+    re.captures(line).and_then(|caps| caps[1].parse().ok())
+}
+
+// A line of ffmpeg's stderr, a progress update or anything else it printed
+enum StderrLine {
+    Progress(Progress),
+    Other(String),
+}
+
+// Reads ffmpeg's stderr until it ends or `on_line` returns false, turning the status lines ("frame=  150 ...
+// time=00:00:05.00 ... speed=1.5x", or -progress's out_time=) into progress through an output of `duration` seconds.
+// ffmpeg ends a status line with a carriage return to overwrite it in a terminal, so those split lines as well
+fn read_ffmpeg_output<R: BufRead>(mut reader: R, duration: Option<f64>, mut on_line: impl FnMut(StderrLine) -> bool) {
+    let mut buffer = Vec::new();
+    while reader.read_until(b'\n', &mut buffer).is_ok_and(|read| read > 0) {
+        for line in String::from_utf8_lossy(&buffer).split(['\r', '\n']).filter(|line| !line.is_empty()) {
+            let line = match parse_ffmpeg_progress(line) {
+                Some(elapsed) => StderrLine::Progress(Progress {
+                    elapsed,
+                    total: duration,
+                    speed: parse_ffmpeg_speed(line),
+                    frame: parse_ffmpeg_frame(line),
+                    total_frames: None,
+                }),
+                None => StderrLine::Other(line.to_string()),
+            };
+            if !on_line(line) {
+                return;
+            }
         }
+        buffer.clear();
     }
-    if *video_speed != 1.0 {
-        video_filters.push(format!("setpts={}*PTS", 1.0 / video_speed));
+}
+
+/// A single progress update parsed from ffmpeg's stderr.
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    /// Seconds of output encoded so far.
+    pub elapsed: f64,
+    /// Expected length of the output in seconds, unknown when reading from stdin.
+    pub total: Option<f64>,
+    /// Encoding speed relative to realtime, when ffmpeg reports it.
+    pub speed: Option<f64>,
+    /// Frames encoded so far, when ffmpeg reports it.
+    pub frame: Option<u64>,
+    /// Expected number of frames in the output, unknown when its length or framerate is.
+    pub total_frames: Option<u64>,
+}
+
+impl Progress {
+    /// How far along the encode is in percent. With `by_frames` it's counted in frames when both frame counts are
+    /// known, otherwise (and by default) in seconds.
+    pub fn percent(&self, by_frames: bool) -> Option<f64> {
+        let frames = self.frame.zip(self.total_frames.filter(|total| *total > 0)).filter(|_| by_frames);
+        let fraction = match frames {
+            Some((frame, total)) => Some(frame as f64 / total as f64),
+            None => self.total.filter(|total| *total > 0.0).map(|total| self.elapsed / total),
+        };
+        fraction.map(|fraction| (fraction * 100.0).min(100.0))
     }
+}
 
-    let video_filter_str = video_filters.join(",");
+// `label` goes in front of the default bar so the files of a batch can be told apart
+fn progress_bar(total: Option<f64>, template: Option<&str>, refresh_hz: Option<u32>, label: Option<&str>) -> ProgressBar {
+    let progress = match total {
+        Some(total) => ProgressBar::new(total as u64),
+        // Without a known length there's nothing to fill a bar against
+        None => ProgressBar::new_spinner(),
+    };
+    if let Some(hz) = refresh_hz {
+        progress.set_draw_target(ProgressDrawTarget::stderr_with_hz(hz as u8));
+    }
 
-    let mut audio_filters = vec![format!(
-        "afade=t=in:st=0:d={},afade=t=out:st={}:d={}",
-        fade_in_duration, fade_out_start_time, fade_out_duration
-    )];
-    if *video_speed != 1.0 {
-        audio_filters.push(format!("atempo={}", video_speed));
+    let custom_style = template.and_then(|template| match ProgressStyle::with_template(template) {
+        Ok(style) => Some(style),
+        Err(e) => {
+            warn!("Invalid progress_template ({}), using the default progress bar", e);
+            None
+        }
+    });
+    let prefix = if label.is_some() { "{prefix} " } else { "" };
+    let style = match (custom_style, total) {
+        (Some(style), _) => style,
+        (None, Some(_)) => ProgressStyle::default_bar()
+            .template(&format!("{}[{{elapsed_precise}}] {{bar:40.cyan/blue}} {{percent}}% ({{pos}}/{{len}}, {{eta}})", prefix))
+            .unwrap(),
+        (None, None) => ProgressStyle::default_spinner()
+            .template(&format!("{}[{{elapsed_precise}}] {{spinner}} {{msg}}", prefix))
+            .unwrap(),
+    };
+    progress.set_style(style.progress_chars("#>-"));
+    if let Some(label) = label {
+        progress.set_prefix(label.to_string());
     }
+    progress
+}
 
-    let audio_filter_str = audio_filters.join(",");
+// Mirrors progress updates into a JSON file so external dashboards can poll it.
+struct ProgressFile {
+    path: String,
+    by_frames: bool,
+    last_write: Option<Instant>,
+}
+
+impl ProgressFile {
+    const WRITE_INTERVAL: Duration = Duration::from_secs(1);
+
+    fn new(path: &str, by_frames: bool) -> Self {
+        ProgressFile { path: path.to_string(), by_frames, last_write: None }
+    }
 
-    let video_codec = if *use_gpu { "hevc_nvenc" } else { "libx265" };
+    fn update(&mut self, progress: &Progress) {
+        if self.last_write.is_some_and(|last| last.elapsed() < Self::WRITE_INTERVAL) {
+            return;
+        }
+        self.last_write = Some(Instant::now());
 
-    let mut ffmpeg_command = vec![ffmpeg_path.clone(), "-i".to_string(), input_video_path.clone()];
+        let percent = progress.percent(self.by_frames);
+        let contents = serde_json::json!({
+            "percent": percent,
+            "elapsed_seconds": progress.elapsed,
+            "total_seconds": progress.total,
+            "speed": progress.speed,
+            "frame": progress.frame,
+            "total_frames": progress.total_frames,
+        });
 
-    if clip_start_time_float > 0.0 {
-        ffmpeg_command.extend(vec!["-ss".to_string(), clip_start_time_float.to_string()]);
+        // Write to a sibling file and rename it over the real one so readers never see a partial write
+        let temp_path = format!("{}.tmp", self.path);
+        let result = fs::write(&temp_path, contents.to_string()).and_then(|_| fs::rename(&temp_path, &self.path));
+        if let Err(e) = result {
+            warn!("Failed to write progress file {}: {}", self.path, e);
+        }
     }
-    if clip_end_time_float < duration {
-        ffmpeg_command.extend(vec!["-to".to_string(), clip_end_time_float.to_string()]);
+
+    fn finish(self) {
+        let _ = fs::remove_file(format!("{}.tmp", self.path));
+        let _ = fs::remove_file(&self.path);
     }
+}
 
-    if let Some(ref audio_path) = background_audio_path {
-        if audio_path.to_lowercase() != "none" {
-            ffmpeg_command.extend(vec![
-                "-ss".to_string(),
-                audio_start_time.to_string(),
-                "-i".to_string(),
-                audio_path.clone(),
-            ]);
-        }
+// Logs the progress as a plain line every so often, which survives where the bar doesn't: a log file, a closed SSH
+// session's scrollback, a batch's interleaved output
+struct ProgressLog {
+    name: String,
+    interval: Duration,
+    by_frames: bool,
+    started: Instant,
+    last_log: Instant,
+}
+
+impl ProgressLog {
+    fn new(name: &str, interval: Duration, by_frames: bool) -> Self {
+        ProgressLog { name: name.to_string(), interval, by_frames, started: Instant::now(), last_log: Instant::now() }
     }
 
-    if video_filter_str.is_empty() {
-        ffmpeg_command.extend(vec![
-            "-c:v".to_string(),
-            "copy".to_string()
-        ]);
-    } else {
-        ffmpeg_command.extend(vec![
-            "-filter_complex".to_string(),
-            format!("[0:v]{}[v]", video_filter_str),
-            "-map".to_string(),
-            "[v]".to_string(),
-        ]);
+    fn update(&mut self, progress: &Progress) {
+        if let Some(line) = self.line(progress, Instant::now()) {
+            info!("{}", line);
+        }
+    }
 
-        if *video_speed != 1.0 {
-            ffmpeg_command.extend(vec![
-                "-r".to_string(),
-                (framerate * video_speed).to_string(),
-            ]);
+    // "Progress of out.mp4: 42% (600/1420s, ETA 00:12:30)", or None while the last line is less than `interval` old.
+    // The ETA goes by ffmpeg's speed, or else by how long the part that's done took
+    fn line(&mut self, progress: &Progress, now: Instant) -> Option<String> {
+        if now.duration_since(self.last_log) < self.interval {
+            return None;
         }
+        self.last_log = now;
 
-        ffmpeg_command.extend(vec![
-            "-c:v".to_string(),
-            video_codec.to_string(),
-        ]);
+        let Some(total) = progress.total.filter(|total| *total > 0.0) else {
+            return Some(format!("Progress of {}: {:.0}s encoded", self.name, progress.elapsed));
+        };
+        let percent = progress.percent(self.by_frames).unwrap_or_default();
+        let remaining = (total - progress.elapsed).max(0.0);
+        let eta = match progress.speed.filter(|speed| *speed > 0.0) {
+            Some(speed) => Some(remaining / speed),
+            None if percent > 0.0 => Some(now.duration_since(self.started).as_secs_f64() * (100.0 - percent) / percent),
+            None => None,
+        };
+        let eta = eta.map_or_else(
+            || "unknown".to_string(),
+            |eta| {
+                let eta = eta.round() as u64;
+                format!("{:02}:{:02}:{:02}", eta / 3600, eta / 60 % 60, eta % 60)
+            },
+        );
+        Some(format!("Progress of {}: {:.0}% ({:.0}/{:.0}s, ETA {})", self.name, percent, progress.elapsed, total, eta))
+    }
+}
 
-        if let Some(ref crf_value) = crf {
-            if crf_value.to_lowercase() != "none" && !use_gpu {
-                ffmpeg_command.extend(vec![
-                    "-crf".to_string(),
-                    crf_value.to_string(),
-                ]);
-            } else {
-                ffmpeg_command.extend(vec![
-                    "-b:v".to_string(),
-                    video_bitrate.clone(),
-                ]);
+// Errors that come from the command itself, retrying them would only fail the same way again
+const FATAL_FFMPEG_ERRORS: &[&str] = &[
+    "Unknown encoder",
+    "Encoder not found",
+    "Unrecognized option",
+    "Option not found",
+    "No such filter",
+    "Error parsing filterchain",
+];
+
+// NVENC failing at runtime, the encoder exists but the GPU (or its driver) couldn't run the encode
+const NVENC_ERRORS: &[&str] = &[
+    "OpenEncodeSessionEx failed",
+    "No capable devices found",
+    "Cannot load libnvidia-encode",
+    "Cannot load nvEncodeAPI",
+    "Driver does not support the required nvenc API version",
+    "incompatible client key",
+    "CUDA_ERROR_",
+];
+
+// HDR to SDR bt709: linearize, map the primaries in float, squeeze the highlights with hable and convert back to
+// limited range 8-bit. Needs an ffmpeg built with zimg for zscale
+const TONEMAP_FILTER: &str =
+    "zscale=t=linear:npl=100,format=gbrpf32le,zscale=p=bt709,tonemap=tonemap=hable:desat=0,zscale=t=bt709:m=bt709:r=tv,format=yuv420p";
+
+// How much of ffmpeg's output is kept to explain a failure, the error is almost always in the last few lines
+const STDERR_TAIL_LINES: usize = 20;
+
+struct FfmpegFailure {
+    error: ProcessingError,
+    fatal: bool,
+}
+
+impl FfmpegFailure {
+    fn fatal(error: ProcessingError) -> Self {
+        FfmpegFailure { error, fatal: true }
+    }
+}
+
+// Set by `interrupt`, every running ffmpeg checks it between reads of its output
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// How long a running ffmpeg can go unchecked for an interrupt or its timeout
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Stops the processing: the running ffmpeg is killed, an incomplete output is removed and the call processing the
+/// video returns `ProcessingError::Interrupted`. Batches and watch mode don't start any new files afterwards. It only
+/// sets a flag, so it can be called from a signal handler.
+pub fn interrupt() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Whether `interrupt` was called.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+// Kills a run that was interrupted or is over its deadline, waiting on it so it doesn't linger as a zombie
+fn stop_if_needed(child: &mut Child, deadline: Option<(Instant, Duration)>) -> Option<FfmpegFailure> {
+    let error = match deadline {
+        _ if interrupted() => ProcessingError::Interrupted,
+        Some((deadline, timeout)) if Instant::now() >= deadline => ProcessingError::FfmpegTimeout(timeout),
+        _ => return None,
+    };
+    let _ = child.kill();
+    let _ = child.wait();
+    Some(FfmpegFailure::fatal(error))
+}
+
+fn run_ffmpeg_once<F>(
+    ffmpeg_command: &[String],
+    duration: Option<f64>,
+    advanced_log: bool,
+    timeout: Option<Duration>,
+    on_progress: &mut F,
+) -> Result<(), FfmpegFailure>
+where
+    F: FnMut(Progress),
+{
+    let mut command = Command::new(&ffmpeg_command[0]);
+    command.args(&ffmpeg_command[1..]);
+    let spawn_failure = |source| FfmpegFailure::fatal(ProcessingError::FfmpegSpawn { path: ffmpeg_command[0].clone(), source });
+    let wait_failure =
+        |source| FfmpegFailure::fatal(ProcessingError::Io { context: "Failed to wait for ffmpeg".to_string(), source });
+    let deadline = timeout.map(|timeout| (Instant::now() + timeout, timeout));
+
+    if advanced_log {
+        let mut child = command.spawn().map_err(spawn_failure)?;
+        let status = loop {
+            if let Some(status) = child.try_wait().map_err(wait_failure)? {
+                break status;
+            }
+            if let Some(failure) = stop_if_needed(&mut child, deadline) {
+                return Err(failure);
+            }
+            thread::sleep(STOP_POLL_INTERVAL);
+        };
+        // Ctrl-C in a terminal reaches ffmpeg as well, which then exits on its own before it's stopped here
+        if interrupted() {
+            return Err(FfmpegFailure::fatal(ProcessingError::Interrupted));
+        }
+        if !status.success() {
+            return Err(FfmpegFailure {
+                // ffmpeg's output already went to the terminal
+                error: ProcessingError::FfmpegExit { status, stderr: Vec::new() },
+                fatal: false,
+            });
+        }
+    } else {
+        // stdout is left alone, ffmpeg only writes to it when the output itself is piped there
+        command.stderr(Stdio::piped());
+        let mut child = command.spawn().map_err(spawn_failure)?;
+        let stderr = child.stderr.take().ok_or_else(|| {
+            FfmpegFailure::fatal(ProcessingError::Io {
+                context: "Failed to capture the ffmpeg output".to_string(),
+                source: io::Error::other("stderr wasn't piped"),
+            })
+        })?;
+        // The output is read on its own thread so a hung ffmpeg that prints nothing can still be stopped here
+        let (line_sender, lines) = mpsc::channel();
+        thread::spawn(move || read_ffmpeg_output(BufReader::new(stderr), duration, |line| line_sender.send(line).is_ok()));
+
+        let mut fatal = false;
+        let mut gpu_failed = false;
+        let mut stderr_tail = VecDeque::with_capacity(STDERR_TAIL_LINES);
+        loop {
+            if let Some(failure) = stop_if_needed(&mut child, deadline) {
+                return Err(failure);
+            }
+            let line = match lines.recv_timeout(STOP_POLL_INTERVAL) {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            let line = match line {
+                StderrLine::Progress(progress) => {
+                    on_progress(progress);
+                    continue;
+                }
+                StderrLine::Other(line) => line,
+            };
+            if !line.trim().is_empty() {
+                if stderr_tail.len() == STDERR_TAIL_LINES {
+                    stderr_tail.pop_front();
+                }
+                stderr_tail.push_back(line.clone());
+            }
+            if FATAL_FFMPEG_ERRORS.iter().any(|pattern| line.contains(pattern)) {
+                fatal = true;
+            }
+            if NVENC_ERRORS.iter().any(|pattern| line.contains(pattern)) {
+                gpu_failed = true;
             }
-        } else {
-            ffmpeg_command.extend(vec![
-                "-b:v".to_string(),
-                video_bitrate.clone(),
-            ]);
+        }
+
+        let status = child.wait().map_err(wait_failure)?;
+        if interrupted() {
+            return Err(FfmpegFailure::fatal(ProcessingError::Interrupted));
+        }
+        if !status.success() {
+            return Err(FfmpegFailure {
+                error: if gpu_failed {
+                    ProcessingError::GpuEncodeFailed(status)
+                } else {
+                    ProcessingError::FfmpegExit { status, stderr: stderr_tail.into() }
+                },
+                fatal,
+            });
         }
     }
 
-    if let Some(ref audio_path) = background_audio_path {
-        if audio_path.to_lowercase() != "none" {
-            if *replace_audio {
-                ffmpeg_command.extend(vec![
-                    "-filter_complex".to_string(),
-                    format!(
-                        "[1:a]volume={},{}[a]",
-                        background_audio_volume, audio_filter_str
-                    ),
-                    "-map".to_string(),
-                    "[a]".to_string(),
-                ]);
-            } else {
-                let normalize_filter = format!(
-                    "[0:a]volume={}[a0];[1:a]volume={},{}[a1];[a0][a1]amix=inputs=2:duration=first:dropout_transition=3[a]",
-                    original_audio_volume, background_audio_volume, audio_filter_str
+    Ok(())
+}
+
+fn run_ffmpeg_command<F>(
+    ffmpeg_command: &[String],
+    duration: Option<f64>,
+    advanced_log: bool,
+    retries: u32,
+    retry_delay: Duration,
+    timeout: Option<Duration>,
+    on_progress: &mut F,
+) -> Result<(), ProcessingError>
+where
+    F: FnMut(Progress),
+{
+    let mut attempt = 0;
+    loop {
+        match run_ffmpeg_once(ffmpeg_command, duration, advanced_log, timeout, on_progress) {
+            Ok(()) => return Ok(()),
+            Err(failure) if failure.fatal || attempt >= retries => return Err(failure.error),
+            Err(failure) => {
+                attempt += 1;
+                warn!(
+                    "{}, retrying in {}s (attempt {} of {})",
+                    failure.error,
+                    retry_delay.as_secs_f64(),
+                    attempt,
+                    retries
                 );
-                ffmpeg_command.extend(vec![
-                    "-filter_complex".to_string(),
-                    normalize_filter,
-                    "-map".to_string(),
-                    "[a]".to_string(),
-                ]);
+                thread::sleep(retry_delay);
             }
-        } else {
-            ffmpeg_command.extend(vec![
-                "-filter_complex".to_string(),
-                format!("[0:a]volume={}{}", original_audio_volume, audio_filter_str),
-                "-map".to_string(),
-                "[a]".to_string(),
-            ]);
         }
-    } else {
-        ffmpeg_command.extend(vec![
-            "-filter_complex".to_string(),
-            format!("[0:a]volume={}{}", original_audio_volume, audio_filter_str),
-            "-map".to_string(),
-            "[a]".to_string(),
-        ]);
     }
+}
 
-    ffmpeg_command.extend(vec![
-        "-c:a".to_string(),
-        "aac".to_string(),
-        "-b:a".to_string(),
-        "192k".to_string(),
-        "-y".to_string(),
-        output_video_path.clone(),
-    ]);
+/// How progress is reported while encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProgressOutput {
+    /// A progress bar on stderr.
+    #[default]
+    Bar,
+    /// One JSON object per line on stdout, ending with a `status` line.
+    Json,
+}
 
-    info!("Starting the video processing...");
-    run_ffmpeg_command(&ffmpeg_command, duration, *advanced_log)?;
+/// The result of one video for `--report`, written as JSON.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobReport {
+    pub input: String,
+    pub output: String,
+    /// `done`, `skipped` (the output was up to date or the input already conformed), `dry_run` or `error`.
+    pub status: &'static str,
+    pub error: Option<String>,
+    /// How long the output should be in seconds, from the input and the clip settings.
+    pub duration_seconds: Option<f64>,
+    /// The ffmpeg video encoder, e.g. `libx265` or `hevc_nvenc`, or `copy` when the video was copied.
+    pub encoder: Option<&'static str>,
+    pub elapsed_seconds: f64,
+    pub output_size_bytes: Option<u64>,
+    /// How long the encode itself took, all of its passes but not the probing or the images drawn from it after.
+    pub encode_seconds: Option<f64>,
+    /// Frames of video encoded, as ffmpeg counted them.
+    pub encoded_frames: Option<u64>,
+    /// `encoded_frames` over `encode_seconds`.
+    pub average_fps: Option<f64>,
+    /// Seconds of output encoded per second of encoding, above 1 is faster than realtime.
+    pub realtime_factor: Option<f64>,
+}
 
-    info!("All done! Your video has been processed successfully.");
+impl JobReport {
+    fn new(config: &Config, result: &Result<Outcome, ProcessingError>, elapsed: Duration) -> Self {
+        let mut report = JobReport {
+            input: config.settings.input_video_path.clone(),
+            output: config.settings.output_video_path.clone(),
+            status: "error",
+            error: None,
+            duration_seconds: None,
+            encoder: None,
+            elapsed_seconds: elapsed.as_secs_f64(),
+            output_size_bytes: None,
+            encode_seconds: None,
+            encoded_frames: None,
+            average_fps: None,
+            realtime_factor: None,
+        };
+        match result {
+            Ok(Outcome::Skipped) => report.status = "skipped",
+            Ok(Outcome::DryRun) => report.status = "dry_run",
+            Ok(Outcome::Encoded { output, encoder, duration, frames, encode_time }) => {
+                report.status = "done";
+                report.output_size_bytes = fs::metadata(output).ok().map(|metadata| metadata.len());
+                report.output = output.clone();
+                report.encoder = Some(encoder);
+                report.duration_seconds = *duration;
+                let seconds = Some(encode_time.as_secs_f64()).filter(|seconds| *seconds > 0.0);
+                report.encode_seconds = seconds;
+                report.encoded_frames = *frames;
+                report.average_fps = frames.zip(seconds).map(|(frames, seconds)| frames as f64 / seconds);
+                report.realtime_factor = duration.zip(seconds).map(|(duration, seconds)| duration / seconds);
+            }
+            Err(e) => report.error = Some(e.to_string()),
+        }
+        report
+    }
 
-    Ok(())
+    // "1800 frames in 42.3s, 42.6 fps, 1.42x realtime with libx265", for benchmark
+    fn speed_summary(&self) -> Option<String> {
+        let seconds = self.encode_seconds?;
+        let mut summary = match self.encoded_frames {
+            Some(frames) => format!("{} frames in {:.1}s", frames, seconds),
+            None => format!("encoded in {:.1}s", seconds),
+        };
+        if let Some(fps) = self.average_fps {
+            summary.push_str(&format!(", {:.1} fps", fps));
+        }
+        if let Some(factor) = self.realtime_factor {
+            summary.push_str(&format!(", {:.2}x realtime", factor));
+        }
+        if let Some(encoder) = self.encoder {
+            summary.push_str(&format!(" with {}", encoder));
+        }
+        Some(summary)
+    }
+}
+
+// Pretty-printed, a report is as likely to be read by a person as by a script
+fn write_report(path: &str, report: &impl Serialize) -> Result<(), ProcessingError> {
+    let json = serde_json::to_string_pretty(report).unwrap_or_default();
+    fs::write(path, json + "\n").map_err(|source| ProcessingError::Io {
+        context: format!("Failed to write the report to {}", path),
+        source,
+    })
+}
+
+/// Processes the configured video, drawing a progress bar on the terminal. With `dry_run` the input is still probed
+/// but the ffmpeg commands are printed to stdout instead of run.
+pub fn add_fade_effects(config: &Config, dry_run: bool) -> Result<(), ProcessingError> {
+    run_job(config, dry_run, ProgressOutput::Bar, None, None, None).0
+}
+
+/// Processes the configured video like `add_fade_effects`, reporting progress as `output` says. With `report_path`
+/// a `JobReport` of the run is written there as well, also when the run fails.
+pub fn add_fade_effects_reporting(
+    config: &Config,
+    dry_run: bool,
+    output: ProgressOutput,
+    report_path: Option<&str>,
+) -> Result<(), ProcessingError> {
+    let (result, report) = run_job(config, dry_run, output, None, None, None);
+    match report_path {
+        Some(path) => result.and(write_report(path, &report)),
+        None => result,
+    }
+}
+
+// `label` names the file in the bar or the JSON lines. With `multi` the bar is drawn alongside the bars of the other
+// jobs that are running at the same time, and with `slots` the encode waits for a free slot of its kind first
+fn run_job(
+    config: &Config,
+    dry_run: bool,
+    output: ProgressOutput,
+    label: Option<&str>,
+    multi: Option<&MultiProgress>,
+    slots: Option<&JobSlots>,
+) -> (Result<(), ProcessingError>, JobReport) {
+    let started = Instant::now();
+    let result = run_job_with_progress(config, dry_run, output, label, multi, slots);
+    let report = JobReport::new(config, &result, started.elapsed());
+    if config.settings.benchmark {
+        if let Some(summary) = report.speed_summary() {
+            info!("Benchmark for {}: {}", report.output, summary);
+        }
+    }
+    (result.map(|_| ()), report)
+}
+
+fn run_job_with_progress(
+    config: &Config,
+    dry_run: bool,
+    output: ProgressOutput,
+    label: Option<&str>,
+    multi: Option<&MultiProgress>,
+    slots: Option<&JobSlots>,
+) -> Result<Outcome, ProcessingError> {
+    if output == ProgressOutput::Json {
+        let result = if config.settings.output_video_path == "-" {
+            Err(ProcessingError::ConfigInvalid(
+                "JSON progress is written to stdout, which can't be used while the video is written there too".to_string(),
+            ))
+        } else {
+            process(config, dry_run, slots, |update| {
+                let percent = update.percent(config.settings.progress_by_frames());
+                let mut event = serde_json::json!({
+                    "elapsed_seconds": update.elapsed,
+                    "total_seconds": update.total,
+                    "percent": percent,
+                });
+                if let Some(label) = label {
+                    event["file"] = label.into();
+                }
+                println!("{}", event);
+            })
+        };
+        let mut status = match result {
+            Ok(_) => serde_json::json!({ "status": "done" }),
+            Err(ref e) => serde_json::json!({ "status": "error", "message": e.to_string() }),
+        };
+        if let Some(label) = label {
+            status["file"] = label.into();
+        }
+        println!("{}", status);
+        return result;
+    }
+
+    let mut progress: Option<(ProgressBar, bool)> = None;
+    let result = process(config, dry_run, slots, |update| {
+        let settings = &config.settings;
+        let (progress, by_frames) = progress.get_or_insert_with(|| {
+            // Counting frames needs ffmpeg to report them and a known total, otherwise it's seconds after all
+            let by_frames = settings.progress_by_frames() && update.frame.is_some() && update.total_frames.is_some();
+            let total = if by_frames { update.total_frames.map(|frames| frames as f64) } else { update.total };
+            let bar = progress_bar(total, settings.progress_template.as_deref(), settings.progress_refresh_hz, label);
+            let bar = match multi {
+                Some(multi) => multi.add(bar),
+                None => bar,
+            };
+            (bar, by_frames)
+        });
+        if !*by_frames {
+            progress.set_position(update.elapsed as u64);
+            progress.set_message(format!("{:.1}s encoded", update.elapsed));
+        } else if let Some(frame) = update.frame {
+            progress.set_position(frame);
+            progress.set_message(format!("{} frames encoded", frame));
+        }
+    });
+    if let (Some(multi), Some((progress, _))) = (multi, progress) {
+        progress.finish();
+        multi.remove(&progress);
+    }
+    result
+}
+
+// How many encodes of a batch may run at once, by whether they copy the video. A copy only reads and writes the file
+// so many of them fit next to each other, while an encode keeps the CPU (or GPU) busy on its own
+struct JobSlots {
+    encodes: usize,
+    copies: usize,
+    // Encodes and copies running right now
+    running: Mutex<(usize, usize)>,
+    freed: Condvar,
+}
+
+struct JobSlot<'a> {
+    slots: &'a JobSlots,
+    copy: bool,
+}
+
+impl JobSlots {
+    fn new(encodes: usize, copies: usize) -> Self {
+        JobSlots { encodes, copies, running: Mutex::new((0, 0)), freed: Condvar::new() }
+    }
+
+    // Waits for a free slot, None when the run is interrupted in the meantime
+    fn acquire(&self, copy: bool) -> Option<JobSlot<'_>> {
+        let mut running = self.running.lock().unwrap();
+        loop {
+            if interrupted() {
+                return None;
+            }
+            let (count, limit) = if copy { (&mut running.1, self.copies) } else { (&mut running.0, self.encodes) };
+            if *count < limit {
+                *count += 1;
+                return Some(JobSlot { slots: self, copy });
+            }
+            running = self.freed.wait_timeout(running, Duration::from_millis(200)).unwrap().0;
+        }
+    }
+}
+
+impl Drop for JobSlot<'_> {
+    fn drop(&mut self) {
+        let mut running = self.slots.running.lock().unwrap();
+        if self.copy {
+            running.1 -= 1;
+        } else {
+            running.0 -= 1;
+        }
+        self.slots.freed.notify_all();
+    }
+}
+
+/// Processes every `[[batch]]` entry of the config with the shared settings, up to `max_parallel_jobs` at a time
+/// (one after the other by default). A file that fails is logged and the rest still run, the return value is the
+/// number of files that failed. With `report_path` a list of `JobReport`s for the files that were started is written
+/// there, in config order.
+pub fn process_batch(config: &Config, dry_run: bool, output: ProgressOutput, report_path: Option<&str>) -> usize {
+    let jobs = config.settings.max_parallel_jobs.unwrap_or(1).clamp(1, config.batch.len().max(1));
+    // Jobs that only copy the video go by their own, higher limit. Whether a job copies is only known once it's
+    // planned, so there are enough workers for either and each job waits for a slot of its kind before it runs
+    let cores = thread::available_parallelism().map_or(1, |cores| cores.get());
+    let copies = config.settings.max_parallel_copies.unwrap_or(cores.max(jobs)).max(1);
+    let slots = JobSlots::new(jobs, copies);
+    let workers = jobs.max(copies).min(config.batch.len().max(1));
+    let multi = (workers > 1 && output == ProgressOutput::Bar).then(MultiProgress::new);
+    // Each job's encoder gets its own threads, which only adds up to a slower batch once the cores run out
+    if let (Some(threads), Ok(cores)) = (config.settings.threads, thread::available_parallelism()) {
+        if jobs * threads > cores.get() {
+            warn!(
+                "{} parallel jobs of {} threads each add up to {} threads on {} cores, lower max_parallel_jobs or threads",
+                jobs,
+                threads,
+                jobs * threads,
+                cores
+            );
+        }
+    }
+    let batch_started = Instant::now();
+    let next_job = AtomicUsize::new(0);
+    let failures = Mutex::new(Vec::new());
+    let reports = Mutex::new(Vec::new());
+
+    let run_jobs = || loop {
+        if interrupted() {
+            break;
+        }
+        let index = next_job.fetch_add(1, Ordering::SeqCst);
+        let Some(job) = config.batch.get(index) else {
+            break;
+        };
+        let mut job_config = config.clone();
+        job_config.settings.input_video_path = job.input.clone();
+        job_config.settings.output_video_path = job.output.clone();
+
+        let name = Path::new(&job.input).file_name().map_or(job.input.clone(), |name| name.to_string_lossy().into_owned());
+        info!("[{}/{}] Processing {}", index + 1, config.batch.len(), job.input);
+        let (result, report) = run_job(&job_config, dry_run, output, Some(&name), multi.as_ref(), Some(&slots));
+        reports.lock().unwrap().push((index, report));
+        if let Err(e) = result {
+            error!("Failed to process {}: {}", job.input, e);
+            failures.lock().unwrap().push((index, e));
+        }
+    };
+    if workers == 1 {
+        run_jobs();
+    } else {
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(run_jobs);
+            }
+        });
+    }
+
+    // Jobs finish in any order when they run in parallel, the summary lists them in config order
+    let mut failures = failures.into_inner().unwrap();
+    failures.sort_by_key(|(index, _)| *index);
+    let started = next_job.into_inner().min(config.batch.len());
+    let succeeded = started - failures.len();
+    if started < config.batch.len() {
+        info!(
+            "Batch interrupted: {} succeeded, {} failed, {} not started",
+            succeeded,
+            failures.len(),
+            config.batch.len() - started
+        );
+    } else {
+        info!("Batch finished: {} succeeded, {} failed", succeeded, failures.len());
+    }
+    for (index, e) in &failures {
+        error!("  {}: {}", config.batch[*index].input, e);
+    }
+    // Parallel jobs overlap, so the batch's speed goes by its own wall time rather than the sum of the jobs'
+    if config.settings.benchmark {
+        let reports = reports.lock().unwrap();
+        let encoded: Vec<&JobReport> = reports.iter().map(|(_, report)| report).filter(|report| report.encode_seconds.is_some()).collect();
+        let seconds = batch_started.elapsed().as_secs_f64();
+        let output_seconds: f64 = encoded.iter().filter_map(|report| report.duration_seconds).sum();
+        let frames: u64 = encoded.iter().filter_map(|report| report.encoded_frames).sum();
+        if !encoded.is_empty() {
+            info!(
+                "Benchmark for the batch: {} files, {} frames in {:.1}s, {:.1} fps, {:.2}x realtime",
+                encoded.len(),
+                frames,
+                seconds,
+                frames as f64 / seconds,
+                output_seconds / seconds
+            );
+        }
+    }
+    if let Some(path) = report_path {
+        let mut reports = reports.into_inner().unwrap();
+        reports.sort_by_key(|(index, _)| *index);
+        let reports: Vec<JobReport> = reports.into_iter().map(|(_, report)| report).collect();
+        if let Err(e) = write_report(path, &reports) {
+            error!("{}", e);
+        }
+    }
+    failures.len()
+}
+
+// Whether skip_existing says the output can be kept as it is. "newer" only keeps it when it was written after the
+// input was last changed, a stream or a pipe has no such time so it's always processed again. "settings" only keeps
+// it when its settings tag matches the current settings, one that can't be probed or has no tag is encoded again
+fn output_up_to_date(settings: &Settings, output_video_path: &str) -> bool {
+    let Ok(output) = fs::metadata(output_video_path) else {
+        return false;
+    };
+    match settings.skip_existing.as_ref().map(|when| when.to_lowercase()).as_deref() {
+        _ if output_video_path == "-" => false,
+        Some("always") => true,
+        Some("newer") => {
+            let modified = |metadata: fs::Metadata| metadata.modified().ok();
+            let input_modified = fs::metadata(&settings.input_video_path).ok().and_then(modified);
+            input_modified.zip(modified(output)).is_some_and(|(input, output)| output > input)
+        }
+        Some("settings") => {
+            let tags = probe::probe_media(&settings.ffmpeg_path, output_video_path).map(|info| info.tags).unwrap_or_default();
+            let tag = tags.iter().find(|(key, _)| key.eq_ignore_ascii_case(SETTINGS_TAG)).map(|(_, hash)| hash);
+            let matches = tag.is_some_and(|hash| *hash == settings.output_hash());
+            if !matches {
+                info!(
+                    "{} was {}, encoding it again",
+                    output_video_path,
+                    if tag.is_some() { "encoded with other settings" } else { "not tagged with its settings" }
+                );
+            }
+            matches
+        }
+        _ => false,
+    }
+}
+
+/// Processes the configured video, calling `on_progress` for every progress update ffmpeg reports
+/// instead of drawing a progress bar.
+pub fn add_fade_effects_with_progress<F>(config: &Config, dry_run: bool, on_progress: F) -> Result<(), ProcessingError>
+where
+    F: FnMut(Progress),
+{
+    process(config, dry_run, None, on_progress).map(|_| ())
+}
+
+// What `process` ended up doing with the video
+enum Outcome {
+    Skipped,
+    DryRun,
+    // `frames` and `encode_time` are what benchmark reports, the time covers every pass of the encode
+    Encoded { output: String, encoder: &'static str, duration: Option<f64>, frames: Option<u64>, encode_time: Duration },
+}
+
+// Everything that runs to process one video and what's needed to run it, built by `plan`
+struct Plan {
+    detect_command: Option<Vec<String>>,
+    first_pass_command: Option<Vec<String>>,
+    ffmpeg_command: Vec<String>,
+    contact_sheet_command: Option<Vec<String>>,
+    thumbnail_command: Option<Vec<String>>,
+    waveform_command: Option<Vec<String>>,
+    // The chapters in ffmpeg's metadata format, written to chapters_path before the encode reads them
+    chapters: Option<String>,
+    chapters_path: String,
+    // Where ffmpeg writes, a temporary file next to the output with atomic_output
+    write_path: String,
+    atomic: bool,
+    // The [[output]] renditions' write paths and where they end up
+    renditions: Vec<(String, String)>,
+    progress_total: Option<f64>,
+    total_frames: Option<u64>,
+    sheet_length: Option<f64>,
+    expected_output_duration: Option<f64>,
+    transforms_path: String,
+    pass_log_path: String,
+    gpu_encoder: Option<GpuEncoder>,
+    encoder: &'static str,
+    output_length: Option<f64>,
+}
+
+// The output path, or the _preview file next to it when rendering a preview
+fn output_path(settings: &Settings) -> String {
+    match settings.preview_duration {
+        Some(_) if settings.output_video_path != "-" => config::suffixed_path(&settings.output_video_path, "_preview"),
+        _ => settings.output_video_path.clone(),
+    }
+}
+
+/// Builds the ffmpeg command that encodes the configured video without running it, as `--dry-run` prints it.
+/// `media` is what the input was probed as (`None` for stdin), the input itself isn't probed again. Background
+/// audio, intros and outros still are, and ffmpeg is asked which encoders it has. With `stabilize` or `two_pass`
+/// other commands run before this one, this is only the final encode.
+pub fn build_ffmpeg_command(config: &Config, media: Option<&probe::MediaInfo>) -> Result<Vec<String>, ProcessingError> {
+    config.validate()?;
+    plan(config, media).map(|plan| plan.ffmpeg_command)
+}
+
+// Builds every command that processes the video from the settings and what the input was probed as, `None` for a
+// stdin stream
+fn plan(config: &Config, info: Option<&probe::MediaInfo>) -> Result<Plan, ProcessingError> {
+    let Settings {
+        input_video_path,
+        ffmpeg_path,
+        video_bitrate,
+        crf,
+        upscale_resolution,
+        audio_start_time,
+        replace_audio,
+        original_audio_volume,
+        clip_start_time,
+        clip_end_time,
+        video_speed,
+        fade_in_duration,
+        fade_out_duration,
+        interpolate,
+        target_fps,
+        output_format,
+        deinterlace,
+        denoise,
+        denoise_method,
+        sharpen,
+        stabilize,
+        speed_segments,
+        contact_sheet_path,
+        contact_sheet_columns,
+        contact_sheet_rows,
+        preview_duration,
+        comparison,
+        fades,
+        flip_horizontal,
+        flip_vertical,
+        negate,
+        vignette,
+        vignette_angle,
+        film_grain,
+        color_effect,
+        loop_count,
+        intro_path,
+        outro_path,
+        concat_inputs,
+        second_input_video_path,
+        crossfade_duration,
+        subtitle_path,
+        subtitle_style,
+        gpu_fallback,
+        two_pass,
+        audio_mode,
+        audio_codec,
+        audio_bitrate,
+        audio_sample_rate,
+        crop,
+        rotate,
+        loudnorm,
+        loudnorm_target,
+        audio_limiter,
+        audio_limiter_threshold,
+        master_volume,
+        faststart,
+        audio_only,
+        threads,
+        pad_to_aspect,
+        pad_color,
+        blurred_background,
+        fade_in_color,
+        fade_out_color,
+        background_audio_delay,
+        waveform_path,
+        waveform_size,
+        chapters_file,
+        denoise_params,
+        sharpen_params,
+        thumbnail_path,
+        thumbnail_time,
+        preserve_metadata,
+        pixel_format,
+        encoder_preset,
+        encoder_tune,
+        video_fade,
+        extra_ffmpeg_args,
+        skip_existing,
+        atomic_output,
+        max_bitrate,
+        buffer_size,
+        loop_background_audio,
+        tonemap,
+        max_resolution,
+        separate_audio_tracks,
+        audio_track_titles,
+        audio_track_languages,
+        keyframe_interval,
+        strict_gop,
+        fast_seek,
+        watermark_path,
+        watermark_position,
+        watermark_opacity,
+        color_range,
+        audio_fade_in_duration,
+        audio_fade_out_duration,
+        ..
+    } = &config.settings;
+    let background_audio = config.settings.background_audio_files();
+
+    let output_video_path = &output_path(&config.settings);
+
+    let reading_stdin = input_video_path == "-";
+    let input = if reading_stdin { "pipe:0".to_string() } else { input_video_path.clone() };
+
+    if let Some(info) = info {
+        info.duration_and_framerate()?;
+    }
+    let duration = info.and_then(|info| info.duration);
+    let framerate = info.and_then(|info| info.framerate);
+    let interlaced = info.is_some_and(|info| info.interlaced);
+    let size = info.and_then(|info| info.width.zip(info.height));
+    let has_audio = info.is_none_or(|info| info.has_audio);
+    // -stream_loop plays the input loop_count more times, so the clip is that much longer
+    if loop_count.is_some() && reading_stdin {
+        return Err(ProcessingError::ConfigInvalid(
+            "loop_count needs to seek back in the input, which isn't possible when reading from stdin".to_string(),
+        ));
+    }
+    let duration = duration.map(|duration| duration * (loop_count.unwrap_or(0) + 1) as f64);
+    let mut input_args = Vec::new();
+    if let Some(count) = loop_count {
+        input_args.extend(vec!["-stream_loop".to_string(), count.to_string()]);
+    }
+    input_args.extend(vec!["-i".to_string(), input.clone()]);
+
+    let source_framerate = || {
+        framerate.ok_or_else(|| {
+            ProcessingError::ConfigInvalid(
+                "The framerate of a stdin stream can't be probed, set target_fps to change the speed or interpolate".to_string(),
+            )
+        })
+    };
+
+    let clip_start_time_float = if let Some(ref clip_start_time) = clip_start_time {
+        if clip_start_time.to_lowercase() == "none" {
+            0.0
+        } else {
+            config::parse_time(clip_start_time)
+                .map_err(|e| ProcessingError::InvalidClipTime(format!("Invalid clip_start_time: {}", e)))?
+        }
+    } else {
+        0.0
+    };
+
+    if let Some(duration) = duration.filter(|duration| clip_start_time_float >= *duration) {
+        return Err(ProcessingError::InvalidClipTime(format!(
+            "clip_start_time ({}s) is past the end of the input ({}s)",
+            clip_start_time_float, duration
+        )));
+    }
+
+    let clip_end_time_float = match clip_end_time {
+        Some(ref clip_end_time) if clip_end_time.to_lowercase() != "none" => {
+            let end = config::parse_time(clip_end_time)
+                .map_err(|e| ProcessingError::InvalidClipTime(format!("Invalid clip_end_time: {}", e)))?;
+            // Past the end the fade-out would be timed for a moment the video never reaches
+            match duration {
+                Some(duration) if end > duration => {
+                    warn!("clip_end_time ({}s) is past the end of the input, ending the clip at {}s instead", end, duration);
+                    Some(duration)
+                }
+                _ => Some(end),
+            }
+        }
+        _ => duration,
+    };
+    let clip_end_time_float = match preview_duration {
+        Some(preview_duration) => {
+            let preview_end = clip_start_time_float + preview_duration;
+            Some(clip_end_time_float.map_or(preview_end, |end| end.min(preview_end)))
+        }
+        None => clip_end_time_float,
+    };
+
+    // Speed segments and clip ranges trim (and retime) the clip inside the filter graph, so everything after them
+    // works on a timeline that starts at 0
+    let segment_graph = match speed_segments {
+        Some(ref segments) => {
+            let clip_end_time_float = clip_end_time_float.ok_or_else(|| {
+                ProcessingError::ConfigInvalid("speed_segments need clip_end_time when reading from stdin".to_string())
+            })?;
+            if let Some(segment) = segments
+                .iter()
+                .find(|segment| segment.start < clip_start_time_float || segment.end > clip_end_time_float)
+            {
+                return Err(ProcessingError::ConfigInvalid(format!(
+                    "speed segment {}-{} falls outside of the clip ({}-{})",
+                    segment.start, segment.end, clip_start_time_float, clip_end_time_float
+                )));
+            }
+            Some(speed_segment_graph(segments, clip_start_time_float, clip_end_time_float))
+        }
+        None => None,
+    };
+    // Clip ranges are cut out and joined the same way, dropping the rest of the source
+    let segment_graph = match config.settings.clip_ranges_seconds().map_err(ProcessingError::ConfigInvalid)? {
+        Some(ranges) => {
+            if let Some(&(start, end)) = duration.and_then(|duration| ranges.iter().find(|(_, end)| *end > duration)) {
+                return Err(ProcessingError::ConfigInvalid(format!(
+                    "clip range {}-{} runs past the end of the input",
+                    start, end
+                )));
+            }
+            Some(piece_graph(ranges.into_iter().map(|(start, end)| (start, end, 1.0)).collect()))
+        }
+        None => segment_graph,
+    };
+
+    // The intro and outro are concatenated around the trimmed clip inside the graph as well, which again leaves a
+    // timeline starting at 0 covering all three
+    let mut bumper_inputs = Vec::new();
+    let mut load_bumper = |path: &Option<String>| -> Result<Option<Bumper>, ProcessingError> {
+        match path {
+            Some(path) if path.to_lowercase() != "none" => {
+                if reading_stdin {
+                    return Err(ProcessingError::ConfigInvalid(
+                        "intro_path, outro_path and concat_inputs need the size of the input, which can't be probed from stdin"
+                            .to_string(),
+                    ));
+                }
+                let (duration, has_audio) = probe_bumper(ffmpeg_path, path)?;
+                bumper_inputs.push(path.clone());
+                Ok(Some(Bumper { input: bumper_inputs.len() - 1, duration, has_audio }))
+            }
+            _ => Ok(None),
+        }
+    };
+    let intro = load_bumper(intro_path)?;
+    // concat_inputs are joined on after the clip like a series of outros, the real outro still comes last
+    let mut concat_clips = Vec::new();
+    for path in concat_inputs {
+        concat_clips.extend(load_bumper(&Some(path.clone()))?);
+    }
+    let outro = load_bumper(outro_path)?;
+    // The second input of a crossfade goes through the same graph path, overlapping the clip by the crossfade
+    let second = load_bumper(second_input_video_path)?;
+    let crossfade_duration = crossfade_duration.unwrap_or(0.0);
+    let has_bumpers = intro.is_some() || outro.is_some() || second.is_some() || !concat_clips.is_empty();
+    let bumper_length = intro.iter().chain(&concat_clips).chain(outro.iter()).map(|bumper| bumper.duration).sum::<f64>()
+        + second.as_ref().map_or(0.0, |second| second.duration - crossfade_duration);
+
+    // fast_seek moves a plain trim's -ss in front of the input, which jumps to the keyframe before it instead of
+    // decoding everything up to it, and restarts the timestamps at zero there
+    let input_seek = *fast_seek && segment_graph.is_none() && !has_bumpers && clip_start_time_float > 0.0;
+    let timeline_end = match segment_graph {
+        Some((_, output_duration)) => Some(output_duration + bumper_length),
+        None if has_bumpers => clip_end_time_float.map(|end| end - clip_start_time_float + bumper_length),
+        None if input_seek => clip_end_time_float.map(|end| end - clip_start_time_float),
+        None => clip_end_time_float,
+    };
+    // A plain trim is done with -ss/-to after the inputs, which cut the output while the graph still runs on the
+    // source's timestamps, so the fades have to be placed at the clip's position in the source. The segment and
+    // bumper graphs trim the clip themselves and start their timeline at zero, as does an input seek
+    let timeline_start = if segment_graph.is_none() && !has_bumpers && !input_seek { clip_start_time_float } else { 0.0 };
+    let progress_total = match segment_graph {
+        Some((_, output_duration)) => Some(output_duration + bumper_length),
+        None if has_bumpers => timeline_end,
+        None => duration,
+    };
+    let clip_length = match segment_graph {
+        Some((_, output_duration)) => Some(output_duration + bumper_length),
+        None => clip_end_time_float.map(|end| end - clip_start_time_float + bumper_length),
+    };
+
+    // What the encode should come out at, to catch A/V drift from a speed change afterwards
+    let expected_output_duration = match segment_graph {
+        Some((_, output_duration)) if speed_segments.is_some() => Some(output_duration + bumper_length),
+        _ if *video_speed != 1.0 => clip_length
+            .or(duration.map(|duration| duration - clip_start_time_float))
+            .map(|length| length / video_speed),
+        _ => None,
+    };
+
+    let fade_seconds = |name: &str, fade: &Option<FadeDuration>| {
+        fade.unwrap_or(FadeDuration::Seconds(3.0))
+            .seconds(clip_length)
+            .ok_or_else(|| {
+                ProcessingError::ConfigInvalid(format!("A percentage {} needs clip_end_time when reading from stdin", name))
+            })
+    };
+    let (video_fades, audio_fades) = match fades {
+        Some(fades) => {
+            if let Some(clip_length) = clip_length.or(duration.map(|duration| duration - clip_start_time_float)) {
+                if let Some(fade) = fades.iter().find(|fade| fade.start + fade.duration > clip_length) {
+                    return Err(ProcessingError::ConfigInvalid(format!(
+                        "fade at {}-{} runs past the end of the clip ({})",
+                        fade.start,
+                        fade.start + fade.duration,
+                        clip_length
+                    )));
+                }
+            }
+            fade_chain(fades, config.settings.fade_curves(), timeline_start)
+        }
+        None => {
+            let fade_in_duration = fade_seconds("fade_in_duration", fade_in_duration)?;
+            // The audio fades fall back to the video ones
+            let audio_fade_in_duration = match audio_fade_in_duration {
+                Some(_) => fade_seconds("audio_fade_in_duration", audio_fade_in_duration)?,
+                None => fade_in_duration,
+            };
+
+            // Black unless a color is given, the audio always fades from and to silence
+            let fade_color = |color: &Option<String>| color.as_ref().map(|color| format!(":c={}", color)).unwrap_or_default();
+            let mut video_fades = Vec::new();
+            // A zero length fade turns that fade off. It would do nothing anyway, but would still force the video to
+            // be re-encoded
+            if fade_in_duration > 0.0 {
+                video_fades.push(format!("fade=t=in:st={}:d={}{}", timeline_start, fade_in_duration, fade_color(fade_in_color)));
+            }
+            let (fade_in_curve, fade_out_curve) = config.settings.fade_curves();
+            let mut audio_fades = Vec::new();
+            if audio_fade_in_duration > 0.0 {
+                audio_fades.push(format!(
+                    "afade=t=in:st={}:d={}{}",
+                    timeline_start,
+                    audio_fade_in_duration,
+                    curve_option(fade_in_curve)
+                ));
+            }
+            // A stdin stream with no clip_end_time has no end, the config check only lets that through with the fade-out off
+            if let Some(timeline_end) = timeline_end {
+                let fade_out_duration = fade_seconds("fade_out_duration", fade_out_duration)?;
+                let audio_fade_out_duration = match audio_fade_out_duration {
+                    Some(_) => fade_seconds("audio_fade_out_duration", audio_fade_out_duration)?,
+                    None => fade_out_duration,
+                };
+                // Overlapping fades would start the fade-out before the clip does, or before it even exists
+                let clip_length = timeline_end - timeline_start;
+                for (name, fade_in_duration, fade_out_duration) in [
+                    ("fade", fade_in_duration, fade_out_duration),
+                    ("audio fade", audio_fade_in_duration, audio_fade_out_duration),
+                ] {
+                    if fade_in_duration + fade_out_duration > clip_length {
+                        return Err(ProcessingError::ConfigInvalid(format!(
+                            "The {}-in ({}s) and {}-out ({}s) together are longer than the clip ({}s)",
+                            name, fade_in_duration, name, fade_out_duration, clip_length
+                        )));
+                    }
+                }
+                let fade_out_start_time = timeline_end - fade_out_duration;
+                let audio_fade_out_start_time = timeline_end - audio_fade_out_duration;
+                if fade_out_duration > 0.0 {
+                    video_fades.push(format!(
+                        "fade=t=out:st={}:d={}{}",
+                        fade_out_start_time,
+                        fade_out_duration,
+                        fade_color(fade_out_color)
+                    ));
+                }
+                if audio_fade_out_duration > 0.0 {
+                    audio_fades.push(format!(
+                        "afade=t=out:st={}:d={}{}",
+                        audio_fade_out_start_time,
+                        audio_fade_out_duration,
+                        curve_option(fade_out_curve)
+                    ));
+                }
+            }
+            (video_fades, audio_fades)
+        }
+    };
+
+    let mut video_filters = Vec::new();
+
+    if let Some(ref mode) = deinterlace {
+        match mode.to_lowercase().as_str() {
+            "none" => {}
+            "auto" if interlaced => video_filters.push("yadif".to_string()),
+            "auto" => {}
+            mode => {
+                // Deinterlacing progressive frames only softens them, but the probe can miss flags some files lack
+                if info.is_some_and(|info| !info.interlaced) {
+                    warn!("deinterlace is set to {} but the input looks progressive, \"auto\" would leave it alone", mode);
+                }
+                video_filters.push(mode.to_string());
+            }
+        }
+    }
+
+    // Straight after deinterlacing, so every filter after it works on SDR frames. Without a probe (stdin) the
+    // source can't be checked, so it's taken at its word
+    let tonemapped = *tonemap && info.is_none_or(|info| info.is_hdr());
+    if tonemapped {
+        video_filters.push(TONEMAP_FILTER.to_string());
+    } else if *tonemap {
+        warn!("tonemap is set but the input isn't HDR (PQ or HLG), leaving its colors alone");
+    }
+
+    // Cropped first so everything after it, from the denoising to the watermark position, works on the new frame
+    if let Some(crop) = crop {
+        if let Some((width, height)) = size {
+            let fits = |offset: Option<u32>, size: u32, source: u32| size <= source && offset.unwrap_or(0) + size <= source;
+            if !fits(crop.x, crop.width, width) || !fits(crop.y, crop.height, height) {
+                return Err(ProcessingError::ConfigInvalid(format!(
+                    "The crop of {}x{} at {},{} doesn't fit in the {}x{} input",
+                    crop.width,
+                    crop.height,
+                    crop.x.map_or("center".to_string(), |x| x.to_string()),
+                    crop.y.map_or("center".to_string(), |y| y.to_string()),
+                    width,
+                    height
+                )));
+            }
+        }
+        video_filters.push(crop.filter());
+    }
+
+    if denoise.is_some() || denoise_params.is_some() {
+        let method = denoise_method.as_deref().unwrap_or("hqdn3d").to_lowercase();
+        let strength = denoise.as_deref().unwrap_or_default().to_lowercase();
+        // Raw parameters replace the presets, for anyone who knows the filter's options
+        let custom = denoise_params.as_ref().map(|params| format!("{}={}", method, params));
+        let filter = match (method.as_str(), strength.as_str()) {
+            _ if custom.is_some() => custom.as_deref(),
+            (_, "none") => None,
+            ("nlmeans", "light") => Some("nlmeans=s=2"),
+            ("nlmeans", "medium") => Some("nlmeans=s=4"),
+            ("nlmeans", _) => Some("nlmeans=s=6"),
+            (_, "light") => Some("hqdn3d=2:1.5:3:2.25"),
+            (_, "medium") => Some("hqdn3d=4:3:6:4.5"),
+            (_, _) => Some("hqdn3d=8:6:12:9"),
+        };
+        if let Some(filter) = filter {
+            if method == "nlmeans" {
+                warn!("nlmeans gives the best denoising but is very slow, expect a long encode");
+            }
+            video_filters.push(filter.to_string());
+        }
+    }
+
+    // vidstabdetect has to see the frames exactly as vidstabtransform will, so it gets the same filters up to this point
+    let transforms_path = if output_video_path == "-" {
+        env::temp_dir().join(format!("clippy-{}.trf", process::id())).to_string_lossy().into_owned()
+    } else {
+        format!("{}.trf", output_video_path)
+    };
+    let chapters_path = if output_video_path == "-" {
+        env::temp_dir().join(format!("clippy-{}-chapters.txt", process::id())).to_string_lossy().into_owned()
+    } else {
+        format!("{}.chapters.txt", output_video_path)
+    };
+    // x265 writes its first pass analysis here, plus a .cutree file next to it
+    let pass_log_path = if output_video_path == "-" {
+        env::temp_dir().join(format!("clippy-{}-x265.log", process::id())).to_string_lossy().into_owned()
+    } else {
+        format!("{}.x265.log", output_video_path)
+    };
+    // -x265-params is a ':' separated list, so a Windows drive letter needs the same escaping as in a filter graph
+    let pass_log_param = pass_log_path.replace('\\', "/").replace(':', "\\:");
+    let stabilize_detect_filters = if *stabilize {
+        if reading_stdin {
+            return Err(ProcessingError::ConfigInvalid(
+                "stabilize needs two passes over the input, which isn't possible when reading from stdin".to_string(),
+            ));
+        }
+        let mut detect_filters = video_filters.clone();
+        detect_filters.push(format!("vidstabdetect=result={}", escape_filter_path(&transforms_path)));
+        video_filters.push(format!("vidstabtransform=input={}", escape_filter_path(&transforms_path)));
+        Some(detect_filters.join(","))
+    } else {
+        None
+    };
+
+    // Before the subtitles and the watermark so they end up upright, and before scaling so upscale_resolution is the
+    // size of the turned picture
+    if let Some(filter) = rotation_filter(rotate.as_deref()) {
+        video_filters.push(filter.to_string());
+    }
+
+    // Flips and negation go before the fades so a fade to black stays black instead of being inverted to white
+    for (enabled, filter) in [(*flip_horizontal, "hflip"), (*flip_vertical, "vflip"), (*negate, "negate")] {
+        if enabled {
+            video_filters.push(filter.to_string());
+        }
+    }
+    // Burned in before the fades so they fade out with the picture, and before scaling so they're rendered at the
+    // source size the subtitle file was made for
+    if let Some(path) = subtitle_path.as_ref().filter(|path| path.to_lowercase() != "none") {
+        if !Path::new(path).is_file() {
+            return Err(ProcessingError::ConfigInvalid(format!("The subtitle file {} doesn't exist", path)));
+        }
+        let style = subtitle_style
+            .as_ref()
+            .map(|style| format!(":force_style='{}'", style.replace('\'', "'\\''")))
+            .unwrap_or_default();
+        // The subtitles are timed against the source, which an input seek no longer starts at
+        let filter = format!("subtitles=filename={}{}", escape_filter_path(path), style);
+        if input_seek {
+            video_filters.push(format!("setpts=PTS+{}/TB,{},setpts=PTS-STARTPTS", clip_start_time_float, filter));
+        } else {
+            video_filters.push(filter);
+        }
+    }
+
+    // The overlay needs the watermark as a second input, so the chain is split around it with labels. It goes in
+    // before the fades so the logo fades out with the picture
+    let watermark = watermark_path.as_ref().filter(|path| path.to_lowercase() != "none");
+    if watermark.is_some() {
+        let input = 1 + background_audio.len();
+        let position = watermark_position.as_deref().unwrap_or("bottom-right").to_lowercase();
+        video_filters.push(format!(
+            "null[wmbase];[{}:v]format=rgba,colorchannelmixer=aa={}[wm];[wmbase][wm]overlay={}",
+            input + bumper_inputs.len(),
+            watermark_opacity.unwrap_or(1.0),
+            watermark_coordinates(&position)
+        ));
+    }
+
+    // Letterboxed (or pillarboxed) after the subtitles and the watermark so those stay on the picture, and before the
+    // scaling so upscale_resolution gets the padded frame. The pixels are made square first, otherwise the bars would
+    // be worked out for the stored size rather than the one that's shown. The ratio stays a fraction in the expression,
+    // 16:9 as a rounded decimal would pad a 1920x1080 input by a pixel or two
+    if let Some(aspect) = pad_to_aspect {
+        let (width, height) = aspect.split_once(':').unwrap_or((aspect, "1"));
+        let (width, height) = (width.trim(), height.trim());
+        if *blurred_background {
+            // The picture is split in two: one copy is scaled up until it covers the whole frame, cropped to the
+            // aspect ratio and blurred, and the other goes on top of it in the middle at its own size
+            video_filters.push(format!(
+                "scale=trunc(iw*sar/2)*2:trunc(ih/2)*2,setsar=1,split[blurbg][blurfg];\
+                 [blurbg]scale=w='max(iw,round(ih*{width}/{height}/2)*2)':h='max(ih,round(iw*{height}/{width}/2)*2)':\
+                 force_original_aspect_ratio=increase:force_divisible_by=2,\
+                 crop=w='min(iw,round(ih*{width}/{height}/2)*2)':h='min(ih,round(iw*{height}/{width}/2)*2)',\
+                 gblur=sigma=20[blurred];[blurred][blurfg]overlay=(W-w)/2:(H-h)/2"
+            ));
+        } else {
+            video_filters.push(format!(
+                "scale=trunc(iw*sar/2)*2:trunc(ih/2)*2,setsar=1,\
+                 pad=w='max(iw,round(ih*{width}/{height}/2)*2)':h='max(ih,round(iw*{height}/{width}/2)*2)':\
+                 x=(ow-iw)/2:y=(oh-ih)/2:color={}",
+                pad_color.as_deref().unwrap_or("black")
+            ));
+        }
+    }
+
+    if let Some(ref resolution) = upscale_resolution {
+        if resolution.to_lowercase() != "none" {
+            video_filters.push(format!("scale={}", resolution));
+        }
+    }
+    if let Some(filter) = max_resolution_filter(max_resolution.as_deref()) {
+        video_filters.push(filter);
+    }
+    // On the finished, scaled picture, pad bars included, but before the fades so their colors come out as they're
+    // set instead of grey or brown
+    if let Some(filter) = color_effect_filter(color_effect.as_deref()) {
+        video_filters.push(filter);
+    }
+    // Without the video fade only the audio fades, which leaves the video free to be copied when nothing else touches it
+    if *video_fade && !video_fades.is_empty() {
+        video_filters.push(video_fades.join(","));
+    }
+    // Sharpening has to come after scaling, otherwise the scaler softens it right back out
+    if let Some(ref params) = sharpen_params {
+        video_filters.push(format!("unsharp={}", params));
+    } else if let Some(amount) = sharpen {
+        video_filters.push(format!("unsharp=5:5:{}:5:5:0.0", amount));
+    }
+    if *vignette {
+        match vignette_angle {
+            Some(angle) => video_filters.push(format!("vignette=angle={}", angle)),
+            None => video_filters.push("vignette".to_string()),
+        }
+    }
+    // Grain is added at the output size, scaling it afterwards would smear it
+    if let Some(strength) = film_grain {
+        video_filters.push(format!("noise=alls={}:allf=t", strength));
+    }
+    let interpolate_slow_motion = *interpolate && *video_speed < 1.0;
+    // Video and audio are both retimed from a zero start with the same factor so they can't drift apart
+    if *video_speed != 1.0 {
+        video_filters.push(format!("setpts=(PTS-STARTPTS)/{}", video_speed));
+    }
+    if let Some(range) = color_range {
+        video_filters.push(format!("setrange=range={}", range.to_lowercase()));
+    }
+    match target_fps {
+        Some(fps) if *interpolate => video_filters.push(format!("minterpolate=fps={}", fps)),
+        Some(fps) => video_filters.push(format!("fps={}", fps)),
+        None if interpolate_slow_motion => video_filters.push(format!("minterpolate=fps={}", source_framerate()?)),
+        None => {}
+    }
+    if *interpolate {
+        if interpolate_slow_motion || target_fps.is_some() {
+            warn!("Frame interpolation is CPU-intensive, expect the encode to take a lot longer");
+        } else {
+            warn!("interpolate only has an effect when video_speed is below 1.0 or target_fps is set, ignoring it");
+        }
+    }
+
+    // An explicit target_fps always wins, otherwise keep every source frame when the speed changes
+    let output_framerate = match target_fps {
+        Some(fps) => Some(*fps),
+        None if *video_speed != 1.0 && !interpolate_slow_motion => Some(source_framerate()? * video_speed),
+        None => None,
+    };
+
+    let video_filter_str = video_filters.join(",");
+
+    let mut audio_filters = Vec::new();
+    // Normalized before the fades, loudnorm would otherwise lift them back up. It works at 192kHz internally, which
+    // is resampled back to a rate the encoders take
+    if *loudnorm {
+        audio_filters.push(format!(
+            "loudnorm=I={}:TP=-1.5:LRA=11,aresample={}",
+            loudnorm_target.unwrap_or(-16.0),
+            audio_sample_rate.unwrap_or(48000)
+        ));
+    }
+    if !audio_fades.is_empty() {
+        audio_filters.push(audio_fades.join(","));
+    }
+    if *video_speed != 1.0 {
+        audio_filters.push(format!("asetpts=PTS-STARTPTS,{}", atempo_chain(*video_speed)));
+    }
+    // The whole mix turned up or down at once, before the limiter so a boost can't push it into clipping
+    if let Some(volume) = master_volume.filter(|volume| !volume.is_unchanged()) {
+        audio_filters.push(format!("volume={}", volume));
+    }
+    // Last, so it catches whatever the volumes and the mix add up to. alimiter turns its limit into a gain that
+    // pulls everything up to full scale unless level is off
+    if *audio_limiter {
+        let limit = 10f64.powf(audio_limiter_threshold.unwrap_or(-1.0) / 20.0);
+        audio_filters.push(format!("alimiter=limit={:.4}:level=0", limit));
+    }
+    // The audio graph always ends in a filter, even when there's nothing left to do to the audio
+    if audio_filters.is_empty() {
+        audio_filters.push("anull".to_string());
+    }
+
+    let audio_filter_str = audio_filters.join(",");
+
+    // Without the video there's nothing for the GPU to encode, whether it's there doesn't matter
+    let hardware_encoder = config.settings.hardware_encoder().filter(|_| !*audio_only);
+    let gpu_encoder = match hardware_encoder {
+        Some(encoder) if gpu_encoder_available(ffmpeg_path, encoder, *gpu_fallback)? => Some(encoder),
+        _ => None,
+    };
+    let codec = config.settings.software_codec();
+    let video_codec = gpu_encoder.map_or(codec.ffmpeg_name(), GpuEncoder::ffmpeg_name);
+    // The presets were checked against the configured encoder, libx265 wouldn't know what to do with p4 or balanced
+    let fell_back = gpu_encoder != hardware_encoder;
+    if fell_back && (encoder_preset.is_some() || encoder_tune.is_some()) {
+        warn!("encoder_preset and encoder_tune are meant for the GPU encoder, leaving them out for libx265");
+    }
+    // ffmpeg's mp4 muxer takes VP9 and AV1 too, but most of the rest don't, or only players that are in on it
+    let container = config.settings.container();
+    if codec.is_web() && !*audio_only {
+        if let Some(container) = container.as_deref().filter(|container| !WEB_CODEC_CONTAINERS.contains(container)) {
+            warn!(
+                "{} isn't a usual home for {} video, webm, mkv or mp4 are the safe choices",
+                container,
+                codec.probe_name().to_uppercase()
+            );
+        }
+    }
+    // WebM only takes Opus or Vorbis audio, aac would be refused. An audio file's extension names its codec
+    let default_audio_codec = match container.as_deref() {
+        Some("webm" | "opus" | "ogg") => "libopus",
+        Some("mp3") => "libmp3lame",
+        Some("flac") => "flac",
+        Some("wav") => "pcm_s16le",
+        _ => "aac",
+    };
+    // The default aac is always built in, a chosen codec like libopus or libfdk_aac depends on how ffmpeg was built
+    if let Some(codec) = audio_codec {
+        if !probe::encoders(ffmpeg_path)?.iter().any(|listed| listed == codec) {
+            return Err(ProcessingError::ConfigInvalid(format!(
+                "The ffmpeg at {} has no {} encoder for audio_codec",
+                ffmpeg_path, codec
+            )));
+        }
+    }
+
+    let mut filter_graph = Vec::new();
+    let (video_source, audio_source) = match segment_graph {
+        Some((ref graph, _)) => {
+            filter_graph.push(graph.clone());
+            ("[sv]", "[sa]")
+        }
+        None => ("[0:v]", "[0:a]"),
+    };
+    let (video_source, audio_source) = if has_bumpers {
+        let (width, height) = size.ok_or_else(|| {
+            ProcessingError::ProbeParse("Could not determine the video size to fit the joined clips to".to_string())
+        })?;
+        // Speed segments already trimmed the clip, otherwise it's trimmed here instead of with -ss/-to, which would
+        // cut the combined video
+        let main_trim = match segment_graph {
+            Some(_) => None,
+            None => {
+                let clip_end_time_float = clip_end_time_float
+                    .ok_or_else(|| ProcessingError::ProbeParse("Could not determine the clip length".to_string()))?;
+                Some((clip_start_time_float, clip_end_time_float))
+            }
+        };
+        let first_bumper_input = 1 + background_audio.len();
+        match second {
+            Some(ref second) => {
+                let main_length = match (&segment_graph, main_trim) {
+                    (Some((_, output_duration)), _) => *output_duration,
+                    (None, Some((start, end))) => end - start,
+                    (None, None) => 0.0,
+                };
+                if crossfade_duration >= main_length || crossfade_duration >= second.duration {
+                    return Err(ProcessingError::ConfigInvalid(format!(
+                        "crossfade_duration ({}s) must be shorter than both clips ({:.2}s and {:.2}s)",
+                        crossfade_duration, main_length, second.duration
+                    )));
+                }
+                filter_graph.push(crossfade_graph(
+                    (video_source, audio_source),
+                    main_trim,
+                    main_length,
+                    (second, crossfade_duration),
+                    first_bumper_input + second.input,
+                    (width, height),
+                    source_framerate()?,
+                ));
+            }
+            None => filter_graph.push(bumper_graph(
+                (video_source, audio_source),
+                main_trim,
+                (intro.as_ref(), &concat_clips, outro.as_ref()),
+                first_bumper_input,
+                (width, height),
+                source_framerate()?,
+            )),
+        }
+        ("[cv]", "[ca]")
+    } else {
+        (video_source, audio_source)
+    };
+
+    let mut seek_args = Vec::new();
+    let mut trim_args = Vec::new();
+    // The output options cut what comes out of the graph, which video_speed has already retimed, so the source times
+    // are scaled to where they ended up. The fades run before the retiming and stay at the source times
+    let retimed = |seconds: f64| (seconds / video_speed).to_string();
+    if input_seek {
+        seek_args.extend(vec!["-ss".to_string(), clip_start_time_float.to_string()]);
+    } else if segment_graph.is_none() && !has_bumpers && clip_start_time_float > 0.0 {
+        trim_args.extend(vec!["-ss".to_string(), retimed(clip_start_time_float)]);
+    }
+    if segment_graph.is_none() && !has_bumpers {
+        if let Some(clip_end_time_float) = clip_end_time_float {
+            if duration.is_none_or(|duration| clip_end_time_float < duration) {
+                // After an input seek the output starts at zero, so the end becomes a length
+                if input_seek {
+                    trim_args.extend(vec!["-t".to_string(), retimed(clip_end_time_float - clip_start_time_float)]);
+                } else {
+                    trim_args.extend(vec!["-to".to_string(), retimed(clip_end_time_float)]);
+                }
+            }
+        }
+    }
+
+    // The mix ends with the video's audio, so a track that runs out first leaves the rest of the video without it
+    let video_length = clip_length.or(duration.map(|duration| duration - clip_start_time_float));
+    if let (false, Some(video_length)) = (*loop_background_audio, video_length) {
+        for audio_path in &background_audio {
+            let audio_length = probe::probe_media(ffmpeg_path, audio_path).ok().and_then(|info| info.duration);
+            if let Some(audio_length) = audio_length.map(|length| length - audio_start_time) {
+                if audio_length + background_audio_delay.unwrap_or(0.0) + BACKGROUND_AUDIO_SLACK_SECS < video_length {
+                    warn!(
+                        "The background audio {} runs for {:.2}s from audio_start_time but the video is {:.2}s, \
+                         set loop_background_audio to fill the rest",
+                        audio_path, audio_length, video_length
+                    );
+                }
+            }
+        }
+    }
+
+    let mut ffmpeg_command = vec![ffmpeg_path.clone()];
+    ffmpeg_command.extend(seek_args.iter().cloned());
+    ffmpeg_command.extend(input_args.iter().cloned());
+
+    for audio_path in &background_audio {
+        if *loop_background_audio {
+            ffmpeg_command.extend(vec!["-stream_loop".to_string(), "-1".to_string()]);
+        }
+        // With an output seek the background audio plays from the start of the source and is cut along with it, an
+        // input seek has to skip the same stretch to line up the same way. A delayed track is placed by its delay
+        // instead and always starts from audio_start_time
+        let audio_seek = if input_seek && background_audio_delay.is_none() {
+            audio_start_time + clip_start_time_float
+        } else {
+            *audio_start_time
+        };
+        ffmpeg_command.extend(vec![
+            "-ss".to_string(),
+            audio_seek.to_string(),
+            "-i".to_string(),
+            audio_path.to_string(),
+        ]);
+    }
+    for path in &bumper_inputs {
+        ffmpeg_command.extend(vec!["-i".to_string(), path.clone()]);
+    }
+    if let Some(path) = watermark {
+        ffmpeg_command.extend(vec!["-i".to_string(), path.clone()]);
+    }
+    // Only the chapters are taken from it, it's the last input so nothing else has to know it's there
+    let chapters_input = 1 + background_audio.len() + bumper_inputs.len() + usize::from(watermark.is_some());
+    if chapters_file.is_some() {
+        ffmpeg_command.extend(vec!["-i".to_string(), chapters_path.clone()]);
+    }
+    // After every input, so they trim the output instead of being taken as options of the next input
+    ffmpeg_command.extend(trim_args.iter().cloned());
+
+    // The segment and bumper graphs cut and join the input's audio along with its video
+    if !has_audio && audio_source != "[0:a]" {
+        return Err(ProcessingError::ConfigInvalid(
+            "speed_segments, clip_ranges, intro_path/outro_path, concat_inputs and second_input_video_path need an input \
+             with an audio track"
+                .to_string(),
+        ));
+    }
+    // Without an audio track and nothing to mix in there's no audio to make, the output is video only
+    let silent = !has_audio && background_audio.is_empty();
+    if silent && *audio_only {
+        return Err(ProcessingError::ConfigInvalid(
+            "The input has no audio track, so audio_only would have nothing to write".to_string(),
+        ));
+    }
+
+    // Silence in front of the background tracks so they start that far into the output, counted from where the graph's
+    // timeline has the clip start
+    let background_delay = background_audio_delay
+        .map(|delay| format!(",adelay={}:all=1", ((timeline_start + delay) * 1000.0).round() as u64))
+        .unwrap_or_default();
+    // The labels of the audio the graph ends in, one per output track
+    let mut audio_outputs = if silent { Vec::new() } else { vec!["[a]".to_string()] };
+    let audio_graph = if background_audio.is_empty() {
+        format!("{}volume={},{}[a]", audio_source, original_audio_volume, audio_filter_str)
+    } else if *separate_audio_tracks {
+        let mut chains = Vec::new();
+        if *replace_audio || !has_audio {
+            audio_outputs.clear();
+            if audio_source != "[0:a]" {
+                chains.push(format!("{}anullsink", audio_source));
+            }
+        } else {
+            chains.push(format!("{}volume={},{}[a]", audio_source, original_audio_volume, audio_filter_str));
+        }
+        // Every track gets the fades and speed change of its own. Padded with silence so -shortest can end them all
+        // with the video, a track running longer than the clip would keep the file going past it
+        for index in 0..background_audio.len() {
+            let volume = config.settings.background_audio_volume_of(index);
+            chains.push(format!(
+                "[{}:a]volume={}{},{},apad[bg{}]",
+                index + 1,
+                volume,
+                background_delay,
+                audio_filter_str,
+                index + 1
+            ));
+            audio_outputs.push(format!("[bg{}]", index + 1));
+        }
+        chains.join(";")
+    } else {
+        // There's nothing to mix the music into for a silent input like a screen recording
+        if !*replace_audio && !has_audio {
+            warn!("The input has no audio track, using only the background audio instead of mixing it in");
+        }
+
+        let mut chains = Vec::new();
+        let mut mix_inputs = Vec::new();
+        if *replace_audio || !has_audio {
+            // The segment and bumper graphs always produce an audio output, which ffmpeg insists is used
+            if audio_source != "[0:a]" {
+                chains.push(format!("{}anullsink", audio_source));
+            }
+        } else {
+            chains.push(format!("{}volume={}[a0]", audio_source, original_audio_volume));
+            mix_inputs.push("[a0]".to_string());
+        }
+        for index in 0..background_audio.len() {
+            let volume = config.settings.background_audio_volume_of(index);
+            chains.push(format!("[{}:a]volume={}{}[a{}]", index + 1, volume, background_delay, index + 1));
+            mix_inputs.push(format!("[a{}]", index + 1));
+        }
+        // The fades and speed change go on the mix so every track stays in step with the video. duration=first ends
+        // the mix with the original audio, or with the first track when that one's replaced
+        let mix = if mix_inputs.len() == 1 {
+            format!("{}{}[a]", mix_inputs[0], audio_filter_str)
+        } else {
+            format!(
+                "{}amix=inputs={}:duration=first:dropout_transition=3,{}[a]",
+                mix_inputs.concat(),
+                mix_inputs.len(),
+                audio_filter_str
+            )
+        };
+        chains.push(mix);
+        chains.join(";")
+    };
+
+    let mut video_args = Vec::new();
+    let mut encoder = video_codec;
+    // What the size estimate goes by, a crf or a copied stream leave the size up to the source and the encoder
+    let mut estimate_bitrate = None;
+    let mut crf_mode = false;
+    // The segment, bumper and comparison graphs build a new video stream, only the input's own one can be copied. The
+    // renditions are split off the graph's video, so that has to exist as well
+    if *audio_only {
+        // The segment and bumper graphs always produce a video output, which ffmpeg insists is used
+        if video_source != "[0:v]" {
+            filter_graph.push(format!("{}nullsink", video_source));
+        }
+        encoder = "none";
+        estimate_bitrate = Some(0);
+        video_args.push("-vn".to_string());
+    } else if video_filter_str.is_empty() && video_source == "[0:v]" && !*comparison && config.outputs.is_empty() {
+        if pixel_format.is_some() {
+            warn!("pixel_format only applies when the video is re-encoded, the copied video keeps its own");
+        }
+        encoder = "copy";
+        if keyframe_interval.is_some() {
+            warn!("keyframe_interval only applies when the video is re-encoded, the copied video keeps its own keyframes");
+        }
+        video_args.extend(vec![
+            "-map".to_string(),
+            "0:v".to_string(),
+            "-c:v".to_string(),
+            "copy".to_string()
+        ]);
+    } else {
+        let video_filter_str = if video_filter_str.is_empty() { "null".to_string() } else { video_filter_str };
+        if *comparison {
+            // The untouched side still gets the size and timing changes, otherwise the two halves wouldn't line up
+            let mut original_filters = Vec::new();
+            if let Some(crop) = crop {
+                original_filters.push(crop.filter());
+            }
+            if let Some(filter) = rotation_filter(rotate.as_deref()) {
+                original_filters.push(filter.to_string());
+            }
+            if let Some(ref resolution) = upscale_resolution {
+                if resolution.to_lowercase() != "none" {
+                    original_filters.push(format!("scale={}", resolution));
+                }
+            }
+            if let Some(filter) = max_resolution_filter(max_resolution.as_deref()) {
+                original_filters.push(filter);
+            }
+            if *video_speed != 1.0 {
+                original_filters.push(format!("setpts=(PTS-STARTPTS)/{}", video_speed));
+            }
+            if let Some(fps) = target_fps {
+                original_filters.push(format!("fps={}", fps));
+            }
+            if original_filters.is_empty() {
+                original_filters.push("null".to_string());
+            }
+
+            filter_graph.push(format!(
+                "{}split=2[cmp_original][cmp_processed];[cmp_original]{}[original];[cmp_processed]{}[processed];\
+                 [original][processed]hstack=inputs=2[v]",
+                video_source,
+                original_filters.join(","),
+                video_filter_str
+            ));
+        } else {
+            filter_graph.push(format!("{}{}[v]", video_source, video_filter_str));
+        }
+        video_args.extend(vec![
+            "-map".to_string(),
+            "[v]".to_string(),
+        ]);
+
+        if let Some(rate) = output_framerate {
+            video_args.extend(vec![
+                "-r".to_string(),
+                probe::format_framerate(rate),
+            ]);
+        }
+
+        video_args.extend(vec![
+            "-c:v".to_string(),
+            video_codec.to_string(),
+        ]);
+        // libvpx encodes a single tile row at a time unless told otherwise, which leaves most cores idle
+        if gpu_encoder.is_none() && codec == VideoCodec::Vp9 {
+            video_args.extend(vec!["-row-mt".to_string(), "1".to_string()]);
+        }
+        // The GPU encoders barely touch the CPU, only the software ones are held back
+        let encoder_threads = threads.filter(|_| gpu_encoder.is_none());
+        if let Some(threads) = encoder_threads {
+            video_args.extend(vec!["-threads".to_string(), threads.to_string()]);
+        }
+        if let Some(pixel_format) = pixel_format {
+            video_args.extend(vec!["-pix_fmt".to_string(), pixel_format.to_lowercase()]);
+        }
+        // libx265 ignores ffmpeg's -pass, the passes go through its own parameters. This is the second pass, the
+        // first one is derived from the finished command below
+        let mut x265_params = Vec::new();
+        if *two_pass {
+            x265_params.push(format!("pass=2:stats={}", pass_log_param));
+        }
+        // libx265 sizes its thread pool by itself and doesn't go by -threads
+        if let Some(threads) = encoder_threads.filter(|_| codec == VideoCodec::X265) {
+            x265_params.push(format!("pools={}", threads));
+        }
+        if let Some(interval) = keyframe_interval {
+            // In frames of the output, which only differs from the source's rate with target_fps
+            let frames = interval.frames(target_fps.or(framerate)).ok_or_else(|| {
+                ProcessingError::ConfigInvalid(
+                    "The framerate of a stdin stream can't be probed, set target_fps or give keyframe_interval in frames"
+                        .to_string(),
+                )
+            })?;
+            video_args.extend(vec!["-g".to_string(), frames.max(1).to_string()]);
+            // A scene cut would otherwise get a keyframe of its own and start a new GOP there
+            if *strict_gop {
+                video_args.extend(vec![
+                    "-keyint_min".to_string(),
+                    frames.max(1).to_string(),
+                    "-sc_threshold".to_string(),
+                    "0".to_string(),
+                ]);
+                // libvpx and libaom place no keyframes between a -keyint_min and -g that agree, SVT-AV1 doesn't detect
+                // scene cuts unless asked
+                match gpu_encoder {
+                    None if codec == VideoCodec::X265 => x265_params.push("scenecut=0".to_string()),
+                    None => {}
+                    Some(GpuEncoder::Nvenc) => video_args.extend(["-strict_gop", "1", "-no-scenecut", "1"].map(str::to_string)),
+                    Some(GpuEncoder::Amf) | Some(GpuEncoder::Qsv) => {}
+                }
+            }
+        }
+        if !x265_params.is_empty() {
+            video_args.extend(vec!["-x265-params".to_string(), x265_params.join(":")]);
+        }
+
+        // Untagged output leaves players guessing the range and colors, which is where washed out or crushed blacks
+        // come from. Keep what the source says and assume SDR bt709 in limited range otherwise. A tone-mapped video
+        // is bt709 whatever the source was
+        let probed = |property: fn(&probe::MediaInfo) -> &Option<String>| {
+            info.filter(|_| !tonemapped).and_then(|info| property(info).clone())
+        };
+        let output_range = color_range.as_ref().map(|range| range.to_lowercase()).or_else(|| probed(|info| &info.color_range));
+        for (option, value) in [
+            ("-color_range", output_range.unwrap_or_else(|| "tv".to_string())),
+            ("-colorspace", probed(|info| &info.colorspace).unwrap_or_else(|| "bt709".to_string())),
+            ("-color_primaries", probed(|info| &info.color_primaries).unwrap_or_else(|| "bt709".to_string())),
+            ("-color_trc", probed(|info| &info.color_transfer).unwrap_or_else(|| "bt709".to_string())),
+        ] {
+            video_args.extend(vec![option.to_string(), value]);
+        }
+
+        // A preview is about checking the settings, not the compression, so trade quality for speed
+        let (preset_flag, presets) = config::preset_option(gpu_encoder, codec);
+        let preset = match encoder_preset {
+            _ if preview_duration.is_some() => Some(presets[0].to_string()),
+            Some(preset) if !fell_back => Some(preset.to_lowercase()),
+            _ => None,
+        };
+        if let Some(preset) = preset {
+            video_args.extend(vec![preset_flag.to_string(), preset]);
+        }
+        if let (Some(tune), Some((tune_flag, _))) = (encoder_tune, config::tune_option(gpu_encoder, codec)) {
+            if !fell_back {
+                video_args.extend(vec![tune_flag.to_string(), tune.to_lowercase()]);
+            }
+        }
+
+        match crf.as_ref().filter(|crf| crf.to_lowercase() != "none") {
+            Some(crf_value) => {
+                if video_bitrate.to_lowercase() != "none" {
+                    warn!(
+                        "Both crf and video_bitrate ({}) are set, crf takes precedence so video_bitrate is ignored. \
+                         Set video_bitrate = \"None\" to make that explicit",
+                        video_bitrate
+                    );
+                }
+                if max_bitrate.is_some() || buffer_size.is_some() {
+                    warn!("max_bitrate and buffer_size only apply with video_bitrate, a crf is in control so they're left out");
+                }
+                // The hardware encoders have no -crf, each has its own constant quality mode on the same 0-51 scale.
+                // libvpx and libaom only go by the crf alone with the bitrate zeroed, otherwise it's a ceiling
+                let quality_args: Vec<&str> = match gpu_encoder {
+                    None => match codec {
+                        VideoCodec::X265 | VideoCodec::SvtAv1 => vec!["-crf", crf_value],
+                        VideoCodec::Vp9 | VideoCodec::Aom => vec!["-crf", crf_value, "-b:v", "0"],
+                    },
+                    Some(GpuEncoder::Nvenc) => vec!["-rc", "vbr", "-cq", crf_value, "-b:v", "0"],
+                    Some(GpuEncoder::Qsv) => vec!["-global_quality", crf_value],
+                    Some(GpuEncoder::Amf) => vec!["-rc", "cqp", "-qp_i", crf_value, "-qp_p", crf_value],
+                };
+                video_args.extend(quality_args.into_iter().map(str::to_string));
+                crf_mode = true;
+            }
+            None => {
+                estimate_bitrate = parse_bitrate(video_bitrate);
+                video_args.extend(vec![
+                    "-b:v".to_string(),
+                    video_bitrate.clone(),
+                ]);
+                // Caps the rate over each buffer's worth of video, which is what streaming platforms check
+                if let Some(max_bitrate) = max_bitrate {
+                    video_args.extend(vec!["-maxrate".to_string(), max_bitrate.clone()]);
+                }
+                if let Some(buffer_size) = buffer_size {
+                    video_args.extend(vec!["-bufsize".to_string(), buffer_size.clone()]);
+                }
+            }
+        }
+    }
+    // Any rate the encoder takes works, an unusual one is more likely a typo than a target platform's requirement
+    if let Some(rate) = audio_sample_rate.filter(|rate| !COMMON_SAMPLE_RATES.contains(rate)) {
+        warn!("audio_sample_rate {} isn't one of the usual {:?}, make sure the audio encoder supports it", rate, COMMON_SAMPLE_RATES);
+    }
+    let sample_rate = audio_sample_rate.map(|rate| rate.to_string());
+    let audio_args: Vec<&str> = match audio_mode.as_deref().map(str::to_lowercase).as_deref() {
+        // The "?" keeps a silent input from failing, there's just nothing to copy then
+        Some("copy") => vec!["-map", "0:a?", "-c:a", "copy"],
+        Some("none") => {
+            // The segment and bumper graphs always produce an audio output, which ffmpeg insists is used
+            if audio_source != "[0:a]" {
+                filter_graph.push(format!("{}anullsink", audio_source));
+            }
+            vec!["-an"]
+        }
+        _ if silent => {
+            info!("The input has no audio track, leaving the audio out of the output");
+            vec!["-an"]
+        }
+        _ => {
+            filter_graph.push(audio_graph);
+            let mut args: Vec<&str> = audio_outputs.iter().flat_map(|label| ["-map", label.as_str()]).collect();
+            args.extend([
+                "-c:a",
+                audio_codec.as_deref().unwrap_or(default_audio_codec),
+                "-b:a",
+                audio_bitrate.as_deref().unwrap_or("192k"),
+            ]);
+            // Resampled on the way out, after the background tracks were mixed in at whatever rate they came at
+            if let Some(ref rate) = sample_rate {
+                args.extend(["-ar", rate]);
+            }
+            args
+        }
+    };
+
+    // A video_bitrate that's off by a factor of a thousand shows up here instead of after a long encode
+    let output_length = expected_output_duration.or(clip_length).or(duration.map(|duration| duration - clip_start_time_float));
+    let audio_estimate_bitrate = match audio_args[..] {
+        [.., "copy"] => info.and_then(|info| info.audio_bitrate),
+        ["-an"] => Some(0),
+        _ => parse_bitrate(audio_bitrate.as_deref().unwrap_or("192k")).map(|rate| rate * audio_outputs.len() as u64),
+    };
+    match (estimate_bitrate, output_length) {
+        (Some(video_rate), Some(length)) => info!(
+            "Estimated output size: {}",
+            format_size(estimated_output_size(length, video_rate, audio_estimate_bitrate.unwrap_or(0)))
+        ),
+        _ if crf_mode => info!("Estimated output size: unknown (CRF mode)"),
+        _ => {}
+    }
+
+    // Everything from here to the container applies to a single output, the renditions repeat it
+    let mut output_args = video_args;
+    output_args.extend(audio_args.iter().map(|arg| arg.to_string()));
+    // A looped or padded track never ends, when it replaces the original audio nothing else would end the mix
+    if (*loop_background_audio || *separate_audio_tracks) && !background_audio.is_empty() {
+        output_args.push("-shortest".to_string());
+    }
+    // Numbered by output audio track, which is also the order the players list them in
+    if audio_mode.as_deref().is_none_or(|mode| mode.to_lowercase() != "none") {
+        let reencoded = audio_mode.as_deref().is_none_or(|mode| mode.to_lowercase() == "reencode");
+        if reencoded && audio_track_titles.len().max(audio_track_languages.len()) > audio_outputs.len() {
+            warn!(
+                "audio_track_titles/audio_track_languages have more entries than the {} audio track(s) of the output, \
+                 the extra ones are left out",
+                audio_outputs.len()
+            );
+        }
+        // A copied input can have any number of tracks, so everything given is passed on
+        let tracks = if reencoded { audio_outputs.len() } else { usize::MAX };
+        for (index, title) in audio_track_titles.iter().take(tracks).enumerate() {
+            output_args.extend(vec![format!("-metadata:s:a:{}", index), format!("title={}", title)]);
+        }
+        for (index, language) in audio_track_languages.iter().take(tracks).enumerate() {
+            output_args.extend(vec![format!("-metadata:s:a:{}", index), format!("language={}", language)]);
+        }
+    }
+
+    if *preserve_metadata {
+        output_args.extend(vec!["-map_metadata".to_string(), "0".to_string()]);
+        // ffmpeg shifts the chapters to an output -ss/-to trim by itself, but a retimed or rearranged clip would
+        // leave them pointing at the wrong frames
+        if chapters_file.is_some() {
+            // chapters_file replaces the source's chapters
+        } else if segment_graph.is_none() && !has_bumpers && *video_speed == 1.0 {
+            output_args.extend(vec!["-map_chapters".to_string(), "0".to_string()]);
+        } else {
+            warn!("Leaving the chapters out, they wouldn't line up with the retimed or rearranged clip");
+        }
+    }
+
+    let chapters = match chapters_file {
+        Some(ref path) => {
+            let text = fs::read_to_string(path).map_err(|source| ProcessingError::Io {
+                context: format!("Failed to read the chapters file {}", path),
+                source,
+            })?;
+            let length = output_length.ok_or_else(|| {
+                ProcessingError::ConfigInvalid(
+                    "chapters_file needs the length of the output, set clip_end_time when reading from stdin".to_string(),
+                )
+            })?;
+            output_args.extend(vec!["-map_chapters".to_string(), chapters_input.to_string()]);
+            Some(
+                chapters_metadata(&text, length)
+                    .map_err(|e| ProcessingError::ConfigInvalid(format!("Invalid chapters_file {}: {}", path, e)))?,
+            )
+        }
+        None => None,
+    };
+
+    // What skip_existing = "settings" checks on the next run. Renditions carry it too, only the main output is checked
+    let tag_settings = output_video_path != "-" && skip_existing.as_deref().is_some_and(|when| when.eq_ignore_ascii_case("settings"));
+    if tag_settings {
+        output_args.extend(vec!["-metadata".to_string(), format!("{}={}", SETTINGS_TAG, config.settings.output_hash())]);
+    }
+
+    // Unchecked, whatever ffmpeg makes of them. They go before the container so the first pass can still strip that
+    output_args.extend(extra_ffmpeg_args.iter().cloned());
+
+    // ffmpeg can't guess the container of a pipe from an extension, so writing to stdout needs an explicit format
+    if let Some(ref format) = output_format {
+        output_args.extend(vec!["-f".to_string(), format.clone()]);
+    }
+    // The encode goes to a temporary file that only replaces the output once it's finished, so a failed run leaves the
+    // previous output alone. HLS and DASH write their segments under the playlist's name, which a rename would break
+    let segmented = output_format.as_ref().is_some_and(|format| ["hls", "dash"].contains(&format.to_lowercase().as_str()));
+    let atomic = *atomic_output && output_video_path != "-" && !segmented;
+    let write_path = if atomic { config::suffixed_path(output_video_path, ".tmp") } else { output_video_path.clone() };
+    let output = if output_video_path == "-" { "pipe:1".to_string() } else { write_path.clone() };
+
+    // The mov muxer writes its index last unless it's moved to the front afterwards, which a player streaming the file
+    // needs before it can start. Moving it rereads the written file, so stdout can't have it
+    let faststart_for = |container: Option<&str>| {
+        *faststart != Some(false) && container.is_some_and(|container| FASTSTART_CONTAINERS.contains(&container))
+    };
+    let output_faststart = output_video_path != "-" && faststart_for(container.as_deref());
+    if *faststart == Some(true) && !output_faststart {
+        warn!(
+            "faststart only applies to mp4 and mov files, leaving it out for the {} output",
+            if output_video_path == "-" { "stdout" } else { container.as_deref().unwrap_or("unknown") }
+        );
+    }
+    // The mov muxer only writes the metadata keys it knows of unless it's told to keep the others, like the settings tag
+    let movflags_for = |container: Option<&str>, faststart: bool| -> Vec<String> {
+        let keep_tags = tag_settings && container.is_some_and(|container| FASTSTART_CONTAINERS.contains(&container));
+        let flags = [(faststart, "+faststart"), (keep_tags, "+use_metadata_tags")];
+        let flags: String = flags.iter().filter(|(wanted, _)| *wanted).map(|(_, flag)| *flag).collect();
+        if flags.is_empty() {
+            vec![]
+        } else {
+            vec!["-movflags".to_string(), flags]
+        }
+    };
+    let output_movflags = movflags_for(container.as_deref(), output_faststart);
+
+    // Each rendition gets its own copy of the finished picture and audio, scaled and encoded as a further output of
+    // the same command, so the input is decoded and filtered only once
+    let mut renditions = Vec::new();
+    let mut rendition_args = Vec::new();
+    if !config.outputs.is_empty() {
+        let copies = config.outputs.len() + 1;
+        let label = |label: &str, index: usize| format!("[{}_{}]", label.trim_matches(['[', ']']), index);
+        let split_labels: String = (1..copies).map(|index| format!("[split_{}]", index)).collect();
+        filter_graph.push(format!("[v]split={}{}{}", copies, label("[v]", 0), split_labels));
+        let split_audio: Vec<&String> = audio_outputs.iter().filter(|output| audio_args.contains(&output.as_str())).collect();
+        for output in &split_audio {
+            let labels: String = (0..copies).map(|index| label(output, index)).collect();
+            filter_graph.push(format!("{}asplit={}{}", output, copies, labels));
+        }
+        let relabel = |args: &[String], index: usize| -> Vec<String> {
+            args.iter()
+                .map(|arg| match arg.as_str() {
+                    "[v]" => label(arg, index),
+                    _ if split_audio.contains(&arg) => label(arg, index),
+                    _ => arg.clone(),
+                })
+                .collect()
+        };
+        for (index, rendition) in config.outputs.iter().enumerate().map(|(index, rendition)| (index + 1, rendition)) {
+            let scale = rendition.resolution.as_ref().map_or("null".to_string(), |resolution| format!("scale={}", resolution));
+            filter_graph.push(format!("[split_{}]{}{}", index, scale, label("[v]", index)));
+
+            let mut args = trim_args.clone();
+            args.extend(relabel(&output_args, index));
+            // Only a bitrate is replaced, a crf keeps controlling every rendition's quality
+            if let Some(bitrate) = rendition.video_bitrate.as_ref().filter(|_| !crf_mode) {
+                if let Some(position) = args.iter().position(|arg| arg == "-b:v") {
+                    args[position + 1] = bitrate.clone();
+                }
+            }
+            let path = match preview_duration {
+                Some(_) => config::suffixed_path(&rendition.path, "_preview"),
+                None => rendition.path.clone(),
+            };
+            let extension = Path::new(&path).extension().and_then(|extension| extension.to_str());
+            let rendition_container = output_format.as_deref().or(extension).map(str::to_lowercase);
+            args.extend(movflags_for(rendition_container.as_deref(), faststart_for(rendition_container.as_deref())));
+            let rendition_write_path = if atomic { config::suffixed_path(&path, ".tmp") } else { path.clone() };
+            args.extend(vec!["-y".to_string(), rendition_write_path.clone()]);
+            rendition_args.extend(args);
+            renditions.push((rendition_write_path, path));
+        }
+        output_args = relabel(&output_args, 0);
+    }
+
+    // With the audio copied or dropped and the video copied there's no graph left at all
+    if !filter_graph.is_empty() {
+        ffmpeg_command.extend(vec!["-filter_complex".to_string(), filter_graph.join(";")]);
+    }
+    ffmpeg_command.extend(output_args);
+    ffmpeg_command.extend(output_movflags.iter().cloned());
+    ffmpeg_command.extend(vec!["-y".to_string(), output]);
+    ffmpeg_command.extend(rendition_args);
+
+    // The contact sheet is its own pass that input-seeks to the clip and picks evenly spaced frames from it
+    let contact_sheet_command = match contact_sheet_path {
+        Some(ref sheet_path) => {
+            if reading_stdin {
+                return Err(ProcessingError::ConfigInvalid(
+                    "contact_sheet_path needs a second pass over the input, which isn't possible when reading from stdin"
+                        .to_string(),
+                ));
+            }
+            let clip_end_time_float = clip_end_time_float.ok_or_else(|| {
+                ProcessingError::ProbeParse("Could not determine the clip length for the contact sheet".to_string())
+            })?;
+            let columns = contact_sheet_columns.unwrap_or(4);
+            let rows = contact_sheet_rows.unwrap_or(4);
+            let interval = (clip_end_time_float - clip_start_time_float) / (columns * rows) as f64;
+
+            let mut sheet_command = vec![
+                ffmpeg_path.clone(),
+                "-ss".to_string(),
+                clip_start_time_float.to_string(),
+                "-to".to_string(),
+                clip_end_time_float.to_string(),
+            ];
+            sheet_command.extend(input_args.iter().cloned());
+            sheet_command.extend(vec![
+                "-vf".to_string(),
+                format!(
+                    "select='isnan(prev_selected_t)+gte(t-prev_selected_t,{})',scale=320:-1,tile={}x{}",
+                    interval, columns, rows
+                ),
+                "-frames:v".to_string(),
+                "1".to_string(),
+                "-y".to_string(),
+                sheet_path.clone(),
+            ]);
+            Some(sheet_command)
+        }
+        None => None,
+    };
+
+    // A single frame of the source, input-seeked straight to it since there's nothing before it to decode
+    let thumbnail_command = match thumbnail_path {
+        Some(ref thumbnail_path) => {
+            if reading_stdin {
+                return Err(ProcessingError::ConfigInvalid(
+                    "thumbnail_path needs a second pass over the input, which isn't possible when reading from stdin"
+                        .to_string(),
+                ));
+            }
+            let clip_length = clip_end_time_float.map(|end| end - clip_start_time_float);
+            let time = match thumbnail_time {
+                Some(ref time) => config::parse_time(time)
+                    .map_err(|e| ProcessingError::InvalidClipTime(format!("Invalid thumbnail_time: {}", e)))?,
+                // The middle of the clip is less likely to be a black frame or a title card than the start
+                None => clip_length.map_or(0.0, |length| length / 2.0),
+            };
+            if let Some(clip_length) = clip_length.filter(|length| time >= *length) {
+                return Err(ProcessingError::ConfigInvalid(format!(
+                    "thumbnail_time {} is past the end of the {}s clip",
+                    time, clip_length
+                )));
+            }
+
+            let mut thumbnail_command =
+                vec![ffmpeg_path.clone(), "-ss".to_string(), (clip_start_time_float + time).to_string()];
+            thumbnail_command.extend(input_args.iter().cloned());
+            thumbnail_command.extend(vec![
+                "-frames:v".to_string(),
+                "1".to_string(),
+                "-y".to_string(),
+                thumbnail_path.clone(),
+            ]);
+            Some(thumbnail_command)
+        }
+        None => None,
+    };
+
+    let detect_command = stabilize_detect_filters.map(|detect_filters| {
+        let mut detect_command = vec![ffmpeg_path.clone()];
+        detect_command.extend(seek_args);
+        detect_command.extend(input_args);
+        detect_command.extend(trim_args);
+        detect_command.extend(vec![
+            "-vf".to_string(),
+            detect_filters,
+            "-an".to_string(),
+            "-f".to_string(),
+            "null".to_string(),
+            "-".to_string(),
+        ]);
+        detect_command
+    });
+
+    // The first pass runs the same graph at the same bitrate but only keeps x265's analysis, the video goes nowhere
+    let first_pass_command = two_pass.then(|| {
+        let mut first_pass = ffmpeg_command.clone();
+        let second_pass_params = format!("pass=2:stats={}", pass_log_param);
+        for arg in first_pass.iter_mut().filter(|arg| arg.contains(&second_pass_params)) {
+            *arg = arg.replace(&second_pass_params, &format!("pass=1:stats={}", pass_log_param));
+        }
+        // Drops "-y <output>", the -movflags the null muxer wouldn't take and the container, the graph's audio output
+        // still has to be mapped somewhere
+        first_pass.truncate(first_pass.len() - 2 - output_movflags.len());
+        if output_format.is_some() {
+            first_pass.truncate(first_pass.len() - 2);
+        }
+        first_pass.extend(vec!["-f".to_string(), "null".to_string(), "-".to_string()]);
+        first_pass
+    });
+
+    // Drawn from the finished file before it's moved into place, so the image shows the audio exactly as it was mixed,
+    // faded and encoded. Only the first audio track, the others are the separate background tracks
+    let waveform_command = match waveform_path {
+        Some(ref waveform_path) => {
+            if audio_args == ["-an"] {
+                return Err(ProcessingError::ConfigInvalid(
+                    "waveform_path draws the output's audio, but the output has none".to_string(),
+                ));
+            }
+            Some(vec![
+                ffmpeg_path.clone(),
+                "-i".to_string(),
+                write_path.clone(),
+                "-filter_complex".to_string(),
+                format!("[0:a:0]showwavespic=s={}", waveform_size.as_deref().unwrap_or("1920x240")),
+                "-frames:v".to_string(),
+                "1".to_string(),
+                "-y".to_string(),
+                waveform_path.clone(),
+            ])
+        }
+        None => None,
+    };
+
+    // The pipeline is timed in seconds, the frame total is only worked out to report progress in frames
+    let output_rate = output_framerate.or(framerate);
+    let total_frames = progress_total.zip(output_rate).map(|(total, rate)| (total * rate).round() as u64);
+
+    Ok(Plan {
+        detect_command,
+        first_pass_command,
+        ffmpeg_command,
+        contact_sheet_command,
+        thumbnail_command,
+        waveform_command,
+        chapters,
+        chapters_path,
+        write_path,
+        renditions,
+        atomic,
+        progress_total,
+        total_frames,
+        sheet_length: clip_end_time_float.map(|end| end - clip_start_time_float),
+        expected_output_duration,
+        transforms_path,
+        pass_log_path,
+        gpu_encoder,
+        encoder,
+        output_length,
+    })
+}
+
+fn process<F>(config: &Config, dry_run: bool, slots: Option<&JobSlots>, mut on_progress: F) -> Result<Outcome, ProcessingError>
+where
+    F: FnMut(Progress),
+{
+    config.validate()?;
+    config.settings.ffmpeg_executable()?;
+
+    let Settings {
+        input_video_path,
+        ffmpeg_path,
+        advanced_log,
+        progress_file,
+        retries,
+        retry_delay_secs,
+        timeout_secs,
+        print_command,
+        stabilize,
+        two_pass,
+        gpu_fallback,
+        verify_output,
+        backup_existing,
+        skip_if_matches,
+        upscale_resolution,
+        max_resolution,
+        preview_duration,
+        dump_filtergraph,
+        log_progress_interval,
+        ..
+    } = &config.settings;
+
+    let output_video_path = &output_path(&config.settings);
+    if preview_duration.is_some() && output_video_path != "-" {
+        info!("Rendering a preview to {}", output_video_path);
+    }
+
+    if output_up_to_date(&config.settings, output_video_path) {
+        info!("{} already exists, skipping {}", output_video_path, input_video_path);
+        return Ok(Outcome::Skipped);
+    }
+
+    // Probing reads from the input, which would eat the start of a piped stream, so stdin goes unprobed
+    let info = if input_video_path == "-" {
+        None
+    } else {
+        let info = probe::probe_media(ffmpeg_path, input_video_path)?;
+        let codec = config.settings.software_codec();
+        if *skip_if_matches && already_conforms(&info, codec, upscale_resolution.as_deref(), max_resolution.as_deref()) {
+            info!("{} already conforms, skipping", input_video_path);
+            return Ok(Outcome::Skipped);
+        }
+        Some(info)
+    };
+
+    let Plan {
+        detect_command,
+        first_pass_command,
+        ffmpeg_command,
+        contact_sheet_command,
+        thumbnail_command,
+        waveform_command,
+        chapters,
+        chapters_path,
+        write_path,
+        renditions,
+        atomic,
+        progress_total,
+        total_frames,
+        sheet_length,
+        expected_output_duration,
+        transforms_path,
+        pass_log_path,
+        gpu_encoder,
+        encoder,
+        output_length,
+    } = plan(config, info.as_ref())?;
+
+    if let Some(path) = dump_filtergraph {
+        let named = [
+            ("stabilize analysis", detect_command.as_ref()),
+            ("first pass", first_pass_command.as_ref()),
+            ("encode", Some(&ffmpeg_command)),
+            ("contact sheet", contact_sheet_command.as_ref()),
+            ("thumbnail", thumbnail_command.as_ref()),
+            ("waveform", waveform_command.as_ref()),
+        ];
+        let commands: Vec<(&str, &[String])> =
+            named.iter().filter_map(|(name, command)| command.map(|command| (*name, command.as_slice()))).collect();
+        fs::write(path, filtergraph_dump(&commands)).map_err(|source| ProcessingError::Io {
+            context: format!("Failed to write the filter graphs to {}", path),
+            source,
+        })?;
+        info!("Wrote the filter graphs to {}", path);
+    }
+
+    // Everything up to here has run, including the probe, so the commands are exactly what would be executed
+    if dry_run {
+        let commands = detect_command.iter().chain(first_pass_command.iter()).chain([&ffmpeg_command]);
+        let images = contact_sheet_command.iter().chain(thumbnail_command.iter()).chain(waveform_command.iter());
+        for command in commands.chain(images) {
+            println!("{}", shell_quote(command));
+        }
+        return Ok(Outcome::DryRun);
+    }
+
+    // Held until the outputs are in place. A job that was interrupted while it waited has nothing to clean up yet
+    let slot = match slots {
+        Some(slots) => Some(slots.acquire(encoder == "copy").ok_or(ProcessingError::Interrupted)?),
+        None => None,
+    };
+
+    let mut progress_file = progress_file.as_deref().map(|path| ProgressFile::new(path, config.settings.progress_by_frames()));
+    let mut progress_log = log_progress_interval.map(|interval| {
+        let name = Path::new(output_video_path).file_name().map_or(output_video_path.clone(), |name| name.to_string_lossy().into_owned());
+        ProgressLog::new(&name, Duration::from_secs_f64(interval), config.settings.progress_by_frames())
+    });
+    let retry_delay = Duration::from_secs_f64(retry_delay_secs.unwrap_or(5.0));
+    let timeout = timeout_secs.map(Duration::from_secs_f64);
+    // The frame count ffmpeg reported last, for benchmark
+    let last_frame = Cell::new(None);
+    let mut report_progress = |mut update: Progress| {
+        update.total_frames = total_frames;
+        if update.frame.is_some() {
+            last_frame.set(update.frame);
+        }
+        if let Some(progress_file) = progress_file.as_mut() {
+            progress_file.update(&update);
+        }
+        if let Some(progress_log) = progress_log.as_mut() {
+            progress_log.update(&update);
+        }
+        on_progress(update);
+    };
+
+    let log_command = |command: &[String]| {
+        if *print_command {
+            info!("Running {}", shell_quote(command));
+        }
+    };
+
+    let encode_started = Instant::now();
+    let mut result = Ok(());
+    if let Some(ref chapters) = chapters {
+        result = fs::write(&chapters_path, chapters).map_err(|source| ProcessingError::Io {
+            context: format!("Failed to write the chapters to {}", chapters_path),
+            source,
+        });
+    }
+    if let (Ok(()), Some(detect_command)) = (&result, detect_command) {
+        warn!("stabilize needs an ffmpeg build with vidstab enabled (--enable-libvidstab)");
+        info!("Analysing camera shake for stabilization...");
+
+        log_command(&detect_command);
+        result = run_ffmpeg_command(
+            &detect_command,
+            progress_total,
+            *advanced_log,
+            retries.unwrap_or(0),
+            retry_delay,
+            timeout,
+            &mut report_progress,
+        );
+    }
+
+    if let (Ok(()), Some(first_pass_command)) = (&result, first_pass_command) {
+        info!("Running the first of two passes...");
+        log_command(&first_pass_command);
+        result = run_ffmpeg_command(
+            &first_pass_command,
+            progress_total,
+            *advanced_log,
+            retries.unwrap_or(0),
+            retry_delay,
+            timeout,
+            &mut report_progress,
+        );
+        if result.is_ok() {
+            info!("First pass done, encoding the second");
+        }
+    }
+
+    // An interrupt between the passes leaves the previous output alone, it's only removed once ffmpeg has written to it
+    if result.is_ok() && interrupted() {
+        result = Err(ProcessingError::Interrupted);
+    }
+    if result.is_ok() && *backup_existing && !atomic && output_video_path != "-" && Path::new(output_video_path).exists() {
+        result = backup_output(output_video_path).map(|backup| info!("Moved the existing output to {}", backup));
+    }
+
+    if result.is_ok() {
+        info!("Starting the video processing...");
+        log_command(&ffmpeg_command);
+        // The passes before it counted frames of their own
+        last_frame.set(None);
+        result = run_ffmpeg_command(
+            &ffmpeg_command,
+            progress_total,
+            *advanced_log,
+            retries.unwrap_or(0),
+            retry_delay,
+            timeout,
+            &mut report_progress,
+        );
+        // Whatever ffmpeg got to write before it was stopped is a truncated video nobody wants
+        if matches!(result, Err(ProcessingError::Interrupted)) && output_video_path != "-" && fs::remove_file(&write_path).is_ok() {
+            info!("Removed the incomplete {}", write_path);
+        }
+    }
+    let encode_time = encode_started.elapsed();
+    let encoded_frames = last_frame.get();
+
+    if let (Ok(()), Some(sheet_command)) = (&result, contact_sheet_command) {
+        info!("Creating the contact sheet...");
+        log_command(&sheet_command);
+        result = run_ffmpeg_command(
+            &sheet_command,
+            sheet_length,
+            *advanced_log,
+            retries.unwrap_or(0),
+            retry_delay,
+            timeout,
+            &mut report_progress,
+        );
+    }
+
+    if let (Ok(()), Some(thumbnail_command)) = (&result, thumbnail_command) {
+        info!("Extracting the thumbnail...");
+        log_command(&thumbnail_command);
+        result = run_ffmpeg_command(
+            &thumbnail_command,
+            None,
+            *advanced_log,
+            retries.unwrap_or(0),
+            retry_delay,
+            timeout,
+            &mut report_progress,
+        );
+    }
+
+    if let (Ok(()), Some(waveform_command)) = (&result, waveform_command) {
+        info!("Drawing the waveform...");
+        log_command(&waveform_command);
+        result = run_ffmpeg_command(
+            &waveform_command,
+            None,
+            *advanced_log,
+            retries.unwrap_or(0),
+            retry_delay,
+            timeout,
+            &mut report_progress,
+        );
+    }
+
+    if let (Ok(()), Some(expected)) = (&result, expected_output_duration) {
+        if output_video_path != "-" {
+            check_output_duration(ffmpeg_path, &write_path, expected);
+        }
+    }
+
+    if result.is_ok() && *verify_output {
+        if output_video_path == "-" {
+            warn!("Output written to stdout can't be verified, skipping verify_output");
+        } else {
+            info!("Verifying the output...");
+            result = verify_output_file(ffmpeg_path, &write_path);
+        }
+    }
+
+    // Only a finished (and verified) encode replaces the output, anything else is thrown away
+    if atomic {
+        if result.is_ok() && *backup_existing && Path::new(output_video_path).exists() {
+            result = backup_output(output_video_path).map(|backup| info!("Moved the existing output to {}", backup));
+        }
+        if result.is_ok() {
+            result = fs::rename(&write_path, output_video_path).map_err(|source| ProcessingError::Io {
+                context: format!("Failed to move {} to {}", write_path, output_video_path),
+                source,
+            });
+        } else if fs::remove_file(&write_path).is_ok() {
+            info!("Removed the unfinished {}, {} was left as it was", write_path, output_video_path);
+        }
+        for (rendition_write_path, rendition_path) in &renditions {
+            if result.is_ok() {
+                result = fs::rename(rendition_write_path, rendition_path).map_err(|source| ProcessingError::Io {
+                    context: format!("Failed to move {} to {}", rendition_write_path, rendition_path),
+                    source,
+                });
+            } else {
+                let _ = fs::remove_file(rendition_write_path);
+            }
+        }
+    }
+
+    if let Some(progress_file) = progress_file {
+        progress_file.finish();
+    }
+    if *stabilize {
+        let _ = fs::remove_file(&transforms_path);
+    }
+    if chapters.is_some() {
+        let _ = fs::remove_file(&chapters_path);
+    }
+    if *two_pass {
+        let _ = fs::remove_file(&pass_log_path);
+        let _ = fs::remove_file(format!("{}.cutree", pass_log_path));
+    }
+    // The run on the CPU below takes a slot of its own
+    drop(slot);
+    // The encoder passed the check up front but the GPU gave out mid-encode, so the whole job runs again on the CPU
+    if let (Err(ProcessingError::GpuEncodeFailed(_)), Some(encoder), true) = (&result, gpu_encoder, *gpu_fallback) {
+        warn!("The {} encoder failed on the GPU, encoding again with libx265", encoder.ffmpeg_name());
+        let mut cpu_config = config.clone();
+        cpu_config.settings.use_gpu = false;
+        cpu_config.settings.gpu_encoder = None;
+        // The presets and tunes were picked for the GPU encoder
+        cpu_config.settings.encoder_preset = None;
+        cpu_config.settings.encoder_tune = None;
+        return process(&cpu_config, dry_run, slots, on_progress);
+    }
+    result?;
+
+    info!("All done! Your video has been processed successfully.");
+
+    Ok(Outcome::Encoded {
+        output: output_video_path.to_string(),
+        encoder,
+        duration: output_length,
+        frames: encoded_frames,
+        encode_time,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::config::SettingsBuilder;
+
+    // A minute long 1080p h264 input with stereo audio
+    const PROBED: &str = "\
+Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'in.mp4':
+  Duration: 00:01:00.00, start: 0.000000, bitrate: 8000 kb/s
+  Stream #0:0(und): Video: h264 (High), yuv420p(progressive), 1920x1080, 7800 kb/s, 30 fps, 30 tbr, 15360 tbn (default)
+  Stream #0:1(und): Audio: aac (LC), 48000 Hz, stereo, fltp, 192 kb/s (default)
+";
+
+    fn try_command(settings: SettingsBuilder) -> Result<Vec<String>, ProcessingError> {
+        let settings = settings.input_video_path("in.mp4").output_video_path("out.mp4").build();
+        let config = Config { settings, batch: Vec::new(), outputs: Vec::new() };
+        build_ffmpeg_command(&config, Some(&probe::parse_media_info(PROBED)))
+    }
+
+    fn command(settings: SettingsBuilder) -> Vec<String> {
+        try_command(settings).unwrap()
+    }
+
+    // The value that follows `flag`
+    fn arg<'a>(command: &'a [String], flag: &str) -> Option<&'a str> {
+        command.iter().position(|arg| arg == flag).map(|at| command[at + 1].as_str())
+    }
+
+    thread_local! {
+        static LOGGED: std::cell::RefCell<Vec<String>> = const { std::cell::RefCell::new(Vec::new()) };
+    }
+
+    // Keeps what's logged on each thread, so tests running side by side don't see each other's warnings
+    struct CapturedLog;
+
+    impl log::Log for CapturedLog {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            LOGGED.with(|logged| logged.borrow_mut().push(format!("{} {}", record.level(), record.args())));
+        }
+
+        fn flush(&self) {}
+    }
+
+    // What `f` returns and the lines it logged
+    fn logged<T>(f: impl FnOnce() -> T) -> (T, Vec<String>) {
+        static LOGGER: CapturedLog = CapturedLog;
+        if log::set_logger(&LOGGER).is_ok() {
+            log::set_max_level(log::LevelFilter::Trace);
+        }
+        LOGGED.with(|logged| logged.borrow_mut().clear());
+        let result = f();
+        (result, LOGGED.with(|logged| logged.take()))
+    }
+
+    // Where `args` follow each other in `command`
+    fn position(command: &[String], args: &[&str]) -> Option<usize> {
+        command.windows(args.len()).position(|window| window.iter().zip(args).all(|(arg, expected)| arg == expected))
+    }
+
+    #[test]
+    fn trims_after_the_inputs_or_seeks_in_front_of_them() {
+        let trim = || Settings::builder().clip_start_time("10").clip_end_time("40");
+
+        let output_seek = command(trim());
+        let input = position(&output_seek, &["-i", "in.mp4"]).unwrap();
+        assert!(position(&output_seek, &["-ss", "10", "-to", "40"]).unwrap() > input, "{:?}", output_seek);
+
+        let input_seek = command(trim().fast_seek(true));
+        let input = position(&input_seek, &["-i", "in.mp4"]).unwrap();
+        assert_eq!(position(&input_seek, &["-ss", "10"]), Some(input - 2), "{:?}", input_seek);
+        assert!(position(&input_seek, &["-t", "30"]).unwrap() > input, "{:?}", input_seek);
+    }
+
+    #[test]
+    fn copies_the_video_only_when_nothing_changes_it() {
+        let no_fades = || {
+            Settings::builder()
+                .clip_start_time("10")
+                .fade_in_duration(FadeDuration::Seconds(0.0))
+                .fade_out_duration(FadeDuration::Seconds(0.0))
+        };
+        assert_eq!(arg(&command(no_fades()), "-c:v"), Some("copy"));
+        assert_eq!(arg(&command(Settings::builder().clip_start_time("10")), "-c:v"), Some("libx265"));
+    }
+
+    #[test]
+    fn crf_wins_over_the_bitrate() {
+        let (bitrate_only, logs) = logged(|| command(Settings::builder().video_bitrate("8000k")));
+        assert_eq!((arg(&bitrate_only, "-b:v"), arg(&bitrate_only, "-crf")), (Some("8000k"), None));
+        assert!(!logs.iter().any(|line| line.contains("crf")), "{:?}", logs);
+
+        let (crf_only, logs) = logged(|| command(Settings::builder().video_bitrate("None").crf("23")));
+        assert_eq!((arg(&crf_only, "-crf"), arg(&crf_only, "-b:v")), (Some("23"), None));
+        assert!(!logs.iter().any(|line| line.contains("crf")), "{:?}", logs);
+
+        let (both, logs) = logged(|| command(Settings::builder().video_bitrate("8000k").crf("23")));
+        assert_eq!((arg(&both, "-crf"), arg(&both, "-b:v")), (Some("23"), None));
+        assert!(
+            logs.iter().any(|line| line.starts_with("WARN Both crf and video_bitrate (8000k) are set, crf takes precedence")),
+            "{:?}",
+            logs
+        );
+
+        // "none" is the same as leaving the crf out
+        let crf_off = command(Settings::builder().video_bitrate("8000k").crf("none"));
+        assert_eq!((arg(&crf_off, "-b:v"), arg(&crf_off, "-crf")), (Some("8000k"), None));
+    }
+
+    #[test]
+    fn any_video_filter_forces_a_reencode() {
+        let trim_only = || {
+            Settings::builder()
+                .clip_start_time("10")
+                .clip_end_time("40")
+                .fade_in_duration(FadeDuration::Seconds(0.0))
+                .fade_out_duration(FadeDuration::Seconds(0.0))
+        };
+        let copied = command(trim_only());
+        assert_eq!(arg(&copied, "-c:v"), Some("copy"));
+        assert_eq!(arg(&copied, "-filter_complex").map(|graph| graph.contains("[v]")), Some(false));
+
+        for (change, settings) in [
+            ("fade", trim_only().fade_in_duration(FadeDuration::Seconds(1.0))),
+            ("scale", trim_only().upscale_resolution("1280x720")),
+            ("speed", trim_only().video_speed(2.0)),
+        ] {
+            let reencoded = command(settings);
+            assert_eq!(arg(&reencoded, "-c:v"), Some("libx265"), "{}", change);
+        }
+    }
+
+    #[test]
+    fn background_audio_is_seeked_in_front_of_its_own_input() {
+        let mixed = || {
+            Settings::builder()
+                .clip_start_time("10")
+                .clip_end_time("40")
+                .background_audio_path("music.mp3")
+                .audio_start_time(2.0)
+                .loop_background_audio(true)
+        };
+
+        // Cut along with the source by the output seek
+        let output_seek = command(mixed());
+        let looped = ["-stream_loop", "-1", "-ss", "2", "-i", "music.mp3"];
+        assert!(position(&output_seek, &looped).is_some(), "{:?}", output_seek);
+        // Skips the same 10s the input seek skips
+        let input_seek = command(mixed().fast_seek(true));
+        assert!(position(&input_seek, &["-ss", "12", "-i", "music.mp3"]).is_some(), "{:?}", input_seek);
+        // A delayed track is placed by the delay instead
+        let delayed = command(mixed().fast_seek(true).background_audio_delay(5.0));
+        assert!(position(&delayed, &["-ss", "2", "-i", "music.mp3"]).is_some(), "{:?}", delayed);
+        assert!(arg(&delayed, "-filter_complex").unwrap().contains("adelay=5000:all=1"));
+    }
+
+    #[test]
+    fn trims_the_retimed_output_at_the_source_times() {
+        let fast = || {
+            Settings::builder()
+                .clip_start_time("10")
+                .clip_end_time("40")
+                .video_speed(2.0)
+                .fade_out_duration(FadeDuration::Seconds(3.0))
+        };
+
+        // Source 10-40 comes out of the graph at 5-20
+        let output_seek = command(fast());
+        assert!(position(&output_seek, &["-ss", "5", "-to", "20"]).is_some(), "{:?}", output_seek);
+        // The fades are placed before the retiming, on the source's timestamps
+        let graph = arg(&output_seek, "-filter_complex").unwrap();
+        assert!(graph.find("fade=t=out:st=37:d=3").unwrap() < graph.find("setpts=(PTS-STARTPTS)/2").unwrap(), "{}", graph);
+        assert!(graph.find("afade=t=out:st=37:d=3").unwrap() < graph.find("atempo=2").unwrap(), "{}", graph);
+
+        let input_seek = command(fast().fast_seek(true));
+        assert!(position(&input_seek, &["-ss", "10", "-i", "in.mp4"]).is_some(), "{:?}", input_seek);
+        assert!(position(&input_seek, &["-t", "15"]).is_some(), "{:?}", input_seek);
+        assert!(arg(&input_seek, "-filter_complex").unwrap().contains("fade=t=out:st=27:d=3"));
+
+        let slow = command(fast().video_speed(0.5));
+        assert!(position(&slow, &["-ss", "20", "-to", "80"]).is_some(), "{:?}", slow);
+    }
+
+    #[test]
+    fn a_zero_or_none_fade_is_left_out() {
+        let graph = |settings: SettingsBuilder| arg(&command(settings), "-filter_complex").unwrap().to_string();
+        let none: FadeDuration = "none".parse().unwrap();
+
+        let fade_in_only = graph(Settings::builder().fade_in_duration(FadeDuration::Seconds(2.0)).fade_out_duration(none));
+        assert!(fade_in_only.contains("fade=t=in:st=0:d=2"), "{}", fade_in_only);
+        assert!(fade_in_only.contains("afade=t=in:st=0:d=2"), "{}", fade_in_only);
+        assert!(!fade_in_only.contains("t=out"), "{}", fade_in_only);
+
+        let fade_out_only = graph(Settings::builder().fade_in_duration(none).fade_out_duration(FadeDuration::Seconds(2.0)));
+        assert!(fade_out_only.contains("fade=t=out:st=58:d=2"), "{}", fade_out_only);
+        assert!(fade_out_only.contains("afade=t=out:st=58:d=2"), "{}", fade_out_only);
+        assert!(!fade_out_only.contains("t=in"), "{}", fade_out_only);
+
+        // With nothing left to do to the audio its chain still needs a filter
+        let no_fades = graph(Settings::builder().fade_in_duration(FadeDuration::Seconds(0.0)).fade_out_duration(none));
+        assert!(!no_fades.contains("fade"), "{}", no_fades);
+        assert!(no_fades.contains("[0:a]volume=1,anull[a]"), "{}", no_fades);
+
+        // Leaving the keys out gets 3 seconds each
+        let defaults = graph(Settings::builder());
+        assert!(defaults.contains("fade=t=in:st=0:d=3") && defaults.contains("fade=t=out:st=57:d=3"), "{}", defaults);
+        assert!(defaults.contains("afade=t=in:st=0:d=3") && defaults.contains("afade=t=out:st=57:d=3"), "{}", defaults);
+    }
+
+    #[test]
+    fn an_end_past_the_input_is_clamped_to_it() {
+        let settings =
+            Settings::builder().clip_start_time("10").clip_end_time("1:30").fade_out_duration(FadeDuration::Seconds(3.0));
+        let (command, logs) = logged(|| command(settings));
+        assert!(position(&command, &["-ss", "10"]).is_some(), "{:?}", command);
+        assert_eq!(arg(&command, "-to"), None);
+        let graph = arg(&command, "-filter_complex").unwrap();
+        assert!(graph.contains("fade=t=out:st=57:d=3"), "{}", graph);
+        assert!(logs.iter().any(|line| line.contains("clip_end_time (90s) is past the end of the input")), "{:?}", logs);
+    }
+
+    #[test]
+    fn a_preview_shortens_the_clip() {
+        let preview = |seconds: f64| {
+            Settings::builder()
+                .preview_duration(seconds)
+                .fade_in_duration(FadeDuration::Seconds(1.0))
+                .fade_out_duration(FadeDuration::Seconds(1.0))
+        };
+
+        let whole = command(preview(10.0));
+        assert_eq!((arg(&whole, "-ss"), arg(&whole, "-to")), (None, Some("10")));
+        assert!(whole.last().is_some_and(|output| output.starts_with("out_preview.")), "{:?}", whole);
+
+        // From the start of the trim, and never past its end
+        let trimmed = command(preview(5.0).clip_start_time("10").clip_end_time("40"));
+        assert!(position(&trimmed, &["-ss", "10", "-to", "15"]).is_some(), "{:?}", trimmed);
+        let short = command(preview(5.0).clip_start_time("10").clip_end_time("12"));
+        assert!(position(&short, &["-ss", "10", "-to", "12"]).is_some(), "{:?}", short);
+        let input_seek = command(preview(5.0).clip_start_time("10").clip_end_time("40").fast_seek(true));
+        assert_eq!(arg(&input_seek, "-t"), Some("5"));
+    }
+
+    #[test]
+    fn fades_have_to_fit_in_the_clip() {
+        let short = || Settings::builder().clip_start_time("10").clip_end_time("14");
+        let overlapping = short().fade_in_duration(FadeDuration::Seconds(3.0)).fade_out_duration(FadeDuration::Seconds(2.0));
+        assert_eq!(
+            try_command(overlapping).unwrap_err().to_string(),
+            "The fade-in (3s) and fade-out (2s) together are longer than the clip (4s)"
+        );
+        // The audio fades are checked on their own
+        let audio = short().fade_in_duration(FadeDuration::Seconds(1.0)).audio_fade_out_duration(FadeDuration::Seconds(3.5));
+        assert_eq!(
+            try_command(audio).unwrap_err().to_string(),
+            "The audio fade-in (1s) and audio fade-out (3.5s) together are longer than the clip (4s)"
+        );
+        let fitting = short().fade_in_duration(FadeDuration::Seconds(2.0)).fade_out_duration(FadeDuration::Seconds(2.0));
+        assert!(try_command(fitting).is_ok());
+    }
+
+    #[test]
+    fn fade_out_follows_the_seek() {
+        let trim = || {
+            Settings::builder()
+                .clip_start_time("10")
+                .clip_end_time("40")
+                .fade_in_duration(FadeDuration::Seconds(1.0))
+                .fade_out_duration(FadeDuration::Seconds(3.0))
+        };
+
+        // -ss after the input cuts the output, the graph still sees the source's timestamps
+        let output_seek = command(trim());
+        let graph = arg(&output_seek, "-filter_complex").unwrap();
+        assert!(graph.contains("fade=t=in:st=10:d=1"), "{}", graph);
+        assert!(graph.contains("fade=t=out:st=37:d=3"), "{}", graph);
+        assert!(graph.contains("afade=t=out:st=37:d=3"), "{}", graph);
+
+        // -ss in front of the input restarts them at zero
+        let input_seek = command(trim().fast_seek(true));
+        let graph = arg(&input_seek, "-filter_complex").unwrap();
+        assert!(graph.contains("fade=t=in:st=0:d=1"), "{}", graph);
+        assert!(graph.contains("fade=t=out:st=27:d=3"), "{}", graph);
+        assert!(graph.contains("afade=t=out:st=27:d=3"), "{}", graph);
+    }
+
+    #[test]
+    fn atempo_is_split_into_steps_it_takes() {
+        assert_eq!(atempo_chain(1.5), "atempo=1.5");
+        assert_eq!(atempo_chain(2.0), "atempo=2");
+        assert_eq!(atempo_chain(3.0), "atempo=2,atempo=1.5");
+        assert_eq!(atempo_chain(4.0), "atempo=2,atempo=2");
+        assert_eq!(atempo_chain(0.5), "atempo=0.5");
+        assert_eq!(atempo_chain(0.25), "atempo=0.5,atempo=0.5");
+        assert_eq!(atempo_chain(0.2), "atempo=0.5,atempo=0.5,atempo=0.8");
+    }
+
+    #[test]
+    fn reports_the_encode_speed() {
+        let config = Config { settings: Settings::default(), batch: Vec::new(), outputs: Vec::new() };
+        let encoded = Ok(Outcome::Encoded {
+            output: "clippy-no-such-output.mp4".to_string(),
+            encoder: "libx265",
+            duration: Some(60.0),
+            frames: Some(1800),
+            encode_time: Duration::from_secs(40),
+        });
+        let report = JobReport::new(&config, &encoded, Duration::from_secs(42));
+        assert_eq!(report.status, "done");
+        assert_eq!(report.encode_seconds, Some(40.0));
+        assert_eq!(report.average_fps, Some(45.0));
+        assert_eq!(report.realtime_factor, Some(1.5));
+        assert_eq!(report.elapsed_seconds, 42.0);
+        assert_eq!(report.speed_summary().unwrap(), "1800 frames in 40.0s, 45.0 fps, 1.50x realtime with libx265");
+
+        // A copy that's over before it's measured has no speed to speak of
+        let instant = Ok(Outcome::Encoded {
+            output: "clippy-no-such-output.mp4".to_string(),
+            encoder: "copy",
+            duration: Some(60.0),
+            frames: None,
+            encode_time: Duration::ZERO,
+        });
+        let report = JobReport::new(&config, &instant, Duration::from_millis(5));
+        assert_eq!((report.encode_seconds, report.average_fps, report.realtime_factor), (None, None, None));
+        assert_eq!(report.speed_summary(), None);
+
+        let skipped = JobReport::new(&config, &Ok(Outcome::Skipped), Duration::from_millis(5));
+        assert_eq!((skipped.status, skipped.speed_summary()), ("skipped", None));
+    }
+
+    #[test]
+    fn color_effects_are_filters() {
+        assert_eq!(color_effect_filter(Some("grayscale")).as_deref(), Some("hue=s=0"));
+        assert_eq!(
+            color_effect_filter(Some("Sepia")).as_deref(),
+            Some("colorchannelmixer=.393:.769:.189:0:.349:.686:.168:0:.272:.534:.131")
+        );
+        assert_eq!(color_effect_filter(Some("curves=preset=vintage")).as_deref(), Some("curves=preset=vintage"));
+        assert_eq!(color_effect_filter(Some("none")), None);
+        assert_eq!(color_effect_filter(None), None);
+    }
+
+    #[test]
+    fn color_effect_goes_between_scaling_and_the_fades() {
+        let settings = Settings::builder().upscale_resolution("1280x720").color_effect("grayscale").sharpen(0.8);
+        let graph = command(settings);
+        let graph = arg(&graph, "-filter_complex").unwrap();
+        let at = |filter: &str| graph.find(filter).unwrap_or_else(|| panic!("no {} in {}", filter, graph));
+        assert!(at("scale=1280x720") < at("hue=s=0"), "{}", graph);
+        assert!(at("hue=s=0") < at("fade=t=in"), "{}", graph);
+        assert!(at("fade=t=out") < at("unsharp="), "{}", graph);
+    }
+
+    #[test]
+    fn dumps_each_chain_on_its_own_line() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        let encode = args(&[
+            "ffmpeg",
+            "-i",
+            "in.mp4",
+            "-filter_complex",
+            "[0:v]fade=t=in:st=0:d=1[v];[0:a]afade=t=in:st=0:d=1[a]",
+            "out.mp4",
+        ]);
+        let thumbnail = args(&["ffmpeg", "-i", "out.mp4", "-vf", "drawtext=text='a;b',scale=320:-2", "thumb.jpg"]);
+        let copy = args(&["ffmpeg", "-i", "in.mp4", "-c", "copy", "out.mp4"]);
+
+        let dump = filtergraph_dump(&[("encode", &encode), ("thumbnail", &thumbnail), ("copy", &copy)]);
+        assert_eq!(
+            dump,
+            "# encode (-filter_complex)\n\
+             [0:v]fade=t=in:st=0:d=1[v];\n\
+             [0:a]afade=t=in:st=0:d=1[a]\n\
+             \n\
+             # thumbnail (-vf)\n\
+             drawtext=text='a;b',scale=320:-2\n\
+             \n"
+        );
+    }
+
+    #[test]
+    fn splits_chains_only_at_unescaped_semicolons() {
+        assert_eq!(split_chains("a;b"), ["a;", "b"]);
+        assert_eq!(split_chains("drawtext=text='x;y';b"), ["drawtext=text='x;y';", "b"]);
+        assert_eq!(split_chains("drawtext=text=x\\;y;b"), ["drawtext=text=x\\;y;", "b"]);
+        assert_eq!(split_chains("a"), ["a"]);
+    }
+
+    #[test]
+    fn progress_is_logged_once_per_interval() {
+        let mut log = ProgressLog::new("out.mp4", Duration::from_secs(30), false);
+        let start = log.last_log;
+        let at = |elapsed: f64| Progress { elapsed, total: Some(100.0), speed: Some(2.0), frame: None, total_frames: None };
+
+        assert_eq!(log.line(&at(10.0), start + Duration::from_secs(10)), None);
+        assert_eq!(
+            log.line(&at(60.0), start + Duration::from_secs(30)).as_deref(),
+            Some("Progress of out.mp4: 60% (60/100s, ETA 00:00:20)")
+        );
+        // The interval starts over from the line that was logged
+        assert_eq!(log.line(&at(90.0), start + Duration::from_secs(59)), None);
+        assert!(log.line(&at(100.0), start + Duration::from_secs(60)).is_some());
+    }
+
+    #[test]
+    fn progress_eta_falls_back_to_the_time_taken() {
+        let mut log = ProgressLog::new("out.mp4", Duration::ZERO, false);
+        let start = log.started;
+        let quarter = Progress { elapsed: 25.0, total: Some(100.0), speed: None, frame: None, total_frames: None };
+        assert_eq!(
+            log.line(&quarter, start + Duration::from_secs(60)).as_deref(),
+            Some("Progress of out.mp4: 25% (25/100s, ETA 00:03:00)")
+        );
+        let stream = Progress { total: None, ..quarter };
+        assert_eq!(log.line(&stream, start + Duration::from_secs(61)).as_deref(), Some("Progress of out.mp4: 25s encoded"));
+    }
+
+    #[test]
+    fn reads_progress_from_ffmpegs_output() {
+        let stderr = "\
+Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'in.mp4':
+frame=  150 fps= 45 q=28.0 size=1024kB time=00:00:05.00 bitrate=1677.7kbits/s speed=1.5x\r\
+frame=  300 fps= 45 q=28.0 size=2048kB time=00:00:10.00 bitrate=1677.7kbits/s speed=1.49x\r
+out_time=00:01:02.500000
+[aac @ 0x55d0] Qavg: 1234.5
+";
+        let mut progress = Vec::new();
+        let mut other = Vec::new();
+        read_ffmpeg_output(stderr.as_bytes(), Some(60.0), |line| {
+            match line {
+                StderrLine::Progress(update) => progress.push((update.elapsed, update.total, update.speed, update.frame)),
+                StderrLine::Other(line) => other.push(line),
+            }
+            true
+        });
+        assert_eq!(
+            progress,
+            [
+                (5.0, Some(60.0), Some(1.5), Some(150)),
+                (10.0, Some(60.0), Some(1.49), Some(300)),
+                (62.5, Some(60.0), None, None),
+            ]
+        );
+        assert_eq!(other, ["Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'in.mp4':", "[aac @ 0x55d0] Qavg: 1234.5"]);
+
+        // Stops once the receiving end is gone
+        let mut seen = 0;
+        read_ffmpeg_output(stderr.as_bytes(), None, |_| {
+            seen += 1;
+            false
+        });
+        assert_eq!(seen, 1);
+    }
+
+    #[test]
+    fn job_slots_limit_encodes_and_copies_separately() {
+        let slots = JobSlots::new(1, 2);
+        let encode = slots.acquire(false).unwrap();
+        let first_copy = slots.acquire(true).unwrap();
+        let second_copy = slots.acquire(true).unwrap();
+        assert_eq!(*slots.running.lock().unwrap(), (1, 2));
+
+        drop(first_copy);
+        assert_eq!(*slots.running.lock().unwrap(), (1, 1));
+        drop((encode, second_copy));
+        assert_eq!(*slots.running.lock().unwrap(), (0, 0));
+    }
 }
@@ -0,0 +1,76 @@
+/// Renders a command as a single line that can be pasted into the host platform's shell.
+pub fn shell_quote(args: &[String]) -> String {
+    args.iter().map(|arg| quote_arg(arg)).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(not(windows))]
+fn quote_arg(arg: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "-_./:=+,@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        return arg.to_string();
+    }
+    // Nothing is special inside single quotes, a literal ' has to close the quote, escape itself and reopen
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+#[cfg(windows)]
+fn quote_arg(arg: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "-_./\\:=+,@".contains(c);
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        return arg.to_string();
+    }
+    // Backslashes are only special right before a quote, so double those and escape the quote itself
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+                quoted.push('"');
+                backslashes = 0;
+            }
+            _ => {
+                quoted.push_str(&"\\".repeat(backslashes));
+                quoted.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(args: &[&str]) -> String {
+        shell_quote(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn leaves_plain_args_alone() {
+        assert_eq!(quote(&["ffmpeg", "-i", "in.mp4", "-b:v", "25M"]), "ffmpeg -i in.mp4 -b:v 25M");
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn quotes_for_posix_shells() {
+        assert_eq!(quote(&["my clip.mp4"]), "'my clip.mp4'");
+        assert_eq!(quote(&["it's"]), "'it'\\''s'");
+        assert_eq!(quote(&[""]), "''");
+        assert_eq!(quote(&["-i", "", "a b"]), "-i '' 'a b'");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn quotes_for_windows() {
+        assert_eq!(quote(&["my clip.mp4"]), "\"my clip.mp4\"");
+        assert_eq!(quote(&["it's"]), "\"it's\"");
+        assert_eq!(quote(&[""]), "\"\"");
+        assert_eq!(quote(&["say \"hi\""]), "\"say \\\"hi\\\"\"");
+        assert_eq!(quote(&["C:\\my dir\\"]), "\"C:\\my dir\\\\\"");
+    }
+}
@@ -0,0 +1,886 @@
+use ::config::{Config as ConfigLoader, File, FileFormat};
+use serde_json::{Map, Value};
+
+use crate::util::config::{Config, Settings};
+
+/// What the `--init` template says about one setting. Its default and whether it's required come from `Settings`
+/// itself, only the text lives here.
+struct SettingDoc {
+    key: &'static str,
+    kind: &'static str,
+    description: &'static str,
+    // A value worth uncommenting, as TOML
+    example: &'static str,
+}
+
+// In the order they're written. Every field of `Settings` needs an entry, `config_template` fails on a field that's
+// missing here or an entry that's no longer a field
+const SETTING_DOCS: &[SettingDoc] = &[
+    SettingDoc {
+        key: "input_video_path",
+        kind: "path",
+        description: "the video to process, a url or - for stdin. can be left out when the config has [[batch]] sections",
+        example: "\"input.mp4\"",
+    },
+    SettingDoc {
+        key: "output_video_path",
+        kind: "path",
+        description: "where the result is written, - for stdout (needs output_format)",
+        example: "\"output.mp4\"",
+    },
+    SettingDoc {
+        key: "ffmpeg_path",
+        kind: "path",
+        description: "the ffmpeg executable, a bare name is looked up on PATH. leave it out to use the ffmpeg on PATH",
+        example: "\"ffmpeg\"",
+    },
+    SettingDoc {
+        key: "base_dir",
+        kind: "path",
+        description: "resolve relative paths against this directory (relative to the config file) instead",
+        example: "\"videos\"",
+    },
+    SettingDoc {
+        key: "video_bitrate",
+        kind: "bitrate",
+        description: "target video bitrate like 8000k or 25M, \"None\" when a crf is set",
+        example: "\"25M\"",
+    },
+    SettingDoc {
+        key: "crf",
+        kind: "string",
+        description: "constant quality from 0 to 51 (lower is better), wins over video_bitrate",
+        example: "\"23\"",
+    },
+    SettingDoc {
+        key: "max_bitrate",
+        kind: "bitrate",
+        description: "cap the video bitrate (-maxrate), only with video_bitrate",
+        example: "\"8000k\"",
+    },
+    SettingDoc {
+        key: "buffer_size",
+        kind: "bitrate",
+        description: "the buffer max_bitrate is measured over (-bufsize)",
+        example: "\"16000k\"",
+    },
+    SettingDoc {
+        key: "two_pass",
+        kind: "bool",
+        description: "encode in two passes with libx265 for better quality at the video_bitrate",
+        example: "true",
+    },
+    SettingDoc {
+        key: "use_gpu",
+        kind: "bool",
+        description: "encode with NVENC, the same as gpu_encoder = \"nvenc\"",
+        example: "true",
+    },
+    SettingDoc {
+        key: "gpu_encoder",
+        kind: "string",
+        description: "hardware encoder to use: nvenc, amf or qsv",
+        example: "\"nvenc\"",
+    },
+    SettingDoc {
+        key: "video_codec",
+        kind: "string",
+        description: "software encoder instead of libx265: libvpx-vp9, libaom-av1 or libsvtav1",
+        example: "\"libvpx-vp9\"",
+    },
+    SettingDoc {
+        key: "gpu_fallback",
+        kind: "bool",
+        description: "encode with libx265 instead of failing when the GPU encoder isn't available or fails mid-encode",
+        example: "true",
+    },
+    SettingDoc {
+        key: "encoder_preset",
+        kind: "string",
+        description: "speed/compression preset of the encoder, e.g. medium for libx265 or p4 for nvenc",
+        example: "\"medium\"",
+    },
+    SettingDoc {
+        key: "encoder_tune",
+        kind: "string",
+        description: "tune the encoder for the content, e.g. grain for libx265 or hq for nvenc",
+        example: "\"grain\"",
+    },
+    SettingDoc {
+        key: "threads",
+        kind: "integer",
+        description: "how many threads the CPU encoder may use, every core when left out",
+        example: "4",
+    },
+    SettingDoc {
+        key: "pixel_format",
+        kind: "string",
+        description: "pixel format of the encode, e.g. yuv420p for the widest player support",
+        example: "\"yuv420p\"",
+    },
+    SettingDoc {
+        key: "tonemap",
+        kind: "bool",
+        description: "tone-map an HDR (PQ or HLG) source down to SDR bt709",
+        example: "true",
+    },
+    SettingDoc {
+        key: "keyframe_interval",
+        kind: "frames or \"<secs>s\"",
+        description: "distance between keyframes (the GOP size), in frames or seconds like \"2s\"",
+        example: "\"2s\"",
+    },
+    SettingDoc {
+        key: "strict_gop",
+        kind: "bool",
+        description: "keep every GOP exactly keyframe_interval long, with no extra keyframes at scene cuts",
+        example: "true",
+    },
+    SettingDoc {
+        key: "color_range",
+        kind: "string",
+        description: "tv (limited) or pc (full) to mark the output range explicitly",
+        example: "\"tv\"",
+    },
+    SettingDoc {
+        key: "output_format",
+        kind: "string",
+        description: "container passed to ffmpeg's -f, e.g. mp4 or matroska, required for stdout",
+        example: "\"matroska\"",
+    },
+    SettingDoc {
+        key: "faststart",
+        kind: "bool",
+        description: "false to leave the index at the end of mp4 and mov outputs instead of moving it to the front",
+        example: "false",
+    },
+    SettingDoc {
+        key: "audio_only",
+        kind: "bool",
+        description: "write only the processed audio, its codec picked by the output's extension (mp3, m4a, opus, flac, wav)",
+        example: "true",
+    },
+    SettingDoc {
+        key: "extra_ffmpeg_args",
+        kind: "list of strings",
+        description: "arguments passed to ffmpeg as they are, right before the output. nothing checks them",
+        example: "[\"-metadata\", \"title=Holiday\"]",
+    },
+    SettingDoc {
+        key: "upscale_resolution",
+        kind: "string",
+        description: "scale to WIDTHxHEIGHT or a size name like hd1080, \"None\" to keep the size",
+        example: "\"1920x1080\"",
+    },
+    SettingDoc {
+        key: "max_resolution",
+        kind: "string",
+        description: "downscale to fit inside WIDTHxHEIGHT, smaller inputs are left alone",
+        example: "\"1920x1080\"",
+    },
+    SettingDoc {
+        key: "crop",
+        kind: "table",
+        description: "cut out a width x height rectangle at x/y, centered when those are left out",
+        example: "{ width = 1280, height = 720 }",
+    },
+    SettingDoc {
+        key: "pad_to_aspect",
+        kind: "string",
+        description: "add bars around the picture to bring it to this aspect ratio instead of stretching it",
+        example: "\"16:9\"",
+    },
+    SettingDoc {
+        key: "pad_color",
+        kind: "string",
+        description: "color of pad_to_aspect's bars, an ffmpeg color name or #RRGGBB",
+        example: "\"white\"",
+    },
+    SettingDoc {
+        key: "blurred_background",
+        kind: "bool",
+        description: "fill pad_to_aspect's bars with a blurred, zoomed-in copy of the picture instead of a color",
+        example: "true",
+    },
+    SettingDoc {
+        key: "rotate",
+        kind: "string",
+        description: "turn the picture clockwise by 90, 180 or 270 degrees",
+        example: "\"90\"",
+    },
+    SettingDoc {
+        key: "clip_start_time",
+        kind: "time",
+        description: "where the clip starts, seconds or a timecode like \"1:30\", \"None\" for the start",
+        example: "\"0:10\"",
+    },
+    SettingDoc {
+        key: "clip_end_time",
+        kind: "time",
+        description: "where the clip ends, \"None\" for the end of the input",
+        example: "\"1:30\"",
+    },
+    SettingDoc {
+        key: "fast_seek",
+        kind: "bool",
+        description: "seek to clip_start_time before opening the input, faster but may start a few frames off",
+        example: "true",
+    },
+    SettingDoc {
+        key: "clip_ranges",
+        kind: "list of [start, end] times",
+        description: "parts of the input to cut out and join, replaces clip_start_time/clip_end_time",
+        example: "[[\"10\", \"20\"], [\"60\", \"90\"]]",
+    },
+    SettingDoc {
+        key: "video_speed",
+        kind: "number",
+        description: "playback speed, 2.0 is twice as fast",
+        example: "1.0",
+    },
+    SettingDoc {
+        key: "speed_segments",
+        kind: "list of tables",
+        description: "parts of the clip played at another speed, in source seconds",
+        example: "[{ start = 5.0, end = 10.0, factor = 2.0 }]",
+    },
+    SettingDoc {
+        key: "interpolate",
+        kind: "bool",
+        description: "generate intermediate frames for smooth slow motion (cpu heavy)",
+        example: "true",
+    },
+    SettingDoc {
+        key: "target_fps",
+        kind: "number",
+        description: "convert to this framerate without changing the playback speed",
+        example: "30.0",
+    },
+    SettingDoc {
+        key: "loop_count",
+        kind: "integer",
+        description: "play the input this many extra times",
+        example: "1",
+    },
+    SettingDoc {
+        key: "fade_in_duration",
+        kind: "fade length",
+        description: "seconds or a percentage like \"10%\", 0 or \"none\" turns it off. 3 seconds when left out",
+        example: "0.8",
+    },
+    SettingDoc {
+        key: "fade_out_duration",
+        kind: "fade length",
+        description: "seconds or a percentage like \"10%\", 0 or \"none\" turns it off. 3 seconds when left out",
+        example: "0.8",
+    },
+    SettingDoc {
+        key: "fade_in_color",
+        kind: "string",
+        description: "color the video fades in from, an ffmpeg color name or #RRGGBB. black when left out",
+        example: "\"white\"",
+    },
+    SettingDoc {
+        key: "fade_out_color",
+        kind: "string",
+        description: "color the video fades out to, an ffmpeg color name or #RRGGBB. black when left out",
+        example: "\"white\"",
+    },
+    SettingDoc {
+        key: "video_fade",
+        kind: "bool",
+        description: "false to only fade the audio, which lets an otherwise untouched video be copied",
+        example: "false",
+    },
+    SettingDoc {
+        key: "fades",
+        kind: "list of tables",
+        description: "fades at set times in clip seconds, replaces the fade-in/out settings",
+        example: "[{ type = \"in\", start = 0.0, duration = 1.0 }, { type = \"out\", start = 9.0, duration = 1.0 }]",
+    },
+    SettingDoc {
+        key: "audio_fade_in_duration",
+        kind: "fade length",
+        description: "fade the audio in over a different length than the video",
+        example: "0.5",
+    },
+    SettingDoc {
+        key: "audio_fade_out_duration",
+        kind: "fade length",
+        description: "fade the audio out over a different length than the video",
+        example: "0.5",
+    },
+    SettingDoc {
+        key: "fade_curve",
+        kind: "string",
+        description: "shape of the audio fades, one of afade's curves like tri, qsin or exp",
+        example: "\"qsin\"",
+    },
+    SettingDoc {
+        key: "fade_in_curve",
+        kind: "string",
+        description: "shape of the audio fade-in, wins over fade_curve",
+        example: "\"qsin\"",
+    },
+    SettingDoc {
+        key: "fade_out_curve",
+        kind: "string",
+        description: "shape of the audio fade-out, wins over fade_curve",
+        example: "\"qsin\"",
+    },
+    SettingDoc {
+        key: "intro_path",
+        kind: "path",
+        description: "clip to put before the trimmed video, fitted to its size",
+        example: "\"intro.mp4\"",
+    },
+    SettingDoc {
+        key: "outro_path",
+        kind: "path",
+        description: "clip to put after the trimmed video, fitted to its size",
+        example: "\"outro.mp4\"",
+    },
+    SettingDoc {
+        key: "concat_inputs",
+        kind: "list of paths",
+        description: "clips to join on after the trimmed video, fitted to its size",
+        example: "[\"part2.mp4\", \"part3.mp4\"]",
+    },
+    SettingDoc {
+        key: "second_input_video_path",
+        kind: "path",
+        description: "video to crossfade into after the trimmed video, needs crossfade_duration",
+        example: "\"next.mp4\"",
+    },
+    SettingDoc {
+        key: "crossfade_duration",
+        kind: "number",
+        description: "seconds the crossfade into second_input_video_path takes",
+        example: "1.0",
+    },
+    SettingDoc {
+        key: "deinterlace",
+        kind: "string",
+        description: "yadif or bwdif, or auto to only deinterlace sources reported as interlaced",
+        example: "\"auto\"",
+    },
+    SettingDoc {
+        key: "denoise",
+        kind: "string",
+        description: "light, medium or strong noise reduction",
+        example: "\"light\"",
+    },
+    SettingDoc {
+        key: "denoise_method",
+        kind: "string",
+        description: "hqdn3d (fast) or nlmeans (better but very slow)",
+        example: "\"hqdn3d\"",
+    },
+    SettingDoc {
+        key: "denoise_params",
+        kind: "string",
+        description: "raw options for the denoise filter, replaces the denoise presets",
+        example: "\"4:3:6:4.5\"",
+    },
+    SettingDoc {
+        key: "sharpen",
+        kind: "number",
+        description: "unsharp amount between 0.0 and 1.5, applied after scaling",
+        example: "0.8",
+    },
+    SettingDoc {
+        key: "sharpen_params",
+        kind: "string",
+        description: "raw options for unsharp, replaces sharpen",
+        example: "\"7:7:1.2\"",
+    },
+    SettingDoc {
+        key: "stabilize",
+        kind: "bool",
+        description: "remove camera shake with vidstab, needs ffmpeg built with libvidstab",
+        example: "true",
+    },
+    SettingDoc {
+        key: "flip_horizontal",
+        kind: "bool",
+        description: "mirror the picture left to right",
+        example: "true",
+    },
+    SettingDoc {
+        key: "flip_vertical",
+        kind: "bool",
+        description: "mirror the picture top to bottom",
+        example: "true",
+    },
+    SettingDoc {
+        key: "negate",
+        kind: "bool",
+        description: "invert the colors",
+        example: "true",
+    },
+    SettingDoc {
+        key: "vignette",
+        kind: "bool",
+        description: "darken the edges of the picture",
+        example: "true",
+    },
+    SettingDoc {
+        key: "vignette_angle",
+        kind: "number",
+        description: "how far in the vignette reaches, radians up to 1.57",
+        example: "0.63",
+    },
+    SettingDoc {
+        key: "film_grain",
+        kind: "number",
+        description: "strength of added film grain, 0 to 100",
+        example: "10.0",
+    },
+    SettingDoc {
+        key: "color_effect",
+        kind: "string",
+        description: "grayscale, sepia, none or an ffmpeg filter of your own like curves=preset=vintage",
+        example: "\"sepia\"",
+    },
+    SettingDoc {
+        key: "subtitle_path",
+        kind: "path",
+        description: "subtitle file (srt, ass...) to burn into the picture",
+        example: "\"subs.srt\"",
+    },
+    SettingDoc {
+        key: "subtitle_style",
+        kind: "string",
+        description: "ASS style overrides for the burned-in subtitles",
+        example: "\"FontName=Arial,FontSize=24\"",
+    },
+    SettingDoc {
+        key: "watermark_path",
+        kind: "path",
+        description: "image overlaid on the video, e.g. a transparent png logo",
+        example: "\"logo.png\"",
+    },
+    SettingDoc {
+        key: "watermark_position",
+        kind: "string",
+        description: "top-left, top-right, bottom-left, bottom-right or center",
+        example: "\"bottom-right\"",
+    },
+    SettingDoc {
+        key: "watermark_opacity",
+        kind: "number",
+        description: "opacity of the watermark from 0.0 to 1.0",
+        example: "0.8",
+    },
+    SettingDoc {
+        key: "comparison",
+        kind: "bool",
+        description: "output the source and the result side by side",
+        example: "true",
+    },
+    SettingDoc {
+        key: "preview_duration",
+        kind: "number",
+        description: "only encode this many seconds with a fast preset, the same as --preview",
+        example: "10.0",
+    },
+    SettingDoc {
+        key: "audio_mode",
+        kind: "string",
+        description: "reencode, copy (keep the original audio as it is) or none (drop it)",
+        example: "\"reencode\"",
+    },
+    SettingDoc {
+        key: "audio_codec",
+        kind: "string",
+        description: "encoder for the output audio, aac when left out",
+        example: "\"libopus\"",
+    },
+    SettingDoc {
+        key: "audio_bitrate",
+        kind: "string",
+        description: "bitrate of the output audio in kbit/s, 192k when left out",
+        example: "\"192k\"",
+    },
+    SettingDoc {
+        key: "audio_sample_rate",
+        kind: "integer",
+        description: "sample rate of the output audio in Hz, the source's when left out",
+        example: "48000",
+    },
+    SettingDoc {
+        key: "original_audio_volume",
+        kind: "volume",
+        description: "volume of the input's audio: a multiplier, a percentage like \"50%\" or decibels like \"-6dB\"",
+        example: "1.0",
+    },
+    SettingDoc {
+        key: "master_volume",
+        kind: "volume",
+        description: "volume of the final mix, after the input's audio and the background audio are combined",
+        example: "\"-3dB\"",
+    },
+    SettingDoc {
+        key: "background_audio_path",
+        kind: "path or list of paths",
+        description: "audio to mix under the input's audio",
+        example: "\"music.mp3\"",
+    },
+    SettingDoc {
+        key: "background_audio_volume",
+        kind: "volume or list of volumes",
+        description: "volume of each background track, a single one applies to all of them",
+        example: "0.3",
+    },
+    SettingDoc {
+        key: "audio_start_time",
+        kind: "number",
+        description: "seconds into the background audio to start from",
+        example: "0.0",
+    },
+    SettingDoc {
+        key: "background_audio_delay",
+        kind: "number",
+        description: "seconds into the output before the background audio starts",
+        example: "5.0",
+    },
+    SettingDoc {
+        key: "replace_audio",
+        kind: "bool",
+        description: "use only the background audio instead of mixing it in",
+        example: "false",
+    },
+    SettingDoc {
+        key: "loop_background_audio",
+        kind: "bool",
+        description: "loop the background audio so it fills the whole video",
+        example: "true",
+    },
+    SettingDoc {
+        key: "separate_audio_tracks",
+        kind: "bool",
+        description: "put each background track on its own audio track instead of mixing it into the original audio",
+        example: "true",
+    },
+    SettingDoc {
+        key: "audio_track_titles",
+        kind: "list of strings",
+        description: "titles for the audio tracks in order, the original audio first",
+        example: "[\"Original\", \"Music\"]",
+    },
+    SettingDoc {
+        key: "audio_track_languages",
+        kind: "list of strings",
+        description: "ISO 639-2 languages for the audio tracks in order, e.g. eng",
+        example: "[\"eng\", \"zxx\"]",
+    },
+    SettingDoc {
+        key: "loudnorm",
+        kind: "bool",
+        description: "normalize the loudness of the final audio",
+        example: "true",
+    },
+    SettingDoc {
+        key: "loudnorm_target",
+        kind: "number",
+        description: "integrated loudness loudnorm aims for in LUFS, -16 when left out",
+        example: "-16.0",
+    },
+    SettingDoc {
+        key: "audio_limiter",
+        kind: "bool",
+        description: "limit the peaks of the final audio so the mix doesn't clip",
+        example: "true",
+    },
+    SettingDoc {
+        key: "audio_limiter_threshold",
+        kind: "number",
+        description: "the highest peak audio_limiter lets through in dBFS, -1 when left out",
+        example: "-1.0",
+    },
+    SettingDoc {
+        key: "contact_sheet_path",
+        kind: "path",
+        description: "also write a grid of frames from the clip as an image",
+        example: "\"sheet.jpg\"",
+    },
+    SettingDoc {
+        key: "contact_sheet_columns",
+        kind: "integer",
+        description: "columns of the contact sheet, 4 when left out",
+        example: "4",
+    },
+    SettingDoc {
+        key: "contact_sheet_rows",
+        kind: "integer",
+        description: "rows of the contact sheet, 4 when left out",
+        example: "4",
+    },
+    SettingDoc {
+        key: "thumbnail_path",
+        kind: "path",
+        description: "also write a single frame of the clip as an image",
+        example: "\"poster.jpg\"",
+    },
+    SettingDoc {
+        key: "thumbnail_time",
+        kind: "time",
+        description: "where in the clip the thumbnail is taken from, the middle when left out",
+        example: "\"0:12\"",
+    },
+    SettingDoc {
+        key: "waveform_path",
+        kind: "path",
+        description: "also draw the finished audio as a waveform image (.png)",
+        example: "\"waveform.png\"",
+    },
+    SettingDoc {
+        key: "waveform_size",
+        kind: "string",
+        description: "size of the waveform image, WIDTHxHEIGHT. 1920x240 when left out",
+        example: "\"1280x200\"",
+    },
+    SettingDoc {
+        key: "preserve_metadata",
+        kind: "bool",
+        description: "copy the source's metadata and chapters into the output",
+        example: "true",
+    },
+    SettingDoc {
+        key: "chapters_file",
+        kind: "path",
+        description: "text file with a \"timestamp title\" line per chapter to add to the output",
+        example: "\"chapters.txt\"",
+    },
+    SettingDoc {
+        key: "backup_existing",
+        kind: "bool",
+        description: "move an existing output to <output>.bak before replacing it",
+        example: "true",
+    },
+    SettingDoc {
+        key: "atomic_output",
+        kind: "bool",
+        description: "encode to a temporary file that only replaces the output once it's finished",
+        example: "false",
+    },
+    SettingDoc {
+        key: "skip_existing",
+        kind: "string",
+        description: "always, newer (only when written after the input changed), settings (only when encoded with the same settings) or never",
+        example: "\"newer\"",
+    },
+    SettingDoc {
+        key: "skip_if_matches",
+        kind: "bool",
+        description: "skip inputs that are already HEVC at upscale_resolution or within max_resolution",
+        example: "true",
+    },
+    SettingDoc {
+        key: "verify_output",
+        kind: "bool",
+        description: "decode the finished output to check it for errors",
+        example: "true",
+    },
+    SettingDoc {
+        key: "retries",
+        kind: "integer",
+        description: "how many times to retry a failed ffmpeg run",
+        example: "2",
+    },
+    SettingDoc {
+        key: "retry_delay_secs",
+        kind: "number",
+        description: "seconds to wait between retries, 5 when left out",
+        example: "5.0",
+    },
+    SettingDoc {
+        key: "timeout_secs",
+        kind: "number",
+        description: "stop an ffmpeg run that's still going after this many seconds",
+        example: "3600.0",
+    },
+    SettingDoc {
+        key: "max_parallel_jobs",
+        kind: "integer",
+        description: "how many files of a [[batch]] are encoded at the same time",
+        example: "2",
+    },
+    SettingDoc {
+        key: "max_parallel_copies",
+        kind: "integer",
+        description: "how many files of a [[batch]] that only copy the video run at the same time, default the number of cores",
+        example: "8",
+    },
+    SettingDoc {
+        key: "advanced_log",
+        kind: "bool",
+        description: "show ffmpeg's own output instead of the progress bar",
+        example: "false",
+    },
+    SettingDoc {
+        key: "print_command",
+        kind: "bool",
+        description: "log every ffmpeg command before it runs",
+        example: "true",
+    },
+    SettingDoc {
+        key: "benchmark",
+        kind: "bool",
+        description: "log the encoding speed (frames per second and times realtime) when each encode is done",
+        example: "true",
+    },
+    SettingDoc {
+        key: "dump_filtergraph",
+        kind: "path",
+        description: "write the filter graphs of the ffmpeg commands here before they run, a chain per line",
+        example: "\"filtergraph.txt\"",
+    },
+    SettingDoc {
+        key: "progress_file",
+        kind: "path",
+        description: "write the progress here as json while encoding",
+        example: "\"progress.json\"",
+    },
+    SettingDoc {
+        key: "progress_template",
+        kind: "string",
+        description: "custom indicatif template for the progress bar",
+        example: "\"{bar:40} {percent}%\"",
+    },
+    SettingDoc {
+        key: "log_progress_interval",
+        kind: "number",
+        description: "also log the progress every this many seconds, for when the bar isn't being watched",
+        example: "30.0",
+    },
+    SettingDoc {
+        key: "progress_refresh_hz",
+        kind: "integer",
+        description: "how often the progress bar is redrawn per second, 1 to 60",
+        example: "10",
+    },
+    SettingDoc {
+        key: "progress_by",
+        kind: "string",
+        description: "count the progress in time (seconds) or frames of the output",
+        example: "\"frames\"",
+    },
+    SettingDoc {
+        key: "log_timestamps",
+        kind: "bool",
+        description: "false to leave the timestamps out of the log",
+        example: "false",
+    },
+    SettingDoc {
+        key: "log_color",
+        kind: "string",
+        description: "color the log: auto, always or never",
+        example: "\"auto\"",
+    },
+];
+
+// Required for a run even though serde doesn't insist, since [[batch]] sections can take their place
+const ALWAYS_WRITTEN: &[&str] = &["input_video_path", "output_video_path"];
+
+// TOML for a JSON value, which only differ in how arrays and tables are written. JSON's string escapes are valid
+// in a TOML basic string
+fn toml_value(value: &Value) -> String {
+    match value {
+        Value::Array(values) => format!("[{}]", values.iter().map(toml_value).collect::<Vec<_>>().join(", ")),
+        Value::Object(fields) => {
+            let fields: Vec<_> = fields.iter().map(|(key, value)| format!("{} = {}", key, toml_value(value))).collect();
+            format!("{{ {} }}", fields.join(", "))
+        }
+        value => value.to_string(),
+    }
+}
+
+/// An annotated TOML config listing every setting with its type, whether it's required, its default and what it
+/// does. Required settings are filled in with their default, the rest are commented out with an example value. Fails when the
+/// documentation is out of step with `Settings`.
+pub fn config_template() -> Result<String, String> {
+    let Ok(Value::Object(fields)) = serde_json::to_value(Settings::default()) else {
+        return Err("The default settings didn't serialize into a table".to_string());
+    };
+
+    let undocumented: Vec<_> =
+        fields.keys().filter(|key| !SETTING_DOCS.iter().any(|doc| doc.key == *key)).map(String::as_str).collect();
+    let unknown: Vec<_> = SETTING_DOCS.iter().map(|doc| doc.key).filter(|key| !fields.contains_key(*key)).collect();
+    if !undocumented.is_empty() || !unknown.is_empty() {
+        return Err(format!(
+            "The config template is out of date, undocumented settings: [{}], documented but unknown: [{}]",
+            undocumented.join(", "),
+            unknown.join(", ")
+        ));
+    }
+
+    // A missing Option reads as None, so only the defaults that are set are needed to read the settings back
+    let defaults: Map<String, Value> = fields.into_iter().filter(|(_, value)| !value.is_null()).collect();
+    // Required is whatever serde can't do without
+    let required = |key: &str| {
+        let mut without = defaults.clone();
+        without.remove(key);
+        serde_json::from_value::<Settings>(Value::Object(without)).is_err()
+    };
+
+    let mut template = String::from(
+        "# clippy config, written by `clippy --init`. every setting is listed with its type, whether it's required and\n\
+         # its default. the optional ones are commented out, remove the leading \"# \" to use them. see the README for\n\
+         # the details of each one\n\
+         \n\
+         [settings]\n",
+    );
+    for doc in SETTING_DOCS {
+        let default = defaults
+            .get(doc.key)
+            .filter(|value| !matches!(value, Value::String(text) if text.is_empty()))
+            .filter(|value| !matches!(value, Value::Array(values) if values.is_empty()))
+            .map(toml_value);
+        let is_required = required(doc.key);
+        template.push_str(&format!(
+            "\n# {}\n# {}, {}, default: {}\n",
+            doc.description,
+            doc.kind,
+            if is_required { "required" } else { "optional" },
+            default.as_deref().unwrap_or("none")
+        ));
+        // Uncommenting an optional setting at its default would do nothing, so those show the example instead
+        if is_required {
+            template.push_str(&format!("{} = {}\n", doc.key, default.as_deref().unwrap_or(doc.example)));
+        } else if ALWAYS_WRITTEN.contains(&doc.key) {
+            template.push_str(&format!("{} = {}\n", doc.key, doc.example));
+        } else {
+            template.push_str(&format!("# {} = {}\n", doc.key, doc.example));
+        }
+    }
+
+    // Whatever gets written has to load, or it's no use as a starting point
+    ConfigLoader::builder()
+        .add_source(File::from_str(&template, FileFormat::Toml))
+        .build()
+        .and_then(|loaded| loaded.try_deserialize::<Config>())
+        .map_err(|e| format!("The config template doesn't load: {}", e))?;
+    Ok(template)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template_reads_back_as_the_defaults() {
+        // Also fails when SETTING_DOCS is out of step with Settings
+        let template = config_template().unwrap();
+        let loaded = ConfigLoader::builder().add_source(File::from_str(&template, FileFormat::Toml)).build().unwrap();
+        let settings = loaded.try_deserialize::<Config>().unwrap().settings;
+
+        // ALWAYS_WRITTEN are the only settings written with an example rather than their default
+        let defaults = Settings {
+            input_video_path: "input.mp4".to_string(),
+            output_video_path: "output.mp4".to_string(),
+            ..Settings::default()
+        };
+        assert_eq!(settings, defaults);
+    }
+}
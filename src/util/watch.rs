@@ -0,0 +1,123 @@
+use log::{error, info};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::util::config::{Config, SettingsOverrides};
+use crate::util::processing;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "mov", "mkv", "avi", "webm", "m4v", "ts"];
+
+fn is_video_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| VIDEO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+fn modified_times(config_paths: &[String]) -> Vec<Option<SystemTime>> {
+    config_paths
+        .iter()
+        .map(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+        .collect()
+}
+
+fn output_dir(config: &Config) -> PathBuf {
+    Path::new(&config.settings.output_video_path)
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default()
+}
+
+fn process_file(config: &Config, input: &Path) {
+    let mut job = config.clone();
+    job.settings.input_video_path = input.to_string_lossy().into_owned();
+    job.settings.output_video_path = output_dir(config)
+        .join(input.file_name().unwrap_or_default())
+        .to_string_lossy()
+        .into_owned();
+
+    info!("Processing {}", input.display());
+    if let Err(e) = processing::add_fade_effects(&job, false) {
+        error!("Failed to process {}: {}", input.display(), e);
+    }
+}
+
+/// Watches `watch_dir` for new video files and processes each with the current config. The config
+/// files are re-read whenever they change, and each output is written to the directory of the
+/// configured `output_video_path` under the input's file name. `format` overrides the config
+/// files' format like in `Config::from_files_with_format`, and `overrides` are applied on top of
+/// every reload. It keeps watching until `processing::interrupt` is called.
+pub fn watch_folder(
+    config_paths: &[String],
+    format: Option<&str>,
+    overrides: &SettingsOverrides,
+    watch_dir: &str,
+) -> Result<(), String> {
+    let load = || {
+        let mut config = Config::from_files_with_format(config_paths, format, overrides.base_dir.as_deref())?;
+        config.apply_overrides(overrides);
+        Ok::<_, config::ConfigError>(config)
+    };
+    let mut config = load().map_err(|e| e.to_string())?;
+    let mut config_times = modified_times(config_paths);
+
+    let watch_dir = Path::new(watch_dir);
+    let same_dir = |config: &Config| match (fs::canonicalize(output_dir(config)), fs::canonicalize(watch_dir)) {
+        (Ok(output), Ok(watched)) => output == watched,
+        _ => false,
+    };
+    if same_dir(&config) {
+        return Err("The output directory can't be the watched directory, outputs would be picked up as new inputs".to_string());
+    }
+
+    let mut processed: HashSet<PathBuf> = HashSet::new();
+    // Files are only picked up once their size stops changing between polls, so half-copied files
+    // aren't processed
+    let mut pending: HashMap<PathBuf, u64> = HashMap::new();
+
+    info!("Watching {} for new videos...", watch_dir.display());
+    while !processing::interrupted() {
+        let times = modified_times(config_paths);
+        if times != config_times {
+            config_times = times;
+            match load() {
+                Ok(new_config) if same_dir(&new_config) => {
+                    error!("Ignoring the updated config, its output directory is the watched directory");
+                }
+                Ok(new_config) => {
+                    info!("Config changed, reloaded it");
+                    config = new_config;
+                }
+                Err(e) => error!("Failed to reload config, keeping the previous one: {}", e),
+            }
+        }
+
+        let entries = fs::read_dir(watch_dir).map_err(|e| format!("Failed to read {}: {}", watch_dir.display(), e))?;
+        for entry in entries.flatten() {
+            if processing::interrupted() {
+                break;
+            }
+            let path = entry.path();
+            if !is_video_file(&path) || processed.contains(&path) {
+                continue;
+            }
+            let Ok(size) = entry.metadata().map(|meta| meta.len()) else {
+                continue;
+            };
+
+            if pending.get(&path) == Some(&size) {
+                pending.remove(&path);
+                processed.insert(path.clone());
+                process_file(&config, &path);
+            } else {
+                pending.insert(path, size);
+            }
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+    Ok(())
+}
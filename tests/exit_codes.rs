@@ -0,0 +1,65 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+// Every setting a config has to have except video_speed, which each test sets itself
+const REQUIRED: &str = "\
+input_video_path = \"in.mp4\"
+output_video_path = \"out.mp4\"
+video_bitrate = \"8000k\"
+advanced_log = false
+original_audio_volume = 1.0
+background_audio_volume = 1.0
+audio_start_time = 0.0
+replace_audio = false
+";
+
+// Writes `settings` as the [settings] of a config in a directory of its own and runs clippy on it
+fn run_with_settings(name: &str, settings: &str) -> Output {
+    let dir = env::temp_dir().join(format!("clippy-exit-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let config = dir.join("config.toml");
+    fs::write(&config, format!("[settings]\n{}", settings)).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_clippy")).arg("--config").arg(&config).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    output
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn an_unreadable_config_exits_with_2() {
+    let output = run_with_settings("unreadable", "input_video_path = \"in.mp4\"\nvideo_bitrate = \n");
+    assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+    assert!(stderr(&output).contains("Failed to read config file"), "{}", stderr(&output));
+}
+
+#[test]
+fn an_invalid_config_exits_with_2() {
+    let settings = format!("{}video_speed = 0.0\n", REQUIRED);
+    let output = run_with_settings("invalid", &settings);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+    assert!(stderr(&output).contains("video_speed must be greater than 0, got 0"), "{}", stderr(&output));
+}
+
+#[test]
+fn a_missing_config_file_exits_with_2() {
+    let missing: PathBuf = env::temp_dir().join("clippy-exit-no-such-dir").join("config.toml");
+    let output = Command::new(env!("CARGO_BIN_EXE_clippy")).arg("--config").arg(&missing).output().unwrap();
+    assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+}
+
+#[test]
+fn a_missing_ffmpeg_exits_with_5() {
+    let missing = env::temp_dir().join("clippy-exit-no-such-dir").join("ffmpeg");
+    let settings = format!("{}video_speed = 1.0\nffmpeg_path = {:?}\n", REQUIRED, missing.to_string_lossy());
+    let output = run_with_settings("no-ffmpeg", &settings);
+    assert_eq!(output.status.code(), Some(5), "{}", stderr(&output));
+    let message = format!("ffmpeg not found at {}; install it or set ffmpeg_path", missing.to_string_lossy());
+    assert!(stderr(&output).contains(&message), "{}", stderr(&output));
+}